@@ -1,8 +1,9 @@
 //! rspc-binario: Binario support for rspc
 //!
+//! Binario's futures are not `Send` so values are encoded and decoded on a pool of threads dedicated to Binario, one per CPU.
+//! A value which is waiting to be read doesn't hold up it's thread, so any number of values can be in flight at once.
+//!
 //! TODO:
-//!  - Support for streaming the result. Right now we encode into a buffer.
-//!  - `BinarioDeserializeError` should end up as a `ProcedureError::Deserialize` not `ProcedureError::Resolver`
 //!  - Binario needs impl for `()` for procedures with no input.
 //!  - Client integration
 //!  - Cleanup HTTP endpoint on `example-binario`. Maybe don't use HTTP cause Axum's model doesn't mesh with Binario?
@@ -15,16 +16,34 @@
     html_favicon_url = "https://github.com/specta-rs/rspc/blob/main/.github/logo.png?raw=true"
 )]
 
-use std::{error, fmt, marker::PhantomData, pin::Pin};
+use std::{
+    future::Future,
+    io,
+    marker::PhantomData,
+    num::NonZeroUsize,
+    pin::Pin,
+    sync::{Mutex, PoisonError},
+    task::{ready, Context, Poll},
+    thread,
+};
 
 use binario::{encode, Decode, Encode};
-use futures_util::{stream, Stream, StreamExt};
+use futures_util::{future::Either, stream, Stream, StreamExt};
 use rspc::{
-    middleware::Middleware, DynInput, ProcedureError, ProcedureStream, ResolverInput,
-    ResolverOutput,
+    middleware::Middleware, DynInput, ProcedureError, ProcedureStream, ResolverError,
+    ResolverInput, ResolverOutput,
 };
+use rspc_procedure::DeserializeError;
 use specta::{datatype::DataType, Generics, Type, TypeCollection};
-use tokio::io::AsyncRead;
+use tokio::{
+    io::{duplex, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf},
+    runtime,
+    sync::{mpsc, oneshot},
+    task::{spawn_local, LocalSet},
+};
+
+// The size of the buffer between the encoder and whoever is reading the output.
+const BUFFER_SIZE: usize = 8 * 1024;
 
 enum Repr {
     Bytes(Vec<u8>),
@@ -58,10 +77,88 @@ impl<T: Decode + Type + Send + 'static> ResolverInput for TypedBinarioInput<T> {
     }
 }
 
-// TODO: This should probs be a stream not a buffer.
-// Binario currently only supports `impl AsyncRead` not `impl Stream`
-pub struct BinarioOutput(pub Vec<u8>);
-pub struct TypedBinarioOutput<T, M>(pub T, pub PhantomData<fn() -> M>);
+/// A value returned from a Binario procedure.
+///
+/// This is a reader for the encoded value so it's never entirely buffered in memory.
+/// If encoding fails the error is returned from the reader.
+pub struct BinarioOutput(pub Pin<Box<dyn AsyncRead + Send + Sync>>);
+
+impl BinarioOutput {
+    /// A value which has already been encoded.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(Box::pin(io::Cursor::new(bytes)))
+    }
+
+    // The value is encoded on the Binario pool as it's read.
+    pub(crate) fn encode<T: Encode + Send + 'static>(value: T) -> Self {
+        let (mut writer, reader) = duplex(BUFFER_SIZE);
+        Self(Box::pin(EncodedReader {
+            reader,
+            task: Some(spawn(move || async move {
+                encode(&value, &mut writer).await?;
+                writer.shutdown().await
+            })),
+        }))
+    }
+
+    /// Encode the value into a writer, returning the number of bytes written.
+    ///
+    /// This doesn't require a Tokio runtime as the value is encoded on Binario's own threads.
+    pub async fn write_to<W: AsyncWrite + Unpin + ?Sized>(
+        mut self,
+        writer: &mut W,
+    ) -> io::Result<u64> {
+        tokio::io::copy(&mut self.0, writer).await
+    }
+
+    /// Get the encoded value as a stream of chunks.
+    pub fn into_stream(
+        self,
+    ) -> impl Stream<Item = Result<Vec<u8>, ProcedureError>> + Send + 'static {
+        stream::unfold(Some(self.0), |reader| async move {
+            let mut reader = reader?;
+            let mut buf = vec![0; BUFFER_SIZE];
+            match reader.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((Ok(buf), Some(reader)))
+                }
+                Err(err) => Some((Err(encode_error(err)), None)),
+            }
+        })
+    }
+}
+
+// Reads a value as it's encoded, returning the encoder's error if it fails.
+struct EncodedReader {
+    reader: DuplexStream,
+    task: Option<Task<()>>,
+}
+
+impl AsyncRead for EncodedReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.reader).poll_read(cx, buf))?;
+
+        // The encoder has either finished or failed so we check which one before returning EOF.
+        if buf.filled().len() == filled && buf.remaining() != 0 {
+            if let Some(task) = &mut self.task {
+                let result = ready!(Pin::new(task).poll(cx));
+                self.task = None;
+                return Poll::Ready(result);
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub struct TypedBinarioOutput<T, M>(Result<T, ProcedureError>, PhantomData<fn() -> M>);
 
 pub(crate) mod sealed {
     use super::*;
@@ -98,7 +195,7 @@ pub(crate) mod sealed {
         fn into_stream(
             self,
         ) -> impl Stream<Item = Result<Self::T, ProcedureError>> + Send + 'static {
-            self.0.map(|v| Ok(v))
+            self.0.map(Ok)
         }
     }
 }
@@ -113,12 +210,10 @@ impl<TError, M: 'static, T: sealed::ValidBinarioOutput<M>> ResolverOutput<TError
     }
 
     fn into_stream(self) -> impl Stream<Item = Result<Self::T, ProcedureError>> + Send + 'static {
-        // TODO: Encoding into a buffer is not how Binario is intended to work but it's how rspc needs it.
-        self.0.into_stream().then(|v| async move {
-            let mut buf = Vec::new();
-            encode(&v?, &mut buf).await.unwrap(); // TODO: Error handling
-            Ok(BinarioOutput(buf))
-        })
+        match self.0 {
+            Ok(v) => Either::Left(v.into_stream().map(|v| v.map(BinarioOutput::encode))),
+            Err(err) => Either::Right(stream::once(async move { Err(err) })),
+        }
     }
 
     fn into_procedure_stream(
@@ -138,38 +233,254 @@ pub fn binario<TError, TCtx, TInput, TResult, M>() -> Middleware<
     TResult,
 >
 where
-    TError: From<DeserializeError> + Send + 'static,
+    TError: Send + 'static,
     TCtx: Send + 'static,
     TInput: Decode + Send + 'static,
     TResult: sealed::ValidBinarioOutput<M>,
 {
     Middleware::new(
         move |ctx: TCtx, input: TypedBinarioInput<TInput>, next| async move {
-            let input = match input.0 .0 {
-                Repr::Bytes(bytes) => binario::decode::<TInput, _>(bytes.as_slice()).await,
-                Repr::Stream(stream) => binario::decode::<TInput, _>(stream).await,
-            }
-            .map_err(DeserializeError)?;
+            let input = match decode::<TInput>(input.0).await {
+                Ok(input) => input,
+                // We return the error through the output so it becomes a `ProcedureError::Deserialize` instead of being converted into `TError`.
+                Err(err) => {
+                    return Ok(TypedBinarioOutput(
+                        Err(DeserializeError::custom(err).into()),
+                        PhantomData,
+                    ))
+                }
+            };
 
             next.exec(ctx, input)
                 .await
-                .map(|v| TypedBinarioOutput(v, PhantomData))
+                .map(|v| TypedBinarioOutput(Ok(v), PhantomData))
         },
     )
 }
 
-pub struct DeserializeError(pub std::io::Error);
+async fn decode<T: Decode + Send + 'static>(input: BinarioInput) -> io::Result<T> {
+    spawn(move || async move {
+        match input.0 {
+            Repr::Bytes(bytes) => binario::decode::<T, _>(bytes.as_slice()).await,
+            Repr::Stream(stream) => binario::decode::<T, _>(stream).await,
+        }
+    })
+    .await
+}
+
+type Job = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+// Binario's futures are not `Send` so they are run as tasks on `LocalSet`s, each driven by it's own thread.
+// Unlike the blocking thread pool, a task which is waiting (Eg. for it's output to be read) doesn't hold onto a thread.
+fn spawn<T, F, Fut>(f: F) -> Task<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = io::Result<T>> + 'static,
+{
+    static POOL: Mutex<Pool> = Mutex::new(Pool {
+        workers: Vec::new(),
+        next: 0,
+    });
+
+    let (tx, rx) = oneshot::channel();
+    let mut job: Job = Box::new(move || {
+        Box::pin(async move {
+            let _ = tx.send(f().await);
+        })
+    });
+
+    let mut pool = POOL.lock().unwrap_or_else(PoisonError::into_inner);
+    loop {
+        let i = match pool.worker() {
+            Ok(i) => i,
+            Err(err) => return Task::Failed(Some(err)),
+        };
+        match pool.workers[i].send(job) {
+            Ok(()) => return Task::Running(rx),
+            // The worker has stopped so we try another one.
+            Err(mpsc::error::SendError(j)) => {
+                job = j;
+                pool.workers.remove(i);
+            }
+        }
+    }
+}
+
+struct Pool {
+    workers: Vec<mpsc::UnboundedSender<Job>>,
+    next: usize,
+}
+
+impl Pool {
+    // Get the index of the worker to use for the next job.
+    //
+    // Workers are started as they're needed, up to one per CPU.
+    // If one can't be started we keep using the running ones and only fail if there are none.
+    fn worker(&mut self) -> io::Result<usize> {
+        let size = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        if self.workers.len() < size {
+            match worker() {
+                Ok(tx) => {
+                    self.workers.push(tx);
+                    return Ok(self.workers.len() - 1);
+                }
+                Err(err) if self.workers.is_empty() => return Err(err),
+                Err(_) => {}
+            }
+        }
 
-impl fmt::Debug for DeserializeError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+        self.next = self.next.wrapping_add(1);
+        Ok(self.next % self.workers.len())
     }
 }
 
-impl fmt::Display for DeserializeError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+fn worker() -> io::Result<mpsc::UnboundedSender<Job>> {
+    let rt = runtime::Builder::new_current_thread().build()?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<Job>();
+    thread::Builder::new()
+        .name("rspc-binario".into())
+        .spawn(move || {
+            LocalSet::new().block_on(&rt, async move {
+                while let Some(job) = rx.recv().await {
+                    spawn_local(job());
+                }
+            });
+        })?;
+    Ok(tx)
+}
+
+// The result of a job run on the Binario pool.
+enum Task<T> {
+    Running(oneshot::Receiver<io::Result<T>>),
+    // The job couldn't be started.
+    Failed(Option<io::Error>),
+}
+
+impl<T> Future for Task<T> {
+    type Output = io::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut *self {
+            Self::Running(rx) => Pin::new(rx).poll(cx).map(|result| {
+                result.unwrap_or_else(|_| {
+                    Err(io::Error::other("the rspc-binario thread has stopped"))
+                })
+            }),
+            Self::Failed(err) => Poll::Ready(Err(err
+                .take()
+                .unwrap_or_else(|| io::Error::other("the rspc-binario job failed to start")))),
+        }
     }
 }
 
-impl error::Error for DeserializeError {}
+// TODO: This should be `ProcedureError::Serializer` once it exists.
+fn encode_error(err: io::Error) -> ProcedureError {
+    ResolverError::new(format!("failed to encode value: {err}"), Some(err)).into()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    async fn read(mut output: BinarioOutput) -> Vec<u8> {
+        let mut buf = Vec::new();
+        output.0.read_to_end(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let value = vec!["hello".to_string(), "world".repeat(BUFFER_SIZE)];
+        let bytes = read(BinarioOutput::encode(value.clone())).await;
+
+        let decoded = decode::<Vec<String>>(BinarioInput::from_bytes(bytes.clone()))
+            .await
+            .unwrap();
+        assert_eq!(decoded, value);
+
+        let decoded = decode::<Vec<String>>(BinarioInput::from_stream(io::Cursor::new(bytes)))
+            .await
+            .unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[tokio::test]
+    async fn invalid_input() {
+        assert!(decode::<String>(BinarioInput::from_bytes(vec![255]))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn values_waiting_to_be_read() {
+        // Each of these is larger than the buffer so they are all waiting on the reader at once.
+        let readers = (0..1024)
+            .map(|i| BinarioOutput::encode(i.to_string().repeat(BUFFER_SIZE)).0)
+            .collect::<Vec<_>>();
+
+        for (i, mut reader) in readers.into_iter().enumerate().rev() {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).await.unwrap();
+            let value = decode::<String>(BinarioInput::from_bytes(buf))
+                .await
+                .unwrap();
+            assert_eq!(value, i.to_string().repeat(BUFFER_SIZE));
+        }
+    }
+
+    #[test]
+    fn without_runtime() {
+        let mut stream = BinarioOutput::encode("abc".to_string())
+            .into_stream()
+            .boxed();
+
+        // The value is encoded on another thread so we poll until it's ready.
+        let chunk = loop {
+            if let Some(chunk) = futures_util::FutureExt::now_or_never(stream.next()) {
+                break chunk;
+            }
+            thread::yield_now();
+        };
+        assert!(chunk.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn already_encoded() {
+        let bytes = read(BinarioOutput::encode("abc".to_string())).await;
+        let mut buf = Vec::new();
+        let n = BinarioOutput::from_bytes(bytes.clone())
+            .write_to(&mut buf)
+            .await
+            .unwrap();
+        assert_eq!(n, bytes.len() as u64);
+        assert_eq!(buf, bytes);
+    }
+
+    #[tokio::test]
+    async fn spread_across_pool() {
+        let ids = futures_util::future::join_all(
+            (0..64).map(|_| spawn(|| async { Ok(thread::current().id()) })),
+        )
+        .await
+        .into_iter()
+        .map(|id| id.unwrap())
+        .collect::<std::collections::HashSet<_>>();
+
+        assert!(!ids.contains(&thread::current().id()));
+        if thread::available_parallelism().map_or(1, NonZeroUsize::get) > 1 {
+            assert!(ids.len() > 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_to_start() {
+        let mut reader = EncodedReader {
+            reader: duplex(BUFFER_SIZE).1,
+            task: Some(Task::Failed(Some(io::Error::other("no threads")))),
+        };
+        let err = reader.read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.to_string(), "no threads");
+    }
+}
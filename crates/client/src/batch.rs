@@ -0,0 +1,411 @@
+use std::{
+    fmt,
+    future::Future,
+    io, mem,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+
+use crate::{
+    decode, BatchTransport, Client, Error, Procedure, ProcedureKind, Request, Response,
+    ResponseError, RspcError, Transport,
+};
+
+type Pending<E> = Vec<(Request, oneshot::Sender<Result<Response, Arc<E>>>)>;
+type Receiver<E> = oneshot::Receiver<Result<Response, Arc<E>>>;
+
+/// A group of queries and mutations which are sent to the server in a single request.
+///
+/// Created with [`Client::batch`].
+pub struct Batch<'a, P, T: Transport> {
+    client: &'a Client<P, T>,
+    pending: Pending<T::Error>,
+}
+
+impl<'a, P, T: BatchTransport> Batch<'a, P, T> {
+    pub(crate) fn new(client: &'a Client<P, T>) -> Self {
+        Self {
+            client,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Add a procedure to the batch.
+    ///
+    /// The returned future will resolve once the batch has been sent with [`Batch::send`].
+    pub fn exec<O: Procedure<Procedures = P>>(
+        &mut self,
+        input: O::Input,
+    ) -> BatchItem<O, T::Error> {
+        if O::KIND == ProcedureKind::Subscription {
+            return BatchItem::error(Error::Rspc(RspcError {
+                code: 400,
+                variant: None,
+                message: format!("'{}' is a subscription, use `Client::subscribe`", O::KEY),
+            }));
+        }
+
+        let input = match serde_json::to_value(&input) {
+            Ok(input) => input,
+            Err(err) => return BatchItem::error(Error::Encode(err)),
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.push((
+            Request {
+                kind: O::KIND,
+                key: O::KEY.into(),
+                input,
+            },
+            tx,
+        ));
+
+        BatchItem {
+            state: Ok(rx),
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Send the batch to the server.
+    pub async fn send(self) {
+        send_batch(&self.client.transport, self.pending).await;
+    }
+}
+
+impl<P, T: Transport> fmt::Debug for Batch<'_, P, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Batch")
+            .field(
+                "requests",
+                &self.pending.iter().map(|(req, _)| req).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+/// The result of a procedure which was added to a [`Batch`].
+pub struct BatchItem<O: Procedure, E> {
+    state: Result<Receiver<E>, Option<Error<O::Error>>>,
+    phantom: std::marker::PhantomData<fn() -> O>,
+}
+
+impl<O: Procedure, E> BatchItem<O, E> {
+    fn error(err: Error<O::Error>) -> Self {
+        Self {
+            state: Err(Some(err)),
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+// We never project a pin to the error so this is fine.
+impl<O: Procedure, E> Unpin for BatchItem<O, E> {}
+
+impl<O: Procedure, E: std::error::Error + Send + Sync + 'static> Future for BatchItem<O, E> {
+    type Output = Result<O::Output, Error<O::Error>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Poll::Ready(match &mut self.state {
+            Ok(rx) => match ready!(Pin::new(rx).poll(cx)) {
+                Ok(Ok(response)) => decode::<O>(response),
+                Ok(Err(err)) => Err(Error::Transport(Box::new(err))),
+                Err(_) => Err(Error::Transport(Box::new(io::Error::other(
+                    "the batch was dropped without being sent",
+                )))),
+            },
+            Err(err) => Err(err.take().expect("`BatchItem` polled after completion")),
+        })
+    }
+}
+
+impl<O: Procedure, E> fmt::Debug for BatchItem<O, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchItem").field("key", &O::KEY).finish()
+    }
+}
+
+/// A [`Transport`] which automatically batches queries and mutations.
+///
+/// Requests made within `window` of the first one are sent to the server together.
+pub struct BatchingTransport<T: Transport> {
+    inner: Arc<Inner<T>>,
+}
+
+struct Inner<T: Transport> {
+    transport: T,
+    window: Duration,
+    queue: Mutex<Pending<T::Error>>,
+}
+
+impl<T: Transport> BatchingTransport<T> {
+    pub fn new(transport: T, window: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                transport,
+                window,
+                queue: Default::default(),
+            }),
+        }
+    }
+
+    /// Get the underlying transport.
+    pub fn transport(&self) -> &T {
+        &self.inner.transport
+    }
+}
+
+impl<T: Transport> Clone for BatchingTransport<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Transport + fmt::Debug> fmt::Debug for BatchingTransport<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchingTransport")
+            .field("transport", &self.inner.transport)
+            .field("window", &self.inner.window)
+            .finish()
+    }
+}
+
+impl<T: BatchTransport + Send + Sync + 'static> Transport for BatchingTransport<T> {
+    type Error = Arc<T::Error>;
+
+    async fn exec(&self, request: Request) -> Result<Response, Self::Error> {
+        let (tx, rx) = oneshot::channel();
+        let first = {
+            let mut queue = self.inner.queue.lock().unwrap_or_else(|e| e.into_inner());
+            queue.push((request, tx));
+            queue.len() == 1
+        };
+
+        // The first request of a batch is responsible for sending it.
+        if first {
+            let inner = self.inner.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(inner.window).await;
+                let batch = mem::take(&mut *inner.queue.lock().unwrap_or_else(|e| e.into_inner()));
+                send_batch(&inner.transport, batch).await;
+            });
+        }
+
+        rx.await.unwrap_or_else(|_| {
+            Ok(Response::Error(ResponseError {
+                code: 500,
+                message: "the batch was cancelled".into(),
+                data: None,
+            }))
+        })
+    }
+}
+
+impl<T: BatchTransport + Send + Sync + 'static> BatchTransport for BatchingTransport<T> {
+    async fn exec_batch(&self, requests: Vec<Request>) -> Result<Vec<Response>, Self::Error> {
+        self.inner
+            .transport
+            .exec_batch(requests)
+            .await
+            .map_err(Arc::new)
+    }
+}
+
+// Execute a batch and send each response to it's caller.
+async fn send_batch<T: BatchTransport>(transport: &T, batch: Pending<T::Error>) {
+    if batch.is_empty() {
+        return;
+    }
+
+    let (requests, senders): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+    match transport.exec_batch(requests).await {
+        Ok(responses) => {
+            let mut responses = responses.into_iter();
+            for tx in senders {
+                let _ = tx.send(Ok(responses.next().unwrap_or_else(|| {
+                    Response::Error(ResponseError {
+                        code: 500,
+                        message: "the server didn't respond to the request".into(),
+                        data: None,
+                    })
+                })));
+            }
+        }
+        Err(err) => {
+            let err = Arc::new(err);
+            for tx in senders {
+                let _ = tx.send(Err(err.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use serde_json::Value;
+
+    use super::*;
+
+    // Responds with the input of each request and records the size of every batch.
+    #[derive(Default)]
+    struct Echo {
+        batches: Mutex<Vec<usize>>,
+        // Respond to one less request than was sent.
+        truncate: AtomicBool,
+        fail: AtomicBool,
+    }
+
+    impl Transport for Echo {
+        type Error = io::Error;
+
+        async fn exec(&self, request: Request) -> Result<Response, Self::Error> {
+            Ok(Response::Value(request.input))
+        }
+    }
+
+    impl BatchTransport for Echo {
+        async fn exec_batch(&self, requests: Vec<Request>) -> Result<Vec<Response>, Self::Error> {
+            self.batches.lock().unwrap().push(requests.len());
+            if self.fail.load(Ordering::Relaxed) {
+                return Err(io::Error::other("connection refused"));
+            }
+
+            let mut responses = requests
+                .into_iter()
+                .map(|req| Response::Value(req.input))
+                .collect::<Vec<_>>();
+            if self.truncate.load(Ordering::Relaxed) {
+                responses.pop();
+            }
+            Ok(responses)
+        }
+    }
+
+    struct Double;
+
+    impl Procedure for Double {
+        type Input = u32;
+        type Output = u32;
+        type Error = ();
+        type Procedures = ();
+        const KEY: &'static str = "double";
+        const KIND: ProcedureKind = ProcedureKind::Query;
+    }
+
+    struct Name;
+
+    impl Procedure for Name {
+        type Input = String;
+        type Output = String;
+        type Error = ();
+        type Procedures = ();
+        const KEY: &'static str = "name";
+        const KIND: ProcedureKind = ProcedureKind::Mutation;
+    }
+
+    struct Events;
+
+    impl Procedure for Events {
+        type Input = ();
+        type Output = Value;
+        type Error = ();
+        type Procedures = ();
+        const KEY: &'static str = "events";
+        const KIND: ProcedureKind = ProcedureKind::Subscription;
+    }
+
+    #[tokio::test]
+    async fn batch() {
+        let client = Client::<(), _>::with_transport(Echo::default());
+
+        let mut batch = client.batch();
+        let a = batch.exec::<Double>(1);
+        let b = batch.exec::<Name>("rspc".into());
+        let c = batch.exec::<Double>(2);
+        let events = batch.exec::<Events>(());
+        batch.send().await;
+
+        // Every caller gets their own typed result.
+        assert_eq!(a.await.unwrap(), 1);
+        assert_eq!(b.await.unwrap(), "rspc");
+        assert_eq!(c.await.unwrap(), 2);
+        assert!(matches!(events.await, Err(Error::Rspc(err)) if err.code == 400));
+        // Subscriptions aren't sent with the batch.
+        assert_eq!(*client.transport().batches.lock().unwrap(), vec![3]);
+
+        let mut batch = client.batch();
+        let unsent = batch.exec::<Double>(1);
+        drop(batch);
+        assert!(matches!(unsent.await, Err(Error::Transport(_))));
+        assert!(client.transport().batches.lock().unwrap().len() == 1);
+    }
+
+    #[tokio::test]
+    async fn batch_errors() {
+        let client = Client::<(), _>::with_transport(Echo::default());
+
+        client.transport().truncate.store(true, Ordering::Relaxed);
+        let mut batch = client.batch();
+        let a = batch.exec::<Double>(1);
+        let b = batch.exec::<Double>(2);
+        batch.send().await;
+        assert_eq!(a.await.unwrap(), 1);
+        assert!(matches!(b.await, Err(Error::Rspc(err)) if err.code == 500));
+
+        // A failed request fails every procedure in it.
+        client.transport().fail.store(true, Ordering::Relaxed);
+        let mut batch = client.batch();
+        let a = batch.exec::<Double>(1);
+        let b = batch.exec::<Double>(2);
+        batch.send().await;
+        for item in [a, b] {
+            assert!(
+                matches!(item.await, Err(Error::Transport(err)) if err.to_string() == "connection refused")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn batching_transport() {
+        let client = Client::<(), _>::with_transport(BatchingTransport::new(
+            Echo::default(),
+            Duration::from_millis(50),
+        ));
+
+        // Calls made within the window are sent together.
+        let (a, b, c) = tokio::join!(
+            client.exec::<Double>(1),
+            client.exec::<Name>("rspc".into()),
+            client.exec::<Double>(3),
+        );
+        assert_eq!((a.unwrap(), &*b.unwrap(), c.unwrap()), (1, "rspc", 3));
+        assert_eq!(
+            *client.transport().transport().batches.lock().unwrap(),
+            vec![3]
+        );
+
+        // A call after the batch was sent starts a new one.
+        assert_eq!(client.exec::<Double>(4).await.unwrap(), 4);
+        assert_eq!(
+            *client.transport().transport().batches.lock().unwrap(),
+            vec![3, 1]
+        );
+
+        client
+            .transport()
+            .transport()
+            .fail
+            .store(true, Ordering::Relaxed);
+        let (a, b) = tokio::join!(client.exec::<Double>(1), client.exec::<Double>(2));
+        assert!(matches!(a, Err(Error::Transport(_))));
+        assert!(matches!(b, Err(Error::Transport(_))));
+    }
+}
@@ -0,0 +1,185 @@
+use std::{error, fmt, sync::Arc};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::ResponseError;
+
+/// An error from executing a procedure.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The transport failed to send the request or receive the response.
+    Transport(Box<dyn error::Error + Send + Sync>),
+    /// Failed to serialize the input.
+    Encode(serde_json::Error),
+    /// The response could not be decoded into the procedure's output or error type.
+    /// This normally means the bindings are out of date with the server.
+    Decode(serde_json::Error),
+    /// The server's response wasn't a valid rspc response (Eg. a proxy responded with an HTML error page).
+    Protocol(Arc<serde_json::Error>),
+    /// An error produced by rspc instead of the procedure (Eg. the procedure was not found or the input was invalid).
+    Rspc(RspcError),
+    /// The error returned by the procedure.
+    Procedure(E),
+}
+
+impl<E: DeserializeOwned> Error<E> {
+    // Convert an error response from the server into a typed error.
+    pub(crate) fn from_response(err: ResponseError) -> Self {
+        match err.data {
+            Some(Value::Object(data)) if data.get("~rspc") == Some(&Value::Bool(true)) => {
+                Self::Rspc(RspcError {
+                    code: err.code,
+                    variant: data
+                        .get("variant")
+                        .and_then(Value::as_str)
+                        .map(ToString::to_string),
+                    message: data
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .map(ToString::to_string)
+                        .unwrap_or(err.message),
+                })
+            }
+            Some(data) => match serde_json::from_value(data) {
+                Ok(err) => Self::Procedure(err),
+                Err(err) => Self::Decode(err),
+            },
+            // The server didn't send any data so it must be an internal error.
+            None => Self::Rspc(RspcError {
+                code: err.code,
+                variant: None,
+                message: err.message,
+            }),
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(err) => write!(f, "transport error: {err}"),
+            Self::Encode(err) => write!(f, "error encoding input: {err}"),
+            Self::Decode(err) => write!(f, "error decoding response: {err}"),
+            Self::Protocol(err) => write!(f, "invalid response from server: {err}"),
+            Self::Rspc(err) => write!(f, "{err}"),
+            Self::Procedure(err) => write!(f, "procedure error: {err:?}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> error::Error for Error<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Transport(err) => Some(&**err),
+            Self::Encode(err) | Self::Decode(err) => Some(err),
+            Self::Protocol(err) => Some(&**err),
+            Self::Rspc(err) => Some(err),
+            Self::Procedure(_) => None,
+        }
+    }
+}
+
+/// An error produced by rspc instead of the procedure.
+///
+/// This corresponds to the variants of `rspc::ProcedureError` other than `Resolver`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RspcError {
+    /// The status code of the error (Eg. `404`).
+    pub code: i32,
+    /// The kind of error (Eg. `NotFound` or `Deserialize`).
+    /// This is `None` if the server didn't include it.
+    pub variant: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for RspcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.variant {
+            Some(variant) => write!(f, "rspc error {variant} ({}): {}", self.code, self.message),
+            None => write!(f, "rspc error ({}): {}", self.code, self.message),
+        }
+    }
+}
+
+impl error::Error for RspcError {}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum MyError {
+        NotAllowed { reason: String },
+    }
+
+    fn error(code: i32, data: Option<Value>) -> Error<MyError> {
+        Error::from_response(ResponseError {
+            code,
+            message: "message".into(),
+            data,
+        })
+    }
+
+    #[test]
+    fn procedure_errors() {
+        let err = error(
+            403,
+            Some(json!({ "NotAllowed": { "reason": "not an admin" } })),
+        );
+        assert!(matches!(
+            err,
+            Error::Procedure(MyError::NotAllowed { reason }) if reason == "not an admin"
+        ));
+
+        // Bindings which are out of date with the server.
+        assert!(matches!(
+            error(500, Some(json!({ "Unknown": null }))),
+            Error::Decode(_)
+        ));
+    }
+
+    #[test]
+    fn rspc_errors() {
+        let err = error(
+            400,
+            Some(json!({ "~rspc": true, "variant": "Deserialize", "message": "invalid type" })),
+        );
+        let Error::Rspc(err) = err else {
+            panic!("expected an rspc error, got {err:?}");
+        };
+        assert_eq!(
+            err,
+            RspcError {
+                code: 400,
+                variant: Some("Deserialize".into()),
+                message: "invalid type".into(),
+            }
+        );
+
+        // Errors from `rspc-axum` itself aren't decoded as the procedure's error even though they have data.
+        for data in [
+            json!({ "~rspc": true, "limit": "depth", "max": 1 }),
+            json!({ "~rspc": true, "reconnect": true }),
+            json!({ "~rspc": true, "detail": "expected value at line 1 column 1" }),
+        ] {
+            let Error::Rspc(err) = error(503, Some(data)) else {
+                panic!("expected an rspc error");
+            };
+            assert_eq!(
+                (err.code, err.variant, &*err.message),
+                (503, None, "message")
+            );
+        }
+
+        // Errors from something other than rspc.
+        let Error::Rspc(err) = error(502, None) else {
+            panic!("expected an rspc error");
+        };
+        assert_eq!((err.code, &*err.message), (502, "message"));
+    }
+}
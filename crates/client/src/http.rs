@@ -0,0 +1,352 @@
+use std::{borrow::Cow, sync::Arc};
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::{
+    transport::{BatchTransport, Request, Response, ResponseError, ResponseInner, Transport},
+    ProcedureKind,
+};
+
+/// Execute procedures over HTTP using [`reqwest`].
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    url: Cow<'static, str>,
+    client: reqwest::Client,
+}
+
+impl HttpTransport {
+    /// This fails if the [`reqwest::Client`] can't be initialised (Eg. the TLS backend failed to load).
+    pub fn new(url: impl Into<Cow<'static, str>>) -> Result<Self, reqwest::Error> {
+        Ok(Self::with_client(
+            url,
+            reqwest::Client::builder()
+                .user_agent(concat!(
+                    env!("CARGO_PKG_NAME"),
+                    "/",
+                    env!("CARGO_PKG_VERSION")
+                ))
+                .build()?,
+        ))
+    }
+
+    /// Use an existing [`reqwest::Client`]. This allows configuring things like timeouts and default headers.
+    pub fn with_client(url: impl Into<Cow<'static, str>>, client: reqwest::Client) -> Self {
+        Self {
+            url: url.into(),
+            client,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}{}{}",
+            self.url,
+            if self.url.ends_with("/") { "" } else { "/" },
+            path
+        )
+    }
+}
+
+impl Transport for HttpTransport {
+    type Error = reqwest::Error;
+
+    async fn exec(&self, request: Request) -> Result<Response, Self::Error> {
+        let url = self.url(&request.key);
+
+        let req = match request.kind {
+            ProcedureKind::Query => self
+                .client
+                .get(&url)
+                .query(&[("input", request.input.to_string())]),
+            ProcedureKind::Mutation => self.client.post(&url).body(request.input.to_string()),
+            ProcedureKind::Subscription => {
+                // TODO: We will need to implement websocket support somehow. https://github.com/seanmonstar/reqwest/issues/864
+                return Ok(Response::Error(ResponseError {
+                    code: 400,
+                    message: "subscriptions are not supported over HTTP, use the `WebsocketTransport` instead".into(),
+                    data: None,
+                }));
+            }
+        };
+
+        #[derive(Deserialize)]
+        struct LegacyFormat {
+            result: ResponseInner,
+        }
+
+        Ok(match read::<LegacyFormat>(req.send().await?).await? {
+            Ok(result) => result.result.into(),
+            Err(response) => response,
+        })
+    }
+}
+
+impl BatchTransport for HttpTransport {
+    async fn exec_batch(&self, requests: Vec<Request>) -> Result<Vec<Response>, Self::Error> {
+        let body = requests
+            .into_iter()
+            .enumerate()
+            .map(|(id, request)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": match request.kind {
+                        ProcedureKind::Query => "query",
+                        ProcedureKind::Mutation => "mutation",
+                        ProcedureKind::Subscription => "subscription",
+                    },
+                    "params": {
+                        "path": request.key,
+                        "input": request.input,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        #[derive(Deserialize)]
+        struct BatchResponse {
+            id: Option<usize>,
+            result: ResponseInner,
+        }
+
+        let mut responses = vec![None; body.len()];
+        let response = self
+            .client
+            .post(self.url("_batch"))
+            .json(&body)
+            .send()
+            .await?;
+        let result = match read::<Vec<BatchResponse>>(response).await? {
+            Ok(result) => result,
+            // The whole batch failed so every request gets the same error.
+            Err(response) => return Ok(vec![response; responses.len()]),
+        };
+        // The server may respond in any order so we use the id to match them up.
+        // A response without an id is an error with the whole batch (Eg. it couldn't be parsed) so it's used for every request.
+        let mut fallback = None;
+        for resp in result {
+            match resp.id {
+                Some(id) => {
+                    if let Some(slot) = responses.get_mut(id) {
+                        *slot = Some(resp.result.into());
+                    }
+                }
+                None => fallback = Some(Response::from(resp.result)),
+            }
+        }
+
+        Ok(responses
+            .into_iter()
+            .map(|resp| {
+                resp.or_else(|| fallback.clone()).unwrap_or_else(|| {
+                    Response::Error(ResponseError {
+                        code: 500,
+                        message: "the server didn't respond to the request".into(),
+                        data: None,
+                    })
+                })
+            })
+            .collect())
+    }
+}
+
+// Decode the body of a response.
+//
+// If it isn't a valid rspc response the error is returned as a `Response` instead.
+// Errors from something other than rspc (Eg. a proxy or a plain text rejection from the context function) use the status code,
+// otherwise the server sent something we don't understand.
+// `rspc-axum` can also respond with RFC 9457 problems if `Config::problem_details` is enabled.
+async fn read<T: DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<Result<T, Response>, reqwest::Error> {
+    let status = response.status();
+    let body = response.bytes().await?;
+    Ok(serde_json::from_slice::<T>(&body).map_err(|err| {
+        if status.is_success() {
+            return Response::Invalid(Arc::new(err));
+        }
+
+        #[derive(Deserialize)]
+        struct Problem {
+            detail: String,
+            data: Option<Value>,
+        }
+
+        if let Ok(problem) = serde_json::from_slice::<Problem>(&body) {
+            return Response::Error(ResponseError {
+                code: status.as_u16().into(),
+                message: problem.detail,
+                data: problem.data,
+            });
+        }
+
+        let message = String::from_utf8_lossy(&body).trim().to_string();
+        Response::Error(ResponseError {
+            code: status.as_u16().into(),
+            message: match message.is_empty() {
+                true => status
+                    .canonical_reason()
+                    .unwrap_or("unknown error")
+                    .to_string(),
+                false => message,
+            },
+            data: None,
+        })
+    }))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde::Serialize;
+
+    use super::*;
+
+    async fn send(status: u16, body: &'static str) -> Response {
+        let response = http::Response::builder().status(status).body(body).unwrap();
+        #[derive(Deserialize)]
+        struct Body {
+            result: ResponseInner,
+        }
+        match read::<Body>(response.into()).await.unwrap() {
+            Ok(body) => body.result.into(),
+            Err(response) => response,
+        }
+    }
+
+    fn error(response: Response) -> ResponseError {
+        match response {
+            Response::Error(err) => err,
+            response => panic!("expected an error, got {response:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rspc_responses() {
+        let response = send(200, r#"{"result":{"type":"response","data":1}}"#).await;
+        assert!(matches!(response, Response::Value(v) if v == 1));
+
+        // Errors from rspc are decoded whatever the status.
+        let body = r#"{"result":{"type":"error","data":{"code":404,"message":"not found"}}}"#;
+        let err = error(send(404, body).await);
+        assert_eq!((err.code, &*err.message), (404, "not found"));
+    }
+
+    #[tokio::test]
+    async fn other_errors() {
+        let err = error(send(502, "<html>Bad Gateway</html>").await);
+        assert_eq!((err.code, &*err.message), (502, "<html>Bad Gateway</html>"));
+        assert_eq!(err.data, None);
+
+        let err = error(send(413, "").await);
+        assert_eq!((err.code, &*err.message), (413, "Payload Too Large"));
+
+        let problem =
+            r#"{"title":"Unauthorized","detail":"error creating context","data":{"~rspc":true}}"#;
+        let err = error(send(401, problem).await);
+        assert_eq!((err.code, &*err.message), (401, "error creating context"));
+        assert_eq!(err.data, Some(json!({ "~rspc": true })));
+    }
+
+    // Start an `rspc-axum` server and return it's URL.
+    async fn serve(config: rspc_axum::Config) -> String {
+        #[derive(Serialize, specta::Type)]
+        struct Error;
+
+        impl rspc::Error for Error {
+            fn into_procedure_error(self) -> rspc::ProcedureError {
+                rspc::ResolverError::new(self, None::<std::io::Error>)
+                    .with_status(418)
+                    .into()
+            }
+        }
+
+        let (procedures, _) = <rspc::Router>::new()
+            .procedure(
+                "double",
+                rspc::Procedure::builder::<Error>()
+                    .query(|_, input: u32| async move { Ok(input * 2) }),
+            )
+            .procedure(
+                "fail",
+                rspc::Procedure::builder::<Error>()
+                    .mutation(|_, _: ()| async move { Err::<(), _>(Error) }),
+            )
+            .build()
+            .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/rspc", listener.local_addr().unwrap());
+        let app = axum::Router::new().nest(
+            "/rspc",
+            rspc_axum::endpoint_with_config(procedures, || (), config),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    fn request(kind: ProcedureKind, key: &'static str, input: Value) -> Request {
+        Request {
+            kind,
+            key: key.into(),
+            input,
+        }
+    }
+
+    #[tokio::test]
+    async fn batches() {
+        let transport = HttpTransport::new(serve(Default::default()).await).unwrap();
+        let responses = transport
+            .exec_batch(vec![
+                request(ProcedureKind::Query, "double", json!(1)),
+                request(ProcedureKind::Mutation, "fail", json!(null)),
+                request(ProcedureKind::Query, "missing", json!(null)),
+                request(ProcedureKind::Query, "double", json!(2)),
+            ])
+            .await
+            .unwrap();
+
+        // The responses are in the same order as the requests.
+        assert!(matches!(&responses[0], Response::Value(v) if *v == 2));
+        assert_eq!(error(responses[1].clone()).code, 418);
+        assert_eq!(error(responses[2].clone()).code, 404);
+        assert!(matches!(&responses[3], Response::Value(v) if *v == 4));
+        assert_eq!(responses.len(), 4);
+
+        let response = transport
+            .exec(request(ProcedureKind::Query, "double", json!(3)))
+            .await
+            .unwrap();
+        assert!(matches!(response, Response::Value(v) if v == 6));
+    }
+
+    #[tokio::test]
+    async fn rejected_batches() {
+        let config = rspc_axum::Config::new().limits(rspc_axum::Limits::new().body_size(16));
+        let transport = HttpTransport::new(serve(config).await).unwrap();
+        let responses = transport
+            .exec_batch(vec![
+                request(ProcedureKind::Query, "double", json!(1)),
+                request(ProcedureKind::Query, "double", json!(2)),
+            ])
+            .await
+            .unwrap();
+
+        // The error was for the whole batch so every request gets it.
+        for response in responses {
+            let err = error(response);
+            assert_eq!(err.code, 413);
+            assert_eq!(err.data.unwrap()["limit"], "bodySize");
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_responses() {
+        assert!(matches!(send(200, "not json").await, Response::Invalid(_)));
+        assert!(matches!(
+            send(200, r#"{"unexpected":true}"#).await,
+            Response::Invalid(_)
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    convert::Infallible,
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_util::{stream, Stream};
+use rspc_procedure::{DynOutput, ProcedureError, ProcedureStream, Procedures};
+
+use crate::transport::{
+    BatchTransport, Request, Response, ResponseError, SubscriptionTransport, Transport,
+};
+
+/// Execute procedures directly without going through a server.
+///
+/// This is useful for testing or embedding a server inside of an application (Eg. a CLI).
+pub struct InProcessTransport<TCtx> {
+    procedures: Procedures<TCtx>,
+    ctx_fn: Arc<dyn Fn() -> TCtx + Send + Sync>,
+}
+
+impl<TCtx> InProcessTransport<TCtx> {
+    pub fn new(
+        procedures: Procedures<TCtx>,
+        ctx_fn: impl Fn() -> TCtx + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            procedures,
+            ctx_fn: Arc::new(ctx_fn),
+        }
+    }
+}
+
+impl<TCtx> Clone for InProcessTransport<TCtx> {
+    fn clone(&self) -> Self {
+        Self {
+            procedures: self.procedures.clone(),
+            ctx_fn: self.ctx_fn.clone(),
+        }
+    }
+}
+
+impl<TCtx> fmt::Debug for InProcessTransport<TCtx> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InProcessTransport")
+            .field("procedures", &self.procedures)
+            .finish()
+    }
+}
+
+impl<TCtx: 'static> Transport for InProcessTransport<TCtx> {
+    type Error = Infallible;
+
+    async fn exec(&self, request: Request) -> Result<Response, Self::Error> {
+        let Some(procedure) = self.procedures.get(&request.key) else {
+            return Ok(Response::Error(ResponseError {
+                code: 404,
+                message: "the requested operation is not supported by this server".into(),
+                data: None,
+            }));
+        };
+
+        let mut stream = procedure.exec_with_deserializer((self.ctx_fn)(), request.input);
+        Ok(match stream.next().await {
+            Some(result) => to_response(result),
+            None => Response::Value(serde_json::Value::Null),
+        })
+    }
+}
+
+impl<TCtx: 'static> BatchTransport for InProcessTransport<TCtx> {
+    async fn exec_batch(&self, requests: Vec<Request>) -> Result<Vec<Response>, Self::Error> {
+        let mut responses = Vec::with_capacity(requests.len());
+        for request in requests {
+            responses.push(self.exec(request).await?);
+        }
+        Ok(responses)
+    }
+}
+
+impl<TCtx: 'static> SubscriptionTransport for InProcessTransport<TCtx> {
+    type Subscription = InProcessSubscription;
+
+    fn subscribe(&self, request: Request) -> Self::Subscription {
+        let Some(procedure) = self.procedures.get(&request.key) else {
+            return InProcessSubscription(Box::pin(stream::once(async {
+                Response::Error(ResponseError {
+                    code: 404,
+                    message: "the requested operation is not supported by this server".into(),
+                    data: None,
+                })
+            })));
+        };
+
+        let stream = procedure.exec_with_deserializer((self.ctx_fn)(), request.input);
+        InProcessSubscription(Box::pin(stream::unfold(
+            stream,
+            |mut stream: ProcedureStream| async move {
+                let response = to_response(stream.next().await?);
+                Some((response, stream))
+            },
+        )))
+    }
+}
+
+/// The values from a subscription over an [`InProcessTransport`].
+///
+/// Dropping this will stop the subscription.
+pub struct InProcessSubscription(Pin<Box<dyn Stream<Item = Response> + Send>>);
+
+impl Stream for InProcessSubscription {
+    type Item = Response;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx)
+    }
+}
+
+impl fmt::Debug for InProcessSubscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InProcessSubscription").finish()
+    }
+}
+
+fn to_response(result: Result<DynOutput<'_>, ProcedureError>) -> Response {
+    match result {
+        Ok(value) => match value.as_serialize().map(serde_json::to_value) {
+            Some(Ok(value)) => Response::Value(value),
+            Some(Err(err)) => Response::Error(ResponseError {
+                code: 500,
+                message: format!("error serializing value: {err}"),
+                data: None,
+            }),
+            None => Response::Error(ResponseError {
+                code: 500,
+                message: "procedure returned a non-serializable value".into(),
+                data: None,
+            }),
+        },
+        Err(err) => Response::Error(ResponseError {
+            code: err.status().into(),
+            message: err.to_string(),
+            data: serde_json::to_value(&err).ok(),
+        }),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use futures_util::StreamExt;
+    use rspc::{Procedure, ResolverError, Router};
+    use serde::Serialize;
+    use serde_json::json;
+    use specta::Type;
+
+    use super::*;
+    use crate::ProcedureKind;
+
+    #[derive(Debug, Serialize, Type)]
+    struct Error(u16);
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            ResolverError::new(self.0, None::<std::io::Error>)
+                .with_status(self.0)
+                .into()
+        }
+    }
+
+    fn transport() -> InProcessTransport<()> {
+        let (procedures, _) = <Router>::new()
+            .procedure(
+                "status",
+                Procedure::builder::<Error>().query(|_, status: u16| async move {
+                    match status {
+                        200 => Ok(status),
+                        status => Err(Error(status)),
+                    }
+                }),
+            )
+            .procedure(
+                "count",
+                Procedure::builder::<Error>().subscription(|_, to: u16| async move {
+                    Ok(rspc::Stream(futures_util::stream::iter(
+                        (0..to).map(Ok::<_, Error>),
+                    )))
+                }),
+            )
+            .build()
+            .unwrap();
+        InProcessTransport::new(procedures, || ())
+    }
+
+    async fn exec(key: &'static str, input: serde_json::Value) -> Response {
+        transport()
+            .exec(Request {
+                kind: ProcedureKind::Query,
+                key: key.into(),
+                input,
+            })
+            .await
+            .unwrap()
+    }
+
+    fn code(response: Response) -> i32 {
+        match response {
+            Response::Value(_) => 200,
+            Response::Error(err) => err.code,
+            Response::Invalid(_) => 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn statuses() {
+        assert!(matches!(exec("status", json!(200)).await, Response::Value(v) if v == 200));
+        // The resolver's status is used, the same as `rspc-axum`.
+        assert_eq!(code(exec("status", json!(404)).await), 404);
+        assert_eq!(code(exec("status", json!(503)).await), 503);
+        assert_eq!(code(exec("status", json!("a")).await), 400);
+        assert_eq!(code(exec("missing", json!(null)).await), 404);
+    }
+
+    #[tokio::test]
+    async fn subscriptions() {
+        let values = transport()
+            .subscribe(Request {
+                kind: ProcedureKind::Subscription,
+                key: "count".into(),
+                input: json!(3),
+            })
+            .map(|response| match response {
+                Response::Value(v) => v,
+                Response::Error(err) => json!(err.code),
+                Response::Invalid(_) => json!(null),
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(values, [json!(0), json!(1), json!(2)]);
+    }
+}
@@ -9,106 +9,168 @@
 )]
 
 // TODO: Change `exec` to `query`/`mutation`/`subscription` with a bound on the incoming operation?
-// TODO: Error handling working (typesafe errors + internal errors being throw not panic)
-// TODO: Maybe make inner client a trait so the user can use any HTTP client.
 // TODO: Treating `reqwest` as a public or private dependency?
 // TODO: Supporting transport formats other than JSON?
 // TODO: Is this safe to use from the same app that defines the router? If not we should try and forbid it with a compiler error.
 
-use std::{borrow::Cow, marker::PhantomData};
+mod batch;
+mod error;
+#[cfg(feature = "http")]
+mod http;
+mod in_process;
+mod transport;
+#[cfg(feature = "ws")]
+mod ws;
 
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+pub use batch::{Batch, BatchItem, BatchingTransport};
+pub use error::{Error, RspcError};
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+pub use http::HttpTransport;
+pub use in_process::{InProcessSubscription, InProcessTransport};
+pub use transport::{
+    BatchTransport, Request, Response, ResponseError, SubscriptionTransport, Transport,
+};
+#[cfg(feature = "ws")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+pub use ws::{WebsocketSubscription, WebsocketTransport};
+
+use std::marker::PhantomData;
+
+use futures_util::{future::Either, stream, Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
 
 /// TODO
-#[derive(Debug, Clone)]
-pub struct Client<P> {
-    url: Cow<'static, str>,
-    client: reqwest::Client,
-    phantom: PhantomData<P>,
+#[derive(Debug)]
+pub struct Client<P, T> {
+    transport: T,
+    phantom: PhantomData<fn() -> P>,
+}
+
+impl<P, T: Clone> Clone for Client<P, T> {
+    fn clone(&self) -> Self {
+        Self {
+            transport: self.transport.clone(),
+            phantom: PhantomData,
+        }
+    }
 }
 
-impl<P> Client<P> {
-    pub fn new(url: impl Into<Cow<'static, str>>) -> Self {
+#[cfg(feature = "http")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+impl<P> Client<P, HttpTransport> {
+    /// Create a new client using the [`HttpTransport`].
+    ///
+    /// This fails if the HTTP client can't be initialised (Eg. the TLS backend failed to load).
+    pub fn new(url: impl Into<std::borrow::Cow<'static, str>>) -> Result<Self, reqwest::Error> {
+        HttpTransport::new(url).map(Self::with_transport)
+    }
+}
+
+impl<P, T: Transport> Client<P, T> {
+    pub fn with_transport(transport: T) -> Self {
         Self {
-            url: url.into(),
-            client: reqwest::Client::builder()
-                .user_agent(concat!(
-                    env!("CARGO_PKG_NAME"),
-                    "/",
-                    env!("CARGO_PKG_VERSION")
-                ))
-                .build()
-                .unwrap(), // TODO: Can this fail?
+            transport,
             phantom: PhantomData,
         }
     }
 
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
     pub async fn exec<O: Procedure<Procedures = P>>(
         &self,
         input: O::Input,
-    ) -> Result<O::Output, O::Error> {
-        let url = format!(
-            "{}{}{}",
-            self.url,
-            if self.url.ends_with("/") { "" } else { "/" },
-            O::KEY
-        );
-
-        match O::KIND {
-            ProcedureKind::Query => {
-                let res = self
-                    .client
-                    .get(&url)
-                    .query(&[("input", serde_json::to_string(&input).unwrap())]) // TODO: Error handling
-                    .send()
-                    .await
-                    .unwrap(); // TODO: Error handling
-
-                // TODO: This is just ignoring error handling. This client is designed as a prototype not to be used.
-                #[derive(Deserialize)]
-                pub struct LegacyFormat {
-                    result: LegacyResult,
-                }
-                #[derive(Deserialize)]
-                pub struct LegacyResult {
-                    #[serde(rename = "type")]
-                    _type: String,
-                    data: serde_json::Value,
-                }
-
-                let result: LegacyFormat = res.json().await.unwrap();
-                Ok(serde_json::from_value(result.result.data).unwrap())
-            }
-            ProcedureKind::Mutation => {
-                let res = self
-                    .client
-                    .post(&url)
-                    .body(serde_json::to_string(&input).unwrap()) // TODO: Error handling
-                    .send()
-                    .await
-                    .unwrap(); // TODO: Error handling
-
-                // TODO: This is just ignoring error handling. This client is designed as a prototype not to be used.
-                #[derive(Deserialize)]
-                pub struct LegacyFormat {
-                    result: LegacyResult,
-                }
-                #[derive(Deserialize)]
-                pub struct LegacyResult {
-                    #[serde(rename = "type")]
-                    _type: String,
-                    data: serde_json::Value,
-                }
-
-                let result: LegacyFormat = res.json().await.unwrap();
-                Ok(serde_json::from_value(result.result.data).unwrap())
-            }
-            ProcedureKind::Subscription => {
-                // TODO: We will need to implement websocket support somehow. https://github.com/seanmonstar/reqwest/issues/864
-                // TODO: Returning a stream
-                unimplemented!("subscriptions are not supported yet!");
-            }
+    ) -> Result<O::Output, Error<O::Error>> {
+        if O::KIND == ProcedureKind::Subscription {
+            return Err(Error::Rspc(RspcError {
+                code: 400,
+                variant: None,
+                message: format!("'{}' is a subscription, use `Client::subscribe`", O::KEY),
+            }));
         }
+
+        let response = self
+            .transport
+            .exec(Request {
+                kind: O::KIND,
+                key: O::KEY.into(),
+                input: serde_json::to_value(&input).map_err(Error::Encode)?,
+            })
+            .await
+            .map_err(|err| Error::Transport(Box::new(err)))?;
+
+        decode::<O>(response)
+    }
+}
+
+impl<P, T: BatchTransport> Client<P, T> {
+    /// Group multiple queries and mutations into a single request.
+    ///
+    /// ```ignore
+    /// let mut batch = client.batch();
+    /// let version = batch.exec::<bindings::version>(());
+    /// let echo = batch.exec::<bindings::echo>("Hello".into());
+    /// batch.send().await;
+    ///
+    /// println!("{:?} {:?}", version.await, echo.await);
+    /// ```
+    pub fn batch(&self) -> Batch<'_, P, T> {
+        Batch::new(self)
+    }
+}
+
+impl<P, T: SubscriptionTransport> Client<P, T> {
+    /// Start a subscription.
+    ///
+    /// The stream ends once the subscription finishes on the server. Dropping the stream will stop the subscription.
+    pub fn subscribe<O: Procedure<Procedures = P>>(
+        &self,
+        input: O::Input,
+    ) -> impl Stream<Item = Result<O::Output, Error<O::Error>>> + Send + 'static {
+        // `None` means the procedure isn't a subscription.
+        let input = match O::KIND {
+            ProcedureKind::Subscription => serde_json::to_value(&input).map_err(Some),
+            _ => Err(None),
+        };
+        let input = match input {
+            Ok(input) => input,
+            Err(err) => {
+                return Either::Left(stream::once(async move {
+                    Err(match err {
+                        Some(err) => Error::Encode(err),
+                        None => Error::Rspc(RspcError {
+                            code: 400,
+                            variant: None,
+                            message: format!(
+                                "'{}' is not a subscription, use `Client::exec`",
+                                O::KEY
+                            ),
+                        }),
+                    })
+                }))
+            }
+        };
+
+        Either::Right(
+            self.transport
+                .subscribe(Request {
+                    kind: O::KIND,
+                    key: O::KEY.into(),
+                    input,
+                })
+                .map(|response| decode::<O>(response)),
+        )
+    }
+}
+
+// Convert a response into the procedure's output or error.
+fn decode<O: Procedure>(response: Response) -> Result<O::Output, Error<O::Error>> {
+    match response {
+        Response::Value(value) => serde_json::from_value(value).map_err(Error::Decode),
+        Response::Error(err) => Err(Error::from_response(err)),
+        Response::Invalid(err) => Err(Error::Protocol(err)),
     }
 }
 
@@ -0,0 +1,94 @@
+use std::{borrow::Cow, error, fmt, future::Future, sync::Arc};
+
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ProcedureKind;
+
+/// A method of executing procedures on an rspc server.
+///
+/// Refer to [`HttpTransport`](crate::HttpTransport), [`WebsocketTransport`](crate::WebsocketTransport) and [`InProcessTransport`](crate::InProcessTransport).
+pub trait Transport {
+    /// An error which prevented the request from being completed.
+    type Error: error::Error + Send + Sync + 'static;
+
+    /// Execute a query or mutation.
+    fn exec(&self, request: Request) -> impl Future<Output = Result<Response, Self::Error>> + Send;
+}
+
+/// A [`Transport`] which is able to execute multiple queries and mutations in a single request.
+///
+/// This is implemented by [`HttpTransport`](crate::HttpTransport) and [`InProcessTransport`](crate::InProcessTransport).
+/// Refer to [`Client::batch`](crate::Client::batch) and [`BatchingTransport`](crate::BatchingTransport).
+pub trait BatchTransport: Transport {
+    /// Execute a batch of queries and mutations.
+    ///
+    /// The responses must be in the same order as the requests.
+    fn exec_batch(
+        &self,
+        requests: Vec<Request>,
+    ) -> impl Future<Output = Result<Vec<Response>, Self::Error>> + Send;
+}
+
+/// A [`Transport`] which is able to execute subscriptions.
+///
+/// This is implemented by [`WebsocketTransport`](crate::WebsocketTransport) and [`InProcessTransport`](crate::InProcessTransport).
+pub trait SubscriptionTransport: Transport {
+    /// The values of a subscription. Dropping it must stop the subscription.
+    type Subscription: Stream<Item = Response> + Send + 'static;
+
+    /// Start a subscription.
+    fn subscribe(&self, request: Request) -> Self::Subscription;
+}
+
+/// A request to execute a procedure.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub kind: ProcedureKind,
+    pub key: Cow<'static, str>,
+    pub input: Value,
+}
+
+/// The result of executing a procedure.
+#[derive(Debug, Clone)]
+pub enum Response {
+    Value(Value),
+    Error(ResponseError),
+    /// The server's response couldn't be decoded (Eg. a proxy responded with an HTML error page).
+    Invalid(Arc<serde_json::Error>),
+}
+
+/// An error returned by the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseError {
+    pub code: i32,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl error::Error for ResponseError {}
+
+// The `result` of a JSON-RPC response from `rspc-axum`.
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub(crate) enum ResponseInner {
+    Event(Value),
+    Response(Value),
+    Error(ResponseError),
+}
+
+impl From<ResponseInner> for Response {
+    fn from(inner: ResponseInner) -> Self {
+        match inner {
+            ResponseInner::Event(v) | ResponseInner::Response(v) => Response::Value(v),
+            ResponseInner::Error(err) => Response::Error(err),
+        }
+    }
+}
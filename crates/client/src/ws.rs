@@ -0,0 +1,536 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt, io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, OnceLock,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::{SinkExt, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{self, Message},
+};
+
+use crate::{
+    transport::{Request, Response, ResponseInner, SubscriptionTransport, Transport},
+    ProcedureKind,
+};
+
+// How long to wait before reconnecting. The last value is used for all further attempts.
+const BACKOFF: [Duration; 5] = [
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+type Pending = HashMap<u32, oneshot::Sender<Result<Response, tungstenite::Error>>>;
+// The message to start the subscription, the id of the last event to resume it from and where to send it's values.
+type Subscriptions = HashMap<u32, (Value, Option<String>, mpsc::UnboundedSender<Response>)>;
+
+/// Execute procedures over a single websocket connection.
+///
+/// The connection is opened on the first request. If it's lost, or the server is shutting down, the transport will reconnect and resubscribe all active subscriptions.
+/// Subscriptions are resumed from the last event they received if the server has resumable subscriptions enabled.
+/// A subscription's stream ends once the server says it has finished, including after an error which stopped it.
+/// Queries and mutations which are in-flight when the connection is lost will error.
+#[derive(Clone)]
+pub struct WebsocketTransport {
+    url: Cow<'static, str>,
+    id: Arc<AtomicU32>,
+    commands: Arc<OnceLock<mpsc::UnboundedSender<Command>>>,
+}
+
+enum Command {
+    Request {
+        id: u32,
+        msg: String,
+        tx: oneshot::Sender<Result<Response, tungstenite::Error>>,
+    },
+    Subscribe {
+        id: u32,
+        msg: Value,
+        tx: mpsc::UnboundedSender<Response>,
+    },
+    Unsubscribe {
+        id: u32,
+    },
+}
+
+impl WebsocketTransport {
+    /// Create a new websocket transport.
+    ///
+    /// This should be the URL of the websocket endpoint (Eg. `ws://localhost:4000/rspc/ws`).
+    pub fn new(url: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            url: url.into(),
+            id: Default::default(),
+            commands: Default::default(),
+        }
+    }
+
+    // Get the channel to the task which owns the connection, starting it if it's not running yet.
+    fn commands(&self) -> &mpsc::UnboundedSender<Command> {
+        self.commands.get_or_init(|| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            tokio::spawn(run(self.url.clone(), rx));
+            tx
+        })
+    }
+}
+
+impl fmt::Debug for WebsocketTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebsocketTransport")
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+impl Transport for WebsocketTransport {
+    type Error = tungstenite::Error;
+
+    async fn exec(&self, request: Request) -> Result<Response, Self::Error> {
+        let id = self.id.fetch_add(1, Ordering::Relaxed);
+        let method = match request.kind {
+            ProcedureKind::Query => "query",
+            ProcedureKind::Mutation => "mutation",
+            ProcedureKind::Subscription => "subscription",
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.commands()
+            .send(Command::Request {
+                id,
+                msg: json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": method,
+                    "params": {
+                        "path": request.key,
+                        "input": request.input,
+                    },
+                })
+                .to_string(),
+                tx,
+            })
+            .map_err(|_| tungstenite::Error::ConnectionClosed)?;
+
+        rx.await
+            .unwrap_or(Err(tungstenite::Error::ConnectionClosed))
+    }
+}
+
+impl SubscriptionTransport for WebsocketTransport {
+    type Subscription = WebsocketSubscription;
+
+    fn subscribe(&self, request: Request) -> Self::Subscription {
+        let id = self.id.fetch_add(1, Ordering::Relaxed);
+        let commands = self.commands().clone();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        // If the connection task has stopped the sender is dropped and the stream ends immediately.
+        let _ = commands.send(Command::Subscribe {
+            id,
+            msg: json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "subscription",
+                "params": {
+                    "path": request.key,
+                    "input": [id, request.input],
+                },
+            }),
+            tx,
+        });
+
+        WebsocketSubscription { id, rx, commands }
+    }
+}
+
+/// The values from a subscription over a [`WebsocketTransport`].
+///
+/// Dropping this will stop the subscription on the server.
+pub struct WebsocketSubscription {
+    id: u32,
+    rx: mpsc::UnboundedReceiver<Response>,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl Stream for WebsocketSubscription {
+    type Item = Response;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for WebsocketSubscription {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Unsubscribe { id: self.id });
+    }
+}
+
+impl fmt::Debug for WebsocketSubscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebsocketSubscription")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+// The task which owns the connection.
+// It runs until the transport and all of it's subscriptions have been dropped.
+async fn run(url: Cow<'static, str>, mut rx: mpsc::UnboundedReceiver<Command>) {
+    let mut subscriptions = Subscriptions::new();
+    // Commands which were received while we were disconnected.
+    let mut queue = Vec::new();
+    let mut attempt = 0;
+
+    loop {
+        // We only connect when there is something to do.
+        if subscriptions.is_empty() && queue.is_empty() {
+            match rx.recv().await {
+                Some(cmd) => queue.push(cmd),
+                None => return,
+            }
+        }
+
+        let socket = match connect_async(&*url).await {
+            Ok((socket, _)) => socket,
+            Err(err) => {
+                // We fail requests instead of leaving them waiting on a server which might never come back.
+                for cmd in queue.drain(..) {
+                    match cmd {
+                        Command::Request { tx, .. } => {
+                            let _ = tx.send(Err(io::Error::other(err.to_string()).into()));
+                        }
+                        Command::Subscribe { id, msg, tx } => {
+                            subscriptions.insert(id, (msg, None, tx));
+                        }
+                        Command::Unsubscribe { id } => {
+                            subscriptions.remove(&id);
+                        }
+                    }
+                }
+
+                if !subscriptions.is_empty() {
+                    tokio::time::sleep(BACKOFF[attempt.min(BACKOFF.len() - 1)]).await;
+                    attempt += 1;
+                }
+                continue;
+            }
+        };
+        attempt = 0;
+
+        let (mut sink, mut stream) = socket.split();
+        let mut pending = Pending::new();
+
+        // Restart the subscriptions from the previous connection.
+        let mut connected = true;
+        for (msg, last_event_id, _) in subscriptions.values() {
+            let mut msg = msg.clone();
+            if let Some(last_event_id) = last_event_id {
+                msg["params"]["lastEventId"] = last_event_id.as_str().into();
+            }
+
+            if sink
+                .send(Message::Text(msg.to_string().into()))
+                .await
+                .is_err()
+            {
+                connected = false;
+                break;
+            }
+        }
+
+        let mut queued = std::mem::take(&mut queue).into_iter();
+        while connected {
+            let cmd = match queued.next() {
+                Some(cmd) => cmd,
+                None => tokio::select! {
+                    cmd = rx.recv() => match cmd {
+                        Some(cmd) => cmd,
+                        // The transport and all subscriptions have been dropped.
+                        None => return,
+                    },
+                    msg = stream.next() => {
+                        match msg {
+                            Some(Ok(msg)) => dispatch(msg, &mut pending, &mut subscriptions),
+                            _ => connected = false,
+                        }
+                        continue;
+                    }
+                },
+            };
+
+            let msg = match cmd {
+                Command::Request { id, msg, tx } => {
+                    pending.insert(id, tx);
+                    msg
+                }
+                Command::Subscribe { id, msg, tx } => {
+                    let text = msg.to_string();
+                    subscriptions.insert(id, (msg, None, tx));
+                    text
+                }
+                Command::Unsubscribe { id } => {
+                    if subscriptions.remove(&id).is_none() {
+                        continue;
+                    }
+
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "method": "subscriptionStop",
+                        "params": {
+                            "input": id,
+                        },
+                    })
+                    .to_string()
+                }
+            };
+
+            if sink.send(Message::Text(msg.into())).await.is_err() {
+                connected = false;
+            }
+        }
+
+        // Anything we didn't get to is handled once we reconnect.
+        queue.extend(queued);
+        // Dropping the senders will cause any in-flight requests to error.
+        drop(pending);
+
+        // Avoid spinning if the server is accepting connections but immediately closing them.
+        if !subscriptions.is_empty() {
+            tokio::time::sleep(BACKOFF[0]).await;
+        }
+    }
+}
+
+// Send a message from the server to the request or subscription it belongs to.
+fn dispatch(msg: Message, pending: &mut Pending, subscriptions: &mut Subscriptions) {
+    #[derive(Deserialize)]
+    struct Msg {
+        id: Option<u32>,
+        // This is a `ResponseInner` with the id of the event if the subscription is resumable.
+        result: Value,
+    }
+
+    let msg = match msg {
+        Message::Text(text) => serde_json::from_str::<Msg>(&text),
+        Message::Binary(binary) => serde_json::from_slice(&binary),
+        _ => return,
+    };
+    let (id, result) = match msg {
+        Ok(Msg {
+            id: Some(id),
+            result,
+        }) => (id, result),
+        // We can't tell which request this is for (Eg. the server couldn't parse it) so every request waiting for a response fails.
+        // Otherwise they would wait until the connection is closed.
+        Ok(Msg { id: None, result }) => {
+            let response = decode(result).map_or_else(Response::Invalid, Into::into);
+            for (_, tx) in pending.drain() {
+                let _ = tx.send(Ok(response.clone()));
+            }
+            return;
+        }
+        Err(err) => {
+            let err = Arc::new(err);
+            for (_, tx) in pending.drain() {
+                let _ = tx.send(Ok(Response::Invalid(err.clone())));
+            }
+            return;
+        }
+    };
+
+    // The subscription has ended. Dropping it's sender ends the stream.
+    if result.get("type").and_then(Value::as_str) == Some("complete") {
+        subscriptions.remove(&id);
+        return;
+    }
+
+    let event_id = result.get("id").and_then(Value::as_str).map(str::to_string);
+    let result = decode(result);
+
+    if let Some(tx) = pending.remove(&id) {
+        let _ = tx.send(Ok(result.map_or_else(Response::Invalid, Into::into)));
+    } else if let Some((_, last_event_id, tx)) = subscriptions.get_mut(&id) {
+        // The server is shutting down so the subscription is resumed once we reconnect.
+        if let Ok(ResponseInner::Error(err)) = &result {
+            if err
+                .data
+                .as_ref()
+                .and_then(|data| data.get("reconnect"))
+                .and_then(Value::as_bool)
+                == Some(true)
+            {
+                return;
+            }
+        }
+
+        if event_id.is_some() {
+            *last_event_id = event_id;
+        }
+        let _ = tx.send(result.map_or_else(Response::Invalid, Into::into));
+    }
+}
+
+fn decode(result: Value) -> Result<ResponseInner, Arc<serde_json::Error>> {
+    ResponseInner::deserialize(result).map_err(Arc::new)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn text(msg: Value) -> Message {
+        Message::Text(msg.to_string().into())
+    }
+
+    fn request(
+        pending: &mut Pending,
+        id: u32,
+    ) -> oneshot::Receiver<Result<Response, tungstenite::Error>> {
+        let (tx, rx) = oneshot::channel();
+        pending.insert(id, tx);
+        rx
+    }
+
+    fn subscribe(subscriptions: &mut Subscriptions, id: u32) -> mpsc::UnboundedReceiver<Response> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        subscriptions.insert(id, (Value::Null, None, tx));
+        rx
+    }
+
+    #[test]
+    fn responses() {
+        let mut pending = Pending::new();
+        let mut subscriptions = Subscriptions::new();
+        let mut a = request(&mut pending, 1);
+        let mut b = request(&mut pending, 2);
+
+        dispatch(
+            text(
+                json!({ "jsonrpc": "2.0", "id": 2, "result": { "type": "response", "data": "b" } }),
+            ),
+            &mut pending,
+            &mut subscriptions,
+        );
+        assert!(matches!(b.try_recv().unwrap().unwrap(), Response::Value(v) if v == "b"));
+        assert!(a.try_recv().is_err());
+
+        // A reply we can't decode fails the request instead of leaving it waiting.
+        dispatch(
+            text(json!({ "jsonrpc": "2.0", "id": 1, "result": { "type": "unknown" } })),
+            &mut pending,
+            &mut subscriptions,
+        );
+        assert!(matches!(
+            a.try_recv().unwrap().unwrap(),
+            Response::Invalid(_)
+        ));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn unknown_requests() {
+        let mut pending = Pending::new();
+        let mut subscriptions = Subscriptions::new();
+
+        // The server couldn't tell which request it was responding to.
+        let mut a = request(&mut pending, 1);
+        let mut b = request(&mut pending, 2);
+        dispatch(
+            text(json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "result": { "type": "error", "data": { "code": 413, "message": "too large", "data": null } },
+            })),
+            &mut pending,
+            &mut subscriptions,
+        );
+        for rx in [&mut a, &mut b] {
+            assert!(
+                matches!(rx.try_recv().unwrap().unwrap(), Response::Error(err) if err.code == 413)
+            );
+        }
+
+        // The message isn't valid at all.
+        let mut c = request(&mut pending, 3);
+        let mut events = subscribe(&mut subscriptions, 4);
+        dispatch(
+            Message::Text("not json".into()),
+            &mut pending,
+            &mut subscriptions,
+        );
+        assert!(matches!(
+            c.try_recv().unwrap().unwrap(),
+            Response::Invalid(_)
+        ));
+        // Subscriptions aren't waiting on a reply so they keep running.
+        assert!(events.try_recv().is_err());
+        assert!(subscriptions.contains_key(&4));
+    }
+
+    #[test]
+    fn subscriptions() {
+        let mut pending = Pending::new();
+        let mut subscriptions = Subscriptions::new();
+        let mut events = subscribe(&mut subscriptions, 1);
+
+        dispatch(
+            text(
+                json!({ "jsonrpc": "2.0", "id": 1, "result": { "type": "event", "data": 1, "id": "a" } }),
+            ),
+            &mut pending,
+            &mut subscriptions,
+        );
+        assert!(matches!(events.try_recv().unwrap(), Response::Value(v) if v == 1));
+        assert_eq!(subscriptions[&1].1.as_deref(), Some("a"));
+
+        // This is resumed after reconnecting so it doesn't reach the stream.
+        dispatch(
+            text(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": { "type": "error", "data": { "code": 503, "message": "server shutting down", "data": { "~rspc": true, "reconnect": true } } },
+            })),
+            &mut pending,
+            &mut subscriptions,
+        );
+        assert!(events.try_recv().is_err());
+
+        dispatch(
+            text(json!({ "jsonrpc": "2.0", "id": 1, "result": { "type": "event", "data": {} } })),
+            &mut pending,
+            &mut subscriptions,
+        );
+        assert!(matches!(events.try_recv().unwrap(), Response::Value(_)));
+        // Events without an id don't clear the last one.
+        assert_eq!(subscriptions[&1].1.as_deref(), Some("a"));
+
+        dispatch(
+            text(json!({ "jsonrpc": "2.0", "id": 1, "result": { "type": "complete" } })),
+            &mut pending,
+            &mut subscriptions,
+        );
+        assert!(subscriptions.is_empty());
+        assert!(matches!(
+            events.try_recv(),
+            Err(mpsc::error::TryRecvError::Disconnected)
+        ));
+    }
+}
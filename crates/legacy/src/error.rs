@@ -143,6 +143,11 @@ impl Error {
         }
     }
 
+    #[doc(hidden)]
+    pub fn code(&self) -> ErrorCode {
+        self.code.clone()
+    }
+
     #[doc(hidden)]
     pub fn message(&self) -> &str {
         &self.message
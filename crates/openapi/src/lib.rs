@@ -6,29 +6,30 @@
     html_favicon_url = "https://github.com/specta-rs/rspc/blob/main/.github/logo.png?raw=true"
 )]
 
-use std::{borrow::Cow, collections::HashMap, sync::Arc};
-
-use axum::{
-    body::Bytes,
-    extract::Query,
-    http::{request::Parts, StatusCode},
-    response::Html,
-    routing::{delete, get, patch, post, put},
-    Json,
-};
-use futures::StreamExt;
-use rspc::{middleware::Middleware, Extension, Procedure, ResolverInput, Router};
-use serde_json::json;
-
-// TODO: Properly handle inputs from query params
+use std::{borrow::Cow, collections::HashMap};
+
+use rspc::Extension;
+
+#[cfg(feature = "axum")]
+mod mount;
+mod params;
+mod routes;
+mod schema;
+mod security;
+
+#[cfg(feature = "axum")]
+#[cfg_attr(docsrs, doc(cfg(feature = "axum")))]
+pub use mount::mount;
+pub use params::{InputError, Parameter, ParameterLocation};
+pub use routes::{Route, RouteTable};
+pub use security::{security, ApiKeyLocation, SecurityScheme};
+
 // TODO: Properly handle responses from query params
-// TODO: Support input's coming from URL. Eg. `/todos/{id}` like tRPC-OpenAPI
-// TODO: Support `application/x-www-form-urlencoded` bodies like tRPC-OpenAPI
-// TODO: Probs put SwaggerUI behind a feature flag
 
 pub struct OpenAPI {
     method: &'static str,
     path: Cow<'static, str>,
+    security: Vec<SecurityScheme>,
 }
 
 impl OpenAPI {
@@ -39,6 +40,7 @@ impl OpenAPI {
         Self {
             method: "GET",
             path: path.into(),
+            security: Vec::new(),
         }
     }
 
@@ -46,6 +48,7 @@ impl OpenAPI {
         Self {
             method: "POST",
             path: path.into(),
+            security: Vec::new(),
         }
     }
 
@@ -53,6 +56,7 @@ impl OpenAPI {
         Self {
             method: "PUT",
             path: path.into(),
+            security: Vec::new(),
         }
     }
 
@@ -60,6 +64,7 @@ impl OpenAPI {
         Self {
             method: "PATCH",
             path: path.into(),
+            security: Vec::new(),
         }
     }
 
@@ -67,214 +72,175 @@ impl OpenAPI {
         Self {
             method: "DELETE",
             path: path.into(),
+            security: Vec::new(),
         }
     }
 
-    // TODO: Configure other OpenAPI stuff like auth???
+    /// Require authentication using the given [`SecurityScheme`].
+    ///
+    /// This can be called multiple times where any of the schemes will be accepted.
+    /// Refer to [`security`] to apply a scheme without declaring an endpoint.
+    ///
+    /// The scheme isn't enforced by rspc. Return an error from your resolver with [`ResolverError::with_status`](rspc_procedure::ResolverError::with_status) set to `401` or `403` to reject a request.
+    pub fn security(mut self, scheme: SecurityScheme) -> Self {
+        self.security.push(scheme);
+        self
+    }
 
+    /// Register the endpoint for the procedure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if another procedure is already registered for the same method and path.
     pub fn build<TCtx, TInput, TResult>(self) -> Extension<TCtx, TInput, TResult> {
         Extension::new().setup(move |state, meta| {
+            let state = state.get_mut_or_init::<OpenAPIState>(Default::default);
+            let conflict = state.endpoints.iter().find(|((method, path), key)| {
+                *method == self.method && same_path(path, &self.path) && *key != meta.name()
+            });
+            #[allow(clippy::panic)]
+            if let Some(((method, path), existing)) = conflict {
+                panic!(
+                    "rspc-openapi: '{existing}' and '{}' are both registered for '{method} {path}'",
+                    meta.name()
+                );
+            }
+
             state
-                .get_mut_or_init::<OpenAPIState>(Default::default)
-                .0
+                .endpoints
                 .insert((self.method, self.path), meta.name().to_string());
+            if !self.security.is_empty() {
+                state
+                    .security
+                    .entry(meta.name().to_string())
+                    .or_default()
+                    .extend(self.security);
+            }
         })
     }
 }
 
+// Check if two paths would match the same requests. The names of the parameters don't matter.
+fn same_path(a: &str, b: &str) -> bool {
+    fn segments(path: &str) -> Vec<&str> {
+        path.trim_start_matches('/')
+            .split('/')
+            .map(
+                |s| match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                    Some(param) if param.starts_with('*') => "{*}",
+                    Some(_) => "{}",
+                    None => s,
+                },
+            )
+            .collect()
+    }
+
+    segments(a) == segments(b)
+}
+
 // The state that is stored into rspc.
-// A map of (method, path) to procedure name.
 #[derive(Default)]
-struct OpenAPIState(HashMap<(&'static str, Cow<'static, str>), String>);
-
-// TODO: Axum should be behind feature flag
-// TODO: Can we decouple webserver from OpenAPI while keeping something maintainable????
-pub fn mount<TCtx, S>(
-    router: Router<TCtx>,
-    // TODO: Make Axum extractors work
-    ctx_fn: impl Fn(&Parts) -> TCtx + Clone + Send + Sync + 'static,
-) -> axum::Router<S>
-where
-    S: Clone + Send + Sync + 'static,
-    TCtx: Send + 'static,
-{
-    let mut r = axum::Router::new();
-
-    // let mut paths: HashMap<_, HashMap<_, _>> = HashMap::new();
-    // if let Some(endpoints) = router.state.get::<OpenAPIState>() {
-    //     for ((method, path), procedure_name) in endpoints.0.iter() {
-    //         let procedure = router
-    //             .into_iter()
-    //             .find(|(k, _)| k.join(".") == *procedure_name)
-    //             // .get(&Cow::Owned(procedure_name.clone()))
-    //             .expect("unreachable: a procedure was registered that doesn't exist")
-    //             .clone();
-    //         let ctx_fn = ctx_fn.clone();
-
-    //         paths
-    //             .entry(path.clone())
-    //             .or_default()
-    //             .insert(method.to_lowercase(), procedure.clone());
-
-    //         r = r.route(
-    //             path,
-    //             match *method {
-    //                 "GET" => {
-    //                     // TODO: By moving `procedure` into the closure we hang onto the types for the duration of the program which is probs undesirable.
-    //                     get(
-    //                         move |parts: Parts, query: Query<HashMap<String, String>>| async move {
-    //                             let ctx = (ctx_fn)(&parts);
-
-    //                             handle_procedure(
-    //                                 ctx,
-    //                                 &mut serde_json::Deserializer::from_str(
-    //                                     query.get("input").map(|v| &**v).unwrap_or("null"),
-    //                                 ),
-    //                                 procedure,
-    //                             )
-    //                             .await
-    //                         },
-    //                     )
-    //                 }
-    //                 "POST" => {
-    //                     // TODO: By moving `procedure` into the closure we hang onto the types for the duration of the program which is probs undesirable.
-    //                     post(move |parts: Parts, body: Bytes| async move {
-    //                         let ctx = (ctx_fn)(&parts);
-
-    //                         handle_procedure(
-    //                             ctx,
-    //                             &mut serde_json::Deserializer::from_slice(&body),
-    //                             procedure,
-    //                         )
-    //                         .await
-    //                     })
-    //                 }
-    //                 "PUT" => {
-    //                     // TODO: By moving `procedure` into the closure we hang onto the types for the duration of the program which is probs undesirable.
-    //                     put(move |parts: Parts, body: Bytes| async move {
-    //                         let ctx = (ctx_fn)(&parts);
-
-    //                         handle_procedure(
-    //                             ctx,
-    //                             &mut serde_json::Deserializer::from_slice(&body),
-    //                             procedure,
-    //                         )
-    //                         .await
-    //                     })
-    //                 }
-    //                 "PATCH" => {
-    //                     // TODO: By moving `procedure` into the closure we hang onto the types for the duration of the program which is probs undesirable.
-    //                     patch(move |parts: Parts, body: Bytes| async move {
-    //                         let ctx = (ctx_fn)(&parts);
-
-    //                         handle_procedure(
-    //                             ctx,
-    //                             &mut serde_json::Deserializer::from_slice(&body),
-    //                             procedure,
-    //                         )
-    //                         .await
-    //                     })
-    //                 }
-    //                 "DELETE" => {
-    //                     // TODO: By moving `procedure` into the closure we hang onto the types for the duration of the program which is probs undesirable.
-    //                     delete(move |parts: Parts, body: Bytes| async move {
-    //                         let ctx = (ctx_fn)(&parts);
-
-    //                         handle_procedure(
-    //                             ctx,
-    //                             &mut serde_json::Deserializer::from_slice(&body),
-    //                             procedure,
-    //                         )
-    //                         .await
-    //                     })
-    //                 }
-    //                 _ => panic!("Unsupported method"),
-    //             },
-    //         );
-    //     }
-    // }
-
-    // let schema = Arc::new(json!({
-    //   "openapi": "3.0.3",
-    //   "info": {
-    //     "title": "rspc OpenAPI",
-    //     "description": "This is a demo of rspc OpenAPI",
-    //     "version": "0.0.0"
-    //   },
-    //   "paths": paths.into_iter()
-    //     .map(|(path, procedures)| {
-    //         let mut methods = HashMap::new();
-    //         for (method, procedure) in procedures {
-    //             methods.insert(method.to_string(), json!({
-    //                 "operationId": procedure.ty().key.to_string(),
-    //                 "responses": {
-    //                     "200": {
-    //                         "description": "Successful operation"
-    //                     }
-    //                 }
-    //             }));
-    //         }
-
-    //         (path, methods)
-    //     })
-    //     .collect::<HashMap<_, _>>()
-    // })); // TODO: Maybe convert to string now cause it will be more efficient to clone
-
-    r.route(
-        // TODO: Allow the user to configure this URL & turn it off
-        "/api/docs",
-        get(|| async { Html(include_str!("swagger.html")) }),
-    )
-    // .route(
-    //     // TODO: Allow the user to configure this URL & turn it off
-    //     "/api/openapi.json",
-    //     get(move || async move { Json((*schema).clone()) }),
-    // )
+struct OpenAPIState {
+    // A map of (method, path) to procedure name.
+    endpoints: HashMap<(&'static str, Cow<'static, str>), String>,
+    // A map of procedure name to the schemes it accepts.
+    security: HashMap<String, Vec<SecurityScheme>>,
 }
 
-// Used for `GET` and `POST` endpoints
-// async fn handle_procedure<'a, 'de, TCtx>(
-//     ctx: TCtx,
-//     input: DynInput<'a, 'de>,
-//     procedure: Procedure2<TCtx>,
-// ) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-//     let mut stream = procedure.exec(ctx, input).map_err(|err| {
-//         (
-//             StatusCode::INTERNAL_SERVER_ERROR,
-//             Json(json!({
-//                 // TODO: This or not?
-//                 "_rspc_error": err.to_string()
-//             })),
-//         )
-//     })?;
-
-//     // TODO: Support for streaming
-//     while let Some(value) = stream.next().await {
-//         // TODO: We should probs deserialize into buffer instead of value???
-//         return match value.map(|v| v.serialize(serde_json::value::Serializer)) {
-//             Ok(Ok(value)) => Ok(Json(value)),
-//             Ok(Err(err)) => Err((
-//                 StatusCode::INTERNAL_SERVER_ERROR,
-//                 Json(json!({
-//                     "_rspc_error": err.to_string()
-//                 })),
-//             )),
-//             Err(err) => Err((
-//                 StatusCode::from_u16(err.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
-//                 Json(
-//                     err.serialize(serde_json::value::Serializer)
-//                         .map_err(|err| {
-//                             (
-//                                 StatusCode::INTERNAL_SERVER_ERROR,
-//                                 Json(json!({
-//                                     "_rspc_error": err.to_string()
-//                                 })),
-//                             )
-//                         })?,
-//                 ),
-//             )),
-//         };
-//     }
-
-//     Ok(Json(serde_json::Value::Null))
-// }
+/// Configure the OpenAPI document and how it's served.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub(crate) title: Cow<'static, str>,
+    pub(crate) version: Cow<'static, str>,
+    pub(crate) description: Option<Cow<'static, str>>,
+    pub(crate) document_path: Option<Cow<'static, str>>,
+    pub(crate) document_url: Option<Cow<'static, str>>,
+    pub(crate) swagger_path: Option<Cow<'static, str>>,
+    pub(crate) max_body_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            title: "rspc OpenAPI".into(),
+            version: "0.0.0".into(),
+            description: None,
+            document_path: Some("/api/openapi.json".into()),
+            document_url: None,
+            swagger_path: Some("/api/docs".into()),
+            max_body_size: 2 * 1024 * 1024,
+        }
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: impl Into<Cow<'static, str>>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn version(mut self, version: impl Into<Cow<'static, str>>) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<Cow<'static, str>>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the path the OpenAPI document is served at. Defaults to `/api/openapi.json`.
+    pub fn document_path(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        self.document_path = Some(path.into());
+        self
+    }
+
+    /// Don't serve the OpenAPI document.
+    ///
+    /// The Swagger UI will also be disabled unless [`Config::document_url`] is set.
+    pub fn without_document(mut self) -> Self {
+        self.document_path = None;
+        self
+    }
+
+    /// Set the URL the Swagger UI loads the document from.
+    ///
+    /// Defaults to the document path. You will need to set this if the router is nested or behind a proxy.
+    pub fn document_url(mut self, url: impl Into<Cow<'static, str>>) -> Self {
+        self.document_url = Some(url.into());
+        self
+    }
+
+    /// Set the path the Swagger UI is served at. Defaults to `/api/docs`.
+    pub fn swagger_path(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        self.swagger_path = Some(path.into());
+        self
+    }
+
+    /// Don't serve the Swagger UI.
+    pub fn without_swagger(mut self) -> Self {
+        self.swagger_path = None;
+        self
+    }
+
+    /// The maximum size of a request body in bytes. Larger requests are rejected with `413 Payload Too Large`. Defaults to 2 MiB.
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Get the HTML of the Swagger UI page, if it's enabled.
+    pub fn swagger_html(&self) -> Option<String> {
+        self.swagger_path.as_ref()?;
+        let url = self.document_url.as_ref().or(self.document_path.as_ref())?;
+
+        Some(include_str!("swagger.html").replace(
+            "\"/api/openapi.json\"",
+            &serde_json::to_string(url).expect("serializing a string can't fail"),
+        ))
+    }
+}
@@ -0,0 +1,85 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{Config, InputError, RouteTable};
+use axum::{
+    body::to_bytes,
+    extract::{Path, Request},
+    http::request::Parts,
+    response::Html,
+    routing::{get, on, MethodFilter},
+    Json, RequestExt,
+};
+use rspc::{Procedures, Types};
+
+/// Mount the procedures registered with [`OpenAPI`](crate::OpenAPI) onto an [`axum::Router`].
+///
+/// The OpenAPI document and Swagger UI are served as configured by [`Config`].
+pub fn mount<TCtx, S>(
+    procedures: Procedures<TCtx>,
+    types: &Types,
+    config: Config,
+    // TODO: Make Axum extractors work
+    ctx_fn: impl Fn(&Parts) -> TCtx + Clone + Send + Sync + 'static,
+) -> axum::Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    TCtx: Send + 'static,
+{
+    let mut r = axum::Router::new();
+    let table = RouteTable::new(&procedures, types);
+
+    let max_body_size = config.max_body_size;
+    for route in table.routes() {
+        let path = route.path().to_string();
+        let route = Arc::new(route.clone());
+        let ctx_fn = ctx_fn.clone();
+
+        r = r.route(
+            &path,
+            on(
+                MethodFilter::try_from(route.method().clone())
+                    .expect("unreachable: `OpenAPI` only uses valid methods"),
+                // TODO: By moving `procedure` into the closure we hang onto the types for the duration of the program which is probs undesirable.
+                move |mut req: Request| async move {
+                    let path_params = req
+                        .extract_parts::<Option<Path<HashMap<String, String>>>>()
+                        .await
+                        .ok()
+                        .flatten()
+                        .map(|p| p.0)
+                        .unwrap_or_default();
+                    let (parts, body) = req.into_parts();
+                    let ctx = (ctx_fn)(&parts);
+
+                    let input = match to_bytes(body, max_body_size).await {
+                        Ok(body) => route.input(
+                            &parts,
+                            path_params.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+                            &body,
+                        ),
+                        Err(_) => Err(InputError::body_too_large(max_body_size)),
+                    };
+
+                    let (status, body) = match input {
+                        Ok(input) => route.exec(ctx, input).await,
+                        Err(err) => err.response(),
+                    };
+
+                    (status, Json(body))
+                },
+            ),
+        );
+    }
+
+    if let Some(path) = &config.document_path {
+        // TODO: Maybe convert to string now cause it will be more efficient to clone
+        let document = Arc::new(table.document(&config));
+        r = r.route(path, get(move || async move { Json((*document).clone()) }));
+    }
+
+    if let (Some(path), Some(html)) = (&config.swagger_path, config.swagger_html()) {
+        r = r.route(path, get(move || async move { Html(html) }));
+    }
+
+    r
+}
@@ -0,0 +1,498 @@
+//! Build a procedure's input from the path, query and body of a request.
+
+use std::{collections::BTreeMap, error, fmt};
+
+use http::StatusCode;
+use serde_json::{json, Map, Value};
+use specta::{
+    datatype::{DataType, List, LiteralType, PrimitiveType, StructFields},
+    TypeCollection,
+};
+
+use crate::schema::Schemas;
+
+/// Where a parameter of the input is taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterLocation {
+    Path,
+    Query,
+    Body,
+}
+
+/// A single field of a procedure's input and where it comes from in the request.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub(crate) name: String,
+    pub(crate) location: ParameterLocation,
+    pub(crate) ty: DataType,
+    pub(crate) required: bool,
+    pub(crate) docs: String,
+}
+
+impl Parameter {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn location(&self) -> ParameterLocation {
+        self.location
+    }
+
+    pub fn ty(&self) -> &DataType {
+        &self.ty
+    }
+
+    /// Path parameters are always required.
+    pub fn required(&self) -> bool {
+        self.required || self.location == ParameterLocation::Path
+    }
+
+    pub fn docs(&self) -> &str {
+        &self.docs
+    }
+}
+
+/// The input could not be built from the request.
+#[derive(Debug)]
+pub struct InputError {
+    status: StatusCode,
+    message: String,
+}
+
+impl InputError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn body_too_large(limit: usize) -> Self {
+        Self {
+            status: StatusCode::PAYLOAD_TOO_LARGE,
+            message: format!("the request body exceeds the limit of {limit} bytes"),
+        }
+    }
+
+    /// The status code and JSON body to respond with. The body is in the same format as errors from [`Route::exec`](crate::Route::exec).
+    pub fn response(&self) -> (StatusCode, Value) {
+        (
+            self.status,
+            json!({
+                "~rspc": true,
+                "variant": "Deserialize",
+                "message": self.message,
+            }),
+        )
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl error::Error for InputError {}
+
+/// The body of a request.
+pub(crate) enum Body<'a> {
+    Json(&'a [u8]),
+    Form(&'a [u8]),
+}
+
+/// How to map the parts of a request into the input of a procedure.
+///
+/// The input is built by merging the body with the query parameters and then the path parameters.
+/// Path and query parameters are strings so they are coerced into the type of the parameter they are for.
+#[derive(Debug, Clone)]
+pub(crate) struct InputMapping {
+    parameters: Vec<Parameter>,
+    /// If the input is not an object it can be taken from a single path parameter (Eg. `/todos/{id}` with `u32` input).
+    /// When this is `true` that is the only parameter.
+    whole: bool,
+    /// If the input is a struct with named fields. It will default to an empty object when the request has no body.
+    object: bool,
+    /// If the input has a flattened field which isn't a struct (Eg. a map) so it can accept query parameters we don't know about.
+    open: bool,
+    input: DataType,
+}
+
+impl InputMapping {
+    pub fn new(method: &str, path: &str, input: &DataType, types: &TypeCollection) -> Self {
+        let path_params = path_params(path);
+        let default_location = match method {
+            "GET" | "DELETE" => ParameterLocation::Query,
+            _ => ParameterLocation::Body,
+        };
+
+        let mut open = false;
+        let Some(fields) = named_fields(input, types, &mut open) else {
+            let whole = match &path_params[..] {
+                [param] => Some(Parameter {
+                    name: param.to_string(),
+                    location: ParameterLocation::Path,
+                    ty: input.clone(),
+                    required: true,
+                    docs: Default::default(),
+                }),
+                _ => None,
+            };
+
+            return Self {
+                whole: whole.is_some(),
+                parameters: whole.into_iter().collect(),
+                object: false,
+                open: false,
+                input: input.clone(),
+            };
+        };
+
+        Self {
+            parameters: fields
+                .into_iter()
+                .map(|(name, ty, optional, docs)| Parameter {
+                    location: match path_params.contains(&name.as_str()) {
+                        true => ParameterLocation::Path,
+                        false => default_location,
+                    },
+                    required: !optional && !matches!(ty, DataType::Nullable(_)),
+                    name,
+                    ty,
+                    docs,
+                })
+                .collect(),
+            whole: false,
+            object: true,
+            open,
+            input: input.clone(),
+        }
+    }
+
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    /// Get the schema of the request body, if the procedure takes one.
+    pub fn body_schema(&self, schemas: &Schemas) -> Option<Value> {
+        if self.whole {
+            return None;
+        }
+
+        if self.parameters.is_empty() {
+            let schema = schemas.schema(&self.input);
+            // `()` takes no input so we don't document it.
+            return (schema != json!({ "type": "null" })).then_some(schema);
+        }
+
+        let parameters = self
+            .parameters
+            .iter()
+            .filter(|p| p.location == ParameterLocation::Body)
+            .collect::<Vec<_>>();
+        if parameters.is_empty() {
+            return None;
+        }
+
+        let properties = parameters
+            .iter()
+            .map(|p| (p.name.clone(), schemas.parameter(p)))
+            .collect::<Map<_, _>>();
+        let required = parameters
+            .iter()
+            .filter(|p| p.required)
+            .map(|p| Value::from(p.name.clone()))
+            .collect::<Vec<_>>();
+
+        Some(json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        }))
+    }
+
+    /// Build the input from the parts of the request.
+    pub fn build<'a>(
+        &self,
+        path: impl IntoIterator<Item = (&'a str, &'a str)>,
+        query: Option<&str>,
+        body: Body,
+    ) -> Result<Value, InputError> {
+        let input = match body {
+            Body::Json(body) if !body.is_empty() => serde_json::from_slice(body)
+                .map_err(|err| InputError::new(format!("invalid JSON body: {err}")))?,
+            Body::Form(body) => Value::Object(
+                self.coerce_all(form_urlencoded::parse(body).map(|(k, v)| (k.into(), v.into()))),
+            ),
+            _ if self.object => Value::Object(Map::new()),
+            _ => Value::Null,
+        };
+
+        let params = form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .map(
+                |(k, v)| match self.parameters.iter().find(|p| p.name == k) {
+                    Some(p) if p.location == ParameterLocation::Query => Ok((k, v)),
+                    None if self.open => Ok((k, v)),
+                    _ => Err(InputError::new(format!("unknown query parameter '{k}'"))),
+                },
+            )
+            .chain(
+                path.into_iter()
+                    .map(|(k, v)| Ok((k.to_string(), v.to_string()))),
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let (true, [param]) = (self.whole, &self.parameters[..]) {
+            if let Some((_, value)) = params.iter().rev().find(|(k, _)| *k == param.name) {
+                return Ok(coerce(&param.ty, value));
+            }
+        }
+
+        if params.is_empty() {
+            return Ok(input);
+        }
+
+        let mut input = match input {
+            Value::Null => Map::new(),
+            Value::Object(input) => input,
+            _ => {
+                return Err(InputError::new(
+                    "the body must be an object when using parameters",
+                ))
+            }
+        };
+        // Path parameters are last so they take precedence.
+        input.extend(self.coerce_all(params));
+
+        Ok(Value::Object(input))
+    }
+
+    // Coerce all of the string values into their parameter's type.
+    // Parameters which are lists can be repeated.
+    fn coerce_all(&self, values: impl IntoIterator<Item = (String, String)>) -> Map<String, Value> {
+        let mut grouped = BTreeMap::<String, Vec<String>>::new();
+        let mut order = Vec::new();
+        for (k, v) in values {
+            let entry = grouped.entry(k.clone()).or_default();
+            if entry.is_empty() {
+                order.push(k);
+            }
+            entry.push(v);
+        }
+
+        order
+            .into_iter()
+            .map(|k| {
+                let mut values = grouped.remove(&k).unwrap_or_default();
+                let ty = self.parameters.iter().find(|p| p.name == k).map(|p| &p.ty);
+                let value = match ty.and_then(list) {
+                    Some(l) => Value::Array(values.iter().map(|v| coerce(l.ty(), v)).collect()),
+                    None => {
+                        let v = values.pop().unwrap_or_default();
+                        ty.map(|ty| coerce(ty, &v)).unwrap_or(Value::String(v))
+                    }
+                };
+
+                (k, value)
+            })
+            .collect()
+    }
+}
+
+/// Get the names of the parameters in a path template (Eg. `id` in `/todos/{id}`).
+pub(crate) fn path_params(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter_map(|s| s.strip_prefix('{')?.strip_suffix('}'))
+        .map(|s| s.trim_start_matches('*'))
+        .collect()
+}
+
+// Resolve the fields of a struct with named fields, including the fields of any flattened structs.
+//
+// `open` is set if a flattened field isn't a struct so it's fields can't be known.
+fn named_fields(
+    dt: &DataType,
+    types: &TypeCollection,
+    open: &mut bool,
+) -> Option<Vec<(String, DataType, bool, String)>> {
+    match dt {
+        DataType::Reference(r) => named_fields(&types.get(r.sid())?.inner, types, open),
+        DataType::Struct(s) => match s.fields() {
+            StructFields::Named(fields) => {
+                let mut result = Vec::new();
+                for (name, f) in fields.fields() {
+                    // `None` means the field was skipped
+                    let Some(ty) = f.ty() else {
+                        continue;
+                    };
+
+                    if f.flatten() {
+                        match named_fields(ty, types, open) {
+                            Some(fields) => result.extend(fields),
+                            None => *open = true,
+                        }
+                        continue;
+                    }
+
+                    result.push((
+                        name.to_string(),
+                        ty.clone(),
+                        f.optional(),
+                        f.docs().to_string(),
+                    ));
+                }
+                Some(result)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Get the list type of a parameter which can be repeated.
+fn list(dt: &DataType) -> Option<&List> {
+    match dt {
+        DataType::List(l) => Some(l),
+        DataType::Nullable(ty) => list(ty),
+        _ => None,
+    }
+}
+
+// Convert a string value from the path, query or a form into the type expected by the input.
+fn coerce(dt: &DataType, value: &str) -> Value {
+    match dt {
+        DataType::Primitive(p) => match p {
+            PrimitiveType::String | PrimitiveType::char => Value::String(value.into()),
+            PrimitiveType::bool => match value {
+                "true" | "1" | "on" => true.into(),
+                "false" | "0" | "off" => false.into(),
+                // This will fail to deserialize with a useful error.
+                _ => Value::String(value.into()),
+            },
+            _ => serde_json::from_str::<serde_json::Number>(value)
+                .map(Value::Number)
+                .unwrap_or_else(|_| Value::String(value.into())),
+        },
+        DataType::Literal(LiteralType::String(_) | LiteralType::char(_)) => {
+            Value::String(value.into())
+        }
+        DataType::Nullable(_) if value.is_empty() || value == "null" => Value::Null,
+        DataType::Nullable(ty) => coerce(ty, value),
+        // Complex values can be provided as JSON.
+        _ => serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.into())),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use specta::Type;
+
+    use super::*;
+
+    fn mapping<T: Type>(method: &str, path: &str) -> InputMapping {
+        let mut types = TypeCollection::default();
+        let input = T::reference(&mut types, &[]).inner;
+        InputMapping::new(method, path, &input, &types)
+    }
+
+    #[derive(Type)]
+    #[allow(dead_code)]
+    struct ListTodos {
+        page: Option<u32>,
+        done: Option<bool>,
+        #[specta(optional)]
+        tags: Option<Vec<String>>,
+    }
+
+    #[derive(Type)]
+    #[allow(dead_code)]
+    struct Pagination {
+        page: u32,
+        limit: u32,
+    }
+
+    #[derive(Type)]
+    #[allow(dead_code)]
+    struct Search {
+        query: String,
+        #[serde(flatten)]
+        pagination: Pagination,
+    }
+
+    #[derive(Type)]
+    #[allow(dead_code)]
+    struct UpdateTodo {
+        id: u32,
+        title: String,
+    }
+
+    #[test]
+    fn empty_request() {
+        let input = mapping::<ListTodos>("GET", "/todos")
+            .build([], None, Body::Json(&[]))
+            .unwrap();
+        assert_eq!(input, json!({}));
+
+        let input = mapping::<()>("GET", "/health")
+            .build([], None, Body::Json(&[]))
+            .unwrap();
+        assert_eq!(input, Value::Null);
+    }
+
+    #[test]
+    fn query_coercion() {
+        let input = mapping::<ListTodos>("GET", "/todos")
+            .build([], Some("page=2&done=true&tags=a&tags=b"), Body::Json(&[]))
+            .unwrap();
+        assert_eq!(
+            input,
+            json!({ "page": 2, "done": true, "tags": ["a", "b"] })
+        );
+
+        let input = mapping::<ListTodos>("GET", "/todos")
+            .build([], Some("page="), Body::Json(&[]))
+            .unwrap();
+        assert_eq!(input, json!({ "page": null }));
+    }
+
+    #[test]
+    fn unknown_query_parameters() {
+        let err = mapping::<ListTodos>("GET", "/todos")
+            .build([], Some("page=1&admin=true"), Body::Json(&[]))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown query parameter 'admin'");
+
+        // Body fields can't be overridden from the query
+        let err = mapping::<UpdateTodo>("POST", "/todos/{id}")
+            .build(
+                [("id", "1")],
+                Some("title=abc"),
+                Body::Json(br#"{"title":"a"}"#),
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "unknown query parameter 'title'");
+    }
+
+    #[test]
+    fn flattened_fields() {
+        let input = mapping::<Search>("GET", "/search")
+            .build([], Some("query=abc&page=2&limit=10"), Body::Json(&[]))
+            .unwrap();
+        assert_eq!(input, json!({ "query": "abc", "page": 2, "limit": 10 }));
+    }
+
+    #[test]
+    fn path_parameters() {
+        let input = mapping::<UpdateTodo>("PUT", "/todos/{id}")
+            .build([("id", "5")], None, Body::Json(br#"{"id":1,"title":"a"}"#))
+            .unwrap();
+        assert_eq!(input, json!({ "id": 5, "title": "a" }));
+
+        let input = mapping::<u32>("GET", "/todos/{id}")
+            .build([("id", "5")], None, Body::Json(&[]))
+            .unwrap();
+        assert_eq!(input, json!(5));
+    }
+}
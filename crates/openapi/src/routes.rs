@@ -0,0 +1,659 @@
+//! A framework agnostic representation of the endpoints registered with [`OpenAPI`](crate::OpenAPI).
+
+use std::{borrow::Cow, collections::BTreeMap};
+
+use http::{header, request::Parts, Method, StatusCode};
+use percent_encoding::percent_decode_str;
+use rspc::{ProcedureError, ProcedureType, Procedures, Types};
+use rspc_procedure::Procedure;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    params::{Body, InputMapping},
+    schema::Schemas,
+    Config, InputError, OpenAPIState, Parameter, ParameterLocation, SecurityScheme,
+};
+
+// The name and decoded value of each path parameter.
+type PathParams = Vec<(String, String)>;
+
+const RSPC_ERROR: &str = "RspcError";
+const RSPC_ERROR_REF: &str = "#/components/schemas/RspcError";
+
+/// A single procedure exposed as a HTTP endpoint.
+pub struct Route<TCtx> {
+    method: Method,
+    path: Cow<'static, str>,
+    key: String,
+    procedure: Procedure<TCtx>,
+    ty: ProcedureType,
+    mapping: InputMapping,
+    security: Vec<SecurityScheme>,
+}
+
+impl<TCtx> Clone for Route<TCtx> {
+    fn clone(&self) -> Self {
+        Self {
+            method: self.method.clone(),
+            path: self.path.clone(),
+            key: self.key.clone(),
+            procedure: self.procedure.clone(),
+            ty: self.ty.clone(),
+            mapping: self.mapping.clone(),
+            security: self.security.clone(),
+        }
+    }
+}
+
+impl<TCtx> Route<TCtx> {
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The path template of the route (Eg. `/todos/{id}`).
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The key of the procedure this route executes.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// How the procedure's input is mapped from the request.
+    pub fn parameters(&self) -> &[Parameter] {
+        self.mapping.parameters()
+    }
+
+    /// The schemes which are accepted to authenticate with this route.
+    ///
+    /// If this is empty the route doesn't require authentication.
+    pub fn security(&self) -> &[SecurityScheme] {
+        &self.security
+    }
+
+    pub fn procedure(&self) -> &Procedure<TCtx> {
+        &self.procedure
+    }
+
+    /// Match a request path against the path template of this route.
+    ///
+    /// This returns the decoded path parameters if the path matches.
+    /// This is useful for frameworks without a router, otherwise prefer using your router's path parameters.
+    pub fn match_path(&self, path: &str) -> Option<PathParams> {
+        let mut params = Vec::new();
+        let mut segments = path.trim_start_matches('/').split('/');
+        for template in self.path.trim_start_matches('/').split('/') {
+            let param = template.strip_prefix('{').and_then(|s| s.strip_suffix('}'));
+
+            match param {
+                // A wildcard takes the rest of the path.
+                Some(param) if param.starts_with('*') => {
+                    let rest = segments.by_ref().collect::<Vec<_>>().join("/");
+                    params.push((param[1..].to_string(), decode(&rest)));
+                }
+                Some(param) => params.push((param.to_string(), decode(segments.next()?))),
+                None if segments.next()? == template => {}
+                None => return None,
+            }
+        }
+
+        segments.next().is_none().then_some(params)
+    }
+
+    /// Build the procedure's input from the parts of a HTTP request.
+    ///
+    /// The body is parsed as `application/x-www-form-urlencoded` if the request has that `Content-Type`, otherwise it's parsed as JSON.
+    pub fn input<'a>(
+        &self,
+        parts: &Parts,
+        path_params: impl IntoIterator<Item = (&'a str, &'a str)>,
+        body: &[u8],
+    ) -> Result<Value, InputError> {
+        let is_form = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+
+        self.mapping.build(
+            path_params,
+            parts.uri.query(),
+            match is_form {
+                true => Body::Form(body),
+                false => Body::Json(body),
+            },
+        )
+    }
+
+    /// Execute the procedure returning the status code and JSON body of the response.
+    pub async fn exec(&self, ctx: TCtx, input: Value) -> (StatusCode, Value) {
+        let mut stream = self.procedure.exec_with_deserializer(ctx, input);
+
+        // TODO: Support for streaming
+        match stream.next().await {
+            Some(Ok(value)) => match value.as_serialize().map(serde_json::to_value) {
+                Some(Ok(value)) => (StatusCode::OK, value),
+                Some(Err(err)) => serializer_error(err.to_string()),
+                None => serializer_error("procedure returned a non-serializable value".into()),
+            },
+            Some(Err(err)) => {
+                let status = match &err {
+                    ProcedureError::NotFound => StatusCode::NOT_FOUND,
+                    ProcedureError::Deserialize(_) | ProcedureError::Downcast(_) => {
+                        StatusCode::BAD_REQUEST
+                    }
+                    ProcedureError::Resolver(err) => StatusCode::from_u16(err.status())
+                        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                    ProcedureError::Unwind(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+
+                match serde_json::to_value(&err) {
+                    Ok(value) => (status, value),
+                    Err(err) => serializer_error(err.to_string()),
+                }
+            }
+            None => (StatusCode::OK, Value::Null),
+        }
+    }
+}
+
+/// All of the procedures registered with [`OpenAPI`](crate::OpenAPI) and the OpenAPI document describing them.
+///
+/// This can be used to integrate with any web framework. Refer to [`mount`](crate::mount) for Axum.
+pub struct RouteTable<TCtx> {
+    routes: Vec<Route<TCtx>>,
+    paths: Map<String, Value>,
+    components: Map<String, Value>,
+    security_schemes: Map<String, Value>,
+}
+
+impl<TCtx> Clone for RouteTable<TCtx> {
+    fn clone(&self) -> Self {
+        Self {
+            routes: self.routes.clone(),
+            paths: self.paths.clone(),
+            components: self.components.clone(),
+            security_schemes: self.security_schemes.clone(),
+        }
+    }
+}
+
+impl<TCtx> RouteTable<TCtx> {
+    /// # Panics
+    ///
+    /// Panics if two different [`SecurityScheme`]s have the same name.
+    pub fn new(procedures: &Procedures<TCtx>, types: &Types) -> Self {
+        let procedure_types = types.procedures();
+        let state = procedures.state().get::<OpenAPIState>();
+        let mut routes = state
+            .map(|s| &s.endpoints)
+            .into_iter()
+            .flatten()
+            .map(|((method, path), key)| {
+                let procedure = procedures
+                    .get(key.as_str())
+                    .expect("unreachable: a procedure was registered that doesn't exist")
+                    .clone();
+                let ty = (*procedure_types
+                    .get(key)
+                    .expect("unreachable: a procedure was registered that doesn't exist"))
+                .clone();
+
+                Route {
+                    method: Method::from_bytes(method.as_bytes())
+                        .expect("unreachable: `OpenAPI` only uses valid methods"),
+                    path: path.clone(),
+                    key: key.clone(),
+                    procedure,
+                    mapping: InputMapping::new(method, path, ty.input(), types.types()),
+                    ty,
+                    security: state
+                        .and_then(|s| s.security.get(key))
+                        .cloned()
+                        .unwrap_or_default(),
+                }
+            })
+            .collect::<Vec<_>>();
+        routes.sort_by(|a, b| (&a.path, a.method.as_str()).cmp(&(&b.path, b.method.as_str())));
+
+        let schemas = Schemas::new(types);
+        let mut paths = BTreeMap::<_, Map<_, _>>::new();
+        for route in &routes {
+            paths.entry(route.path.to_string()).or_default().insert(
+                route.method.as_str().to_lowercase(),
+                operation(route, &schemas),
+            );
+        }
+
+        let mut components = schemas.components();
+        components.insert(
+            RSPC_ERROR.into(),
+            json!({
+                "description": "An error produced by rspc instead of the procedure.",
+                "type": "object",
+                "properties": {
+                    "~rspc": { "const": true },
+                    "variant": { "type": "string" },
+                    "message": { "type": "string" },
+                },
+                "required": ["~rspc", "variant", "message"],
+            }),
+        );
+
+        // Schemes are referenced by their name so two different schemes can't share one.
+        let mut security_schemes = Map::new();
+        for scheme in routes.iter().flat_map(|r| &r.security) {
+            let schema = scheme.schema();
+            match security_schemes.get(scheme.key()) {
+                #[allow(clippy::panic)]
+                Some(existing) if *existing != schema => panic!(
+                    "rspc-openapi: multiple security schemes are named '{}'. Use `SecurityScheme::name` to give them unique names.",
+                    scheme.key()
+                ),
+                Some(_) => {}
+                None => {
+                    security_schemes.insert(scheme.key().to_string(), schema);
+                }
+            }
+        }
+
+        Self {
+            routes,
+            paths: paths.into_iter().map(|(k, v)| (k, v.into())).collect(),
+            components,
+            security_schemes,
+        }
+    }
+
+    pub fn routes(&self) -> &[Route<TCtx>] {
+        &self.routes
+    }
+
+    /// Find the route for a request, along with it's path parameters.
+    pub fn find(&self, method: &Method, path: &str) -> Option<(&Route<TCtx>, PathParams)> {
+        self.routes
+            .iter()
+            .filter(|r| r.method == method)
+            .find_map(|r| Some((r, r.match_path(path)?)))
+    }
+
+    /// Get the OpenAPI document.
+    pub fn document(&self, config: &Config) -> Value {
+        let mut info = json!({
+            "title": config.title,
+            "version": config.version,
+        });
+        if let Some(description) = &config.description {
+            info["description"] = description.to_string().into();
+        }
+
+        let mut document = json!({
+            "openapi": "3.1.0",
+            "info": info,
+            "paths": self.paths,
+            "components": {
+                "schemas": self.components,
+            },
+        });
+        if !self.security_schemes.is_empty() {
+            document["components"]["securitySchemes"] = self.security_schemes.clone().into();
+        }
+        document
+    }
+}
+
+// Generate the OpenAPI operation for a route.
+fn operation<TCtx>(route: &Route<TCtx>, schemas: &Schemas) -> Value {
+    let mut operation = json!({
+        "operationId": route.key,
+        "responses": {
+            "200": {
+                "description": "Successful operation",
+                "content": {
+                    "application/json": {
+                        "schema": schemas.schema(route.ty.output()),
+                    },
+                },
+            },
+            "400": error_response("Invalid input", json!({ "$ref": RSPC_ERROR_REF })),
+            "500": error_response(
+                "The procedure returned an error",
+                json!({ "anyOf": [schemas.schema(route.ty.error()), { "$ref": RSPC_ERROR_REF }] }),
+            ),
+        },
+    });
+
+    let parameters = route
+        .parameters()
+        .iter()
+        .filter(|p| p.location() != ParameterLocation::Body)
+        .map(|p| {
+            json!({
+                "name": p.name(),
+                "in": match p.location() {
+                    ParameterLocation::Path => "path",
+                    _ => "query",
+                },
+                "required": p.required(),
+                "schema": schemas.parameter(p),
+            })
+        })
+        .collect::<Vec<_>>();
+    if !parameters.is_empty() {
+        operation["parameters"] = parameters.into();
+    }
+
+    if !route.security.is_empty() {
+        // Each requirement is an alternative so any of the schemes can be used.
+        operation["security"] = route
+            .security
+            .iter()
+            .map(|s| json!({ s.key(): [] }))
+            .collect::<Vec<_>>()
+            .into();
+
+        let error =
+            json!({ "anyOf": [schemas.schema(route.ty.error()), { "$ref": RSPC_ERROR_REF }] });
+        operation["responses"]["401"] = error_response("Authentication is required", error.clone());
+        operation["responses"]["403"] =
+            error_response("Not permitted to access this resource", error);
+    }
+
+    if let Some(schema) = route.mapping.body_schema(schemas) {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": {
+                "application/json": {
+                    "schema": schema,
+                },
+                "application/x-www-form-urlencoded": {
+                    "schema": schema,
+                },
+            },
+        });
+    }
+
+    operation
+}
+
+fn error_response(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": {
+            "application/json": {
+                "schema": schema,
+            },
+        },
+    })
+}
+
+fn serializer_error(message: String) -> (StatusCode, Value) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        json!({
+            "~rspc": true,
+            "variant": "Serializer",
+            "message": message,
+        }),
+    )
+}
+
+fn decode(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().to_string()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use rspc::{Procedure, ResolverError, Router};
+    use serde::{Deserialize, Serialize};
+    use specta::Type;
+
+    use super::*;
+    use crate::OpenAPI;
+
+    #[derive(Debug, Serialize, Type)]
+    struct Error(String);
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            ResolverError::new(self.0, None::<std::io::Error>)
+                .with_status(404)
+                .into()
+        }
+    }
+
+    #[derive(Deserialize, Type)]
+    struct UpdateTodo {
+        id: u32,
+        title: String,
+    }
+
+    #[derive(Deserialize, Type)]
+    #[allow(dead_code)]
+    struct ListTodos {
+        done: Option<bool>,
+    }
+
+    fn table() -> RouteTable<()> {
+        let router = <Router>::new()
+            .procedure(
+                "todos.list",
+                Procedure::builder::<Error>()
+                    .with(OpenAPI::get("/todos").build())
+                    .query(|_, _: ListTodos| async { Ok(vec!["a".to_string()]) }),
+            )
+            .procedure(
+                "todos.update",
+                Procedure::builder::<Error>()
+                    .with(OpenAPI::put("/todos/{id}").build())
+                    .mutation(|_, input: UpdateTodo| async move {
+                        match input.id {
+                            0 => Err(Error("not found".into())),
+                            id => Ok(format!("{id}: {}", input.title)),
+                        }
+                    }),
+            )
+            .procedure(
+                "files.get",
+                Procedure::builder::<Error>()
+                    .with(OpenAPI::get("/files/{*path}").build())
+                    .query(|_, _: ()| async { Ok(()) }),
+            )
+            // Not exposed over OpenAPI.
+            .procedure(
+                "internal",
+                Procedure::builder::<Error>().query(|_, _: ()| async { Ok(()) }),
+            );
+
+        let (procedures, types) = router.build().unwrap();
+        RouteTable::new(&procedures, &types)
+    }
+
+    fn parts(uri: &str, content_type: Option<&str>) -> Parts {
+        let mut req = http::Request::builder().uri(uri);
+        if let Some(content_type) = content_type {
+            req = req.header(header::CONTENT_TYPE, content_type);
+        }
+        req.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn routes() {
+        let table = table();
+        let routes = table
+            .routes()
+            .iter()
+            .map(|r| (r.method().as_str(), r.path(), r.key()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            routes,
+            [
+                ("GET", "/files/{*path}", "files.get"),
+                ("GET", "/todos", "todos.list"),
+                ("PUT", "/todos/{id}", "todos.update"),
+            ]
+        );
+
+        let update = &table.routes()[2];
+        let params = update
+            .parameters()
+            .iter()
+            .map(|p| (p.name(), p.location()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            params,
+            [
+                ("id", ParameterLocation::Path),
+                ("title", ParameterLocation::Body)
+            ]
+        );
+    }
+
+    #[test]
+    fn match_path() {
+        let table = table();
+        let update = &table.routes()[2];
+        assert_eq!(
+            update.match_path("/todos/a%20b"),
+            Some(vec![("id".into(), "a b".into())])
+        );
+        assert_eq!(update.match_path("/todos"), None);
+        assert_eq!(update.match_path("/todos/1/2"), None);
+        assert_eq!(update.match_path("/users/1"), None);
+
+        let files = &table.routes()[0];
+        assert_eq!(
+            files.match_path("/files/a/b.txt"),
+            Some(vec![("path".into(), "a/b.txt".into())])
+        );
+    }
+
+    #[test]
+    fn find() {
+        let table = table();
+        let (route, params) = table.find(&Method::PUT, "/todos/5").unwrap();
+        assert_eq!(route.key(), "todos.update");
+        assert_eq!(params, [("id".to_string(), "5".to_string())]);
+
+        assert!(table.find(&Method::GET, "/todos/5").is_none());
+        assert_eq!(
+            table.find(&Method::GET, "/todos").unwrap().0.key(),
+            "todos.list"
+        );
+    }
+
+    #[test]
+    fn input() {
+        let table = table();
+        let update = &table.routes()[2];
+
+        let input = update
+            .input(
+                &parts("/todos/5", None),
+                [("id", "5")],
+                br#"{"title":"hello"}"#,
+            )
+            .unwrap();
+        assert_eq!(input, json!({ "id": 5, "title": "hello" }));
+
+        let input = update
+            .input(
+                &parts("/todos/5", Some("application/x-www-form-urlencoded")),
+                [("id", "5")],
+                b"title=hello+world",
+            )
+            .unwrap();
+        assert_eq!(input, json!({ "id": 5, "title": "hello world" }));
+
+        let list = &table.routes()[1];
+        let input = list
+            .input(&parts("/todos?done=true", None), [], b"")
+            .unwrap();
+        assert_eq!(input, json!({ "done": true }));
+
+        let err = list
+            .input(&parts("/todos?unknown=1", None), [], b"")
+            .unwrap_err();
+        assert_eq!(err.response().0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn exec() {
+        let table = table();
+        let update = &table.routes()[2];
+
+        assert_eq!(
+            update.exec((), json!({ "id": 1, "title": "a" })).await,
+            (StatusCode::OK, json!("1: a"))
+        );
+
+        // The status of the resolver's error is used.
+        let (status, body) = update.exec((), json!({ "id": 0, "title": "a" })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, json!("not found"));
+
+        let (status, body) = update.exec((), json!({ "id": "a" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["~rspc"], true);
+        assert_eq!(body["variant"], "Deserialize");
+    }
+
+    #[test]
+    fn document() {
+        let document = table().document(
+            &Config::new()
+                .title("Todos")
+                .version("1.0.0")
+                .description("A todo list"),
+        );
+
+        assert_eq!(document["openapi"], "3.1.0");
+        assert_eq!(
+            document["info"],
+            json!({ "title": "Todos", "version": "1.0.0", "description": "A todo list" })
+        );
+        assert_eq!(
+            document["paths"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            ["/files/{*path}", "/todos", "/todos/{id}"]
+        );
+
+        let update = &document["paths"]["/todos/{id}"]["put"];
+        assert_eq!(update["operationId"], "todos.update");
+        assert_eq!(update["parameters"][0]["name"], "id");
+        assert_eq!(update["parameters"][0]["in"], "path");
+        assert_eq!(update["parameters"][0]["required"], true);
+        assert!(update["requestBody"]["content"]["application/json"].is_object());
+        assert!(update["responses"]["500"].is_object());
+        assert!(document["components"]["schemas"][RSPC_ERROR].is_object());
+        // Nothing requires authentication.
+        assert!(document["components"].get("securitySchemes").is_none());
+        assert!(update.get("security").is_none());
+    }
+
+    #[test]
+    fn swagger() {
+        let html = Config::new().swagger_html().unwrap();
+        assert!(html.contains("\"/api/openapi.json\""));
+
+        let html = Config::new()
+            .document_url("/nested/api/openapi.json")
+            .swagger_html()
+            .unwrap();
+        assert!(html.contains("\"/nested/api/openapi.json\""));
+
+        assert!(Config::new().without_swagger().swagger_html().is_none());
+        // There is no document to load.
+        assert!(Config::new().without_document().swagger_html().is_none());
+        assert!(Config::new()
+            .without_document()
+            .document_url("https://example.com/openapi.json")
+            .swagger_html()
+            .is_some());
+    }
+}
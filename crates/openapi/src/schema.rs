@@ -0,0 +1,413 @@
+//! Convert Specta types into JSON Schema for the OpenAPI document.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use rspc::Types;
+use serde_json::{json, Map, Value};
+use specta::{
+    datatype::{
+        DataType, DeprecatedType, EnumRepr, EnumVariants, Field, LiteralType, PrimitiveType,
+        StructFields,
+    },
+    SpectaID, TypeCollection,
+};
+
+use crate::Parameter;
+
+// A map of generic name to it's converted schema.
+type Generics = HashMap<String, Value>;
+
+pub(crate) struct Schemas<'a> {
+    types: &'a TypeCollection,
+    // The generic types which are currently being inlined. Used to stop recursive types from overflowing the stack.
+    inlining: RefCell<Vec<SpectaID>>,
+}
+
+impl<'a> Schemas<'a> {
+    pub fn new(types: &'a Types) -> Self {
+        Self {
+            types: types.types(),
+            inlining: Default::default(),
+        }
+    }
+
+    /// Get the schema for a type.
+    ///
+    /// Named types are referenced from `components/schemas` unless they are generic in which case they are inlined.
+    pub fn schema(&self, dt: &DataType) -> Value {
+        self.datatype(dt, &Default::default())
+    }
+
+    /// Get the schema for a parameter of the input.
+    pub fn parameter(&self, parameter: &Parameter) -> Value {
+        let mut schema = self.schema(parameter.ty());
+        with_docs(&mut schema, parameter.docs(), None);
+        schema
+    }
+
+    /// Get the `components/schemas` for all of the non-generic named types.
+    pub fn components(&self) -> Map<String, Value> {
+        self.types
+            .into_iter()
+            .filter(|(_, ndt)| ndt.inner.generics().is_none_or(Vec::is_empty))
+            .map(|(_, ndt)| {
+                let mut schema = self.datatype(&ndt.inner, &Default::default());
+                with_docs(&mut schema, ndt.docs(), ndt.deprecated());
+                (ndt.name().to_string(), schema)
+            })
+            .collect()
+    }
+
+    fn datatype(&self, dt: &DataType, generics: &Generics) -> Value {
+        match dt {
+            DataType::Any | DataType::Unknown => json!({}),
+            DataType::Primitive(p) => primitive(p),
+            DataType::Literal(l) => literal(l),
+            DataType::List(l) => {
+                let mut schema = json!({
+                    "type": "array",
+                    "items": self.datatype(l.ty(), generics),
+                });
+                if let Some(length) = l.length() {
+                    schema["minItems"] = length.into();
+                    schema["maxItems"] = length.into();
+                }
+                if l.unique() {
+                    schema["uniqueItems"] = true.into();
+                }
+                schema
+            }
+            DataType::Map(m) => json!({
+                "type": "object",
+                "additionalProperties": self.datatype(m.value_ty(), generics),
+            }),
+            DataType::Nullable(t) => json!({
+                "anyOf": [self.datatype(t, generics), { "type": "null" }],
+            }),
+            DataType::Struct(s) => {
+                let mut schema = match s.fields() {
+                    StructFields::Unit => json!({ "type": "null" }),
+                    StructFields::Unnamed(fields) => self.unnamed(fields.fields(), generics),
+                    StructFields::Named(fields) => self.named(fields.fields(), generics),
+                };
+                if let Some(tag) = s.tag() {
+                    add_tag(&mut schema, tag, s.name());
+                }
+                schema
+            }
+            DataType::Enum(e) => {
+                let variants = e
+                    .variants()
+                    .iter()
+                    .filter(|(_, v)| !v.skip())
+                    .map(|(name, v)| {
+                        let inner = match v.inner() {
+                            EnumVariants::Unit => None,
+                            EnumVariants::Named(fields) => {
+                                Some(self.named(fields.fields(), generics))
+                            }
+                            EnumVariants::Unnamed(fields) => {
+                                Some(self.unnamed(fields.fields(), generics))
+                            }
+                        };
+
+                        let mut schema = match (e.repr(), inner) {
+                            (EnumRepr::Untagged, inner) => {
+                                inner.unwrap_or_else(|| json!({ "type": "null" }))
+                            }
+                            (EnumRepr::External, None) => json!({ "const": name }),
+                            (EnumRepr::External, Some(inner)) => {
+                                object([(name.to_string(), inner)])
+                            }
+                            (EnumRepr::Internal { tag }, None) => {
+                                object([(tag.to_string(), json!({ "const": name }))])
+                            }
+                            (EnumRepr::Internal { tag }, Some(mut inner)) => {
+                                add_tag(&mut inner, tag, name);
+                                inner
+                            }
+                            (EnumRepr::Adjacent { tag, content }, inner) => {
+                                let mut fields = vec![(tag.to_string(), json!({ "const": name }))];
+                                if let Some(inner) = inner {
+                                    fields.push((content.to_string(), inner));
+                                }
+                                object(fields)
+                            }
+                        };
+                        with_docs(&mut schema, v.docs(), v.deprecated());
+                        schema
+                    })
+                    .collect::<Vec<_>>();
+
+                json!({ "anyOf": variants })
+            }
+            DataType::Tuple(t) => self.tuple(t.elements().iter(), generics),
+            DataType::Reference(r) => {
+                if r.generics().is_empty() {
+                    return json!({ "$ref": format!("#/components/schemas/{}", r.name()) });
+                }
+
+                // Generic types can't be represented in JSON Schema so we inline them.
+                // A recursive type would be inlined forever so it's left untyped.
+                let Some(ndt) = self.types.get(r.sid()) else {
+                    return json!({});
+                };
+                if self.inlining.borrow().contains(&r.sid()) {
+                    return json!({});
+                }
+                let generics = r
+                    .generics()
+                    .iter()
+                    .map(|(g, dt)| (g.to_string(), self.datatype(dt, generics)))
+                    .collect();
+
+                self.inlining.borrow_mut().push(r.sid());
+                let schema = self.datatype(&ndt.inner, &generics);
+                self.inlining.borrow_mut().pop();
+                schema
+            }
+            DataType::Generic(g) => generics
+                .get(&g.to_string())
+                .cloned()
+                .unwrap_or_else(|| json!({})),
+        }
+    }
+
+    fn named(&self, fields: &[(impl AsRef<str>, Field)], generics: &Generics) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        let mut flattened = Vec::new();
+        for (name, field) in fields {
+            // `None` means the field was skipped
+            let Some(ty) = field.ty() else {
+                continue;
+            };
+
+            let mut schema = self.datatype(ty, generics);
+            if field.flatten() {
+                flattened.push(schema);
+                continue;
+            }
+
+            with_docs(&mut schema, field.docs(), field.deprecated());
+            if !field.optional() {
+                required.push(Value::from(name.as_ref()));
+            }
+            properties.insert(name.as_ref().to_string(), schema);
+        }
+
+        let mut schema = json!({
+            "type": "object",
+            "properties": properties,
+        });
+        if !required.is_empty() {
+            schema["required"] = required.into();
+        }
+        if !flattened.is_empty() {
+            flattened.insert(0, schema);
+            schema = json!({ "allOf": flattened });
+        }
+        schema
+    }
+
+    fn unnamed(&self, fields: &[Field], generics: &Generics) -> Value {
+        let fields = fields.iter().filter_map(|f| f.ty()).collect::<Vec<_>>();
+        match &fields[..] {
+            // A newtype is serialized as it's inner value
+            [ty] => self.datatype(ty, generics),
+            fields => self.tuple(fields.iter().copied(), generics),
+        }
+    }
+
+    fn tuple<'b>(
+        &self,
+        elements: impl ExactSizeIterator<Item = &'b DataType>,
+        generics: &Generics,
+    ) -> Value {
+        let len = elements.len();
+        if len == 0 {
+            return json!({ "type": "null" });
+        }
+
+        json!({
+            "type": "array",
+            "prefixItems": elements.map(|dt| self.datatype(dt, generics)).collect::<Vec<_>>(),
+            "minItems": len,
+            "maxItems": len,
+        })
+    }
+}
+
+fn primitive(p: &PrimitiveType) -> Value {
+    match p {
+        PrimitiveType::i8 | PrimitiveType::i16 | PrimitiveType::i32 => {
+            json!({ "type": "integer", "format": "int32" })
+        }
+        PrimitiveType::i64 | PrimitiveType::i128 | PrimitiveType::isize => {
+            json!({ "type": "integer", "format": "int64" })
+        }
+        PrimitiveType::u8 | PrimitiveType::u16 | PrimitiveType::u32 => {
+            json!({ "type": "integer", "format": "int32", "minimum": 0 })
+        }
+        PrimitiveType::u64 | PrimitiveType::u128 | PrimitiveType::usize => {
+            json!({ "type": "integer", "format": "int64", "minimum": 0 })
+        }
+        PrimitiveType::f32 => json!({ "type": "number", "format": "float" }),
+        PrimitiveType::f64 => json!({ "type": "number", "format": "double" }),
+        PrimitiveType::bool => json!({ "type": "boolean" }),
+        PrimitiveType::char => json!({ "type": "string", "minLength": 1, "maxLength": 1 }),
+        PrimitiveType::String => json!({ "type": "string" }),
+    }
+}
+
+fn literal(l: &LiteralType) -> Value {
+    let value = match l {
+        LiteralType::i8(v) => json!(v),
+        LiteralType::i16(v) => json!(v),
+        LiteralType::i32(v) => json!(v),
+        LiteralType::u8(v) => json!(v),
+        LiteralType::u16(v) => json!(v),
+        LiteralType::u32(v) => json!(v),
+        LiteralType::f32(v) => json!(v),
+        LiteralType::f64(v) => json!(v),
+        LiteralType::bool(v) => json!(v),
+        LiteralType::String(v) => json!(v),
+        LiteralType::char(v) => json!(v),
+        LiteralType::None => return json!({ "type": "null" }),
+        // `LiteralType` is `#[non_exhaustive]`
+        _ => return json!({}),
+    };
+
+    json!({ "const": value })
+}
+
+fn object(fields: impl IntoIterator<Item = (String, Value)>) -> Value {
+    let properties = fields.into_iter().collect::<Map<_, _>>();
+    let required = properties.keys().cloned().collect::<Vec<_>>();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+// Add the tag of an internally tagged enum or `#[serde(tag = "...")]` struct to the schema.
+fn add_tag(schema: &mut Value, tag: &str, name: &str) {
+    let tag_schema = object([(tag.to_string(), json!({ "const": name }))]);
+    match schema.get("properties").is_some() {
+        true => {
+            schema["properties"][tag] = json!({ "const": name });
+            match schema.get_mut("required").and_then(Value::as_array_mut) {
+                Some(required) => required.insert(0, tag.into()),
+                None => schema["required"] = json!([tag]),
+            }
+        }
+        false => *schema = json!({ "allOf": [tag_schema, schema.take()] }),
+    }
+}
+
+fn with_docs(schema: &mut Value, docs: &str, deprecated: Option<&DeprecatedType>) {
+    // `$ref` can't have siblings in OpenAPI 3.0 tooling so we wrap it.
+    if schema.get("$ref").is_some() && (!docs.is_empty() || deprecated.is_some()) {
+        *schema = json!({ "allOf": [schema.take()] });
+    }
+
+    if !docs.is_empty() {
+        schema["description"] = docs.trim().into();
+    }
+    if deprecated.is_some() {
+        schema["deprecated"] = true.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specta::Type;
+
+    use super::*;
+
+    fn schema<T: Type>() -> (Value, Map<String, Value>) {
+        let mut types = TypeCollection::default();
+        let dt = T::reference(&mut types, &[]).inner;
+        let schemas = Schemas {
+            types: &types,
+            inlining: Default::default(),
+        };
+        (schemas.schema(&dt), schemas.components())
+    }
+
+    #[derive(Type)]
+    #[allow(dead_code)]
+    struct Todo {
+        id: u32,
+        title: String,
+        #[specta(optional)]
+        description: Option<String>,
+    }
+
+    #[derive(Type)]
+    #[allow(dead_code)]
+    struct Page<T> {
+        items: Vec<T>,
+    }
+
+    #[derive(Type)]
+    #[allow(dead_code)]
+    struct Tree<T> {
+        value: T,
+        children: Vec<Tree<T>>,
+    }
+
+    #[test]
+    fn named_types_are_referenced() {
+        let (schema, components) = schema::<Todo>();
+        assert_eq!(schema, json!({ "$ref": "#/components/schemas/Todo" }));
+        assert_eq!(
+            components["Todo"],
+            json!({
+                "type": "object",
+                "properties": {
+                    "id": { "type": "integer", "format": "int32", "minimum": 0 },
+                    "title": { "type": "string" },
+                    "description": { "anyOf": [{ "type": "string" }, { "type": "null" }] },
+                },
+                "required": ["id", "title"],
+            })
+        );
+    }
+
+    #[test]
+    fn generic_types_are_inlined() {
+        let (schema, components) = schema::<Page<Todo>>();
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "items": {
+                        "type": "array",
+                        "items": { "$ref": "#/components/schemas/Todo" },
+                    },
+                },
+                "required": ["items"],
+            })
+        );
+        assert!(!components.contains_key("Page"));
+    }
+
+    #[test]
+    fn recursive_generic_types() {
+        let (schema, _) = schema::<Tree<bool>>();
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "value": { "type": "boolean" },
+                    "children": { "type": "array", "items": {} },
+                },
+                "required": ["value", "children"],
+            })
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::borrow::Cow;
+
+use rspc::Extension;
+use serde_json::{json, Value};
+
+use crate::OpenAPIState;
+
+/// Where an API key is provided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiKeyLocation {
+    Header,
+    Query,
+    Cookie,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Bearer {
+        format: Option<Cow<'static, str>>,
+    },
+    ApiKey {
+        name: Cow<'static, str>,
+        location: ApiKeyLocation,
+    },
+}
+
+/// A method of authenticating requests.
+///
+/// The scheme is identified in the OpenAPI document by it's name so each distinct scheme must have a unique name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityScheme {
+    name: Cow<'static, str>,
+    kind: Kind,
+    description: Option<Cow<'static, str>>,
+}
+
+impl SecurityScheme {
+    /// A session stored in a cookie, like the one used by `rspc-zer`.
+    pub fn cookie(cookie_name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            name: "cookieAuth".into(),
+            kind: Kind::ApiKey {
+                name: cookie_name.into(),
+                location: ApiKeyLocation::Cookie,
+            },
+            description: None,
+        }
+    }
+
+    /// A bearer token in the `Authorization` header.
+    pub fn bearer() -> Self {
+        Self {
+            name: "bearerAuth".into(),
+            kind: Kind::Bearer { format: None },
+            description: None,
+        }
+    }
+
+    /// An API key in a header, query parameter or cookie.
+    pub fn api_key(name: impl Into<Cow<'static, str>>, location: ApiKeyLocation) -> Self {
+        Self {
+            name: "apiKeyAuth".into(),
+            kind: Kind::ApiKey {
+                name: name.into(),
+                location,
+            },
+            description: None,
+        }
+    }
+
+    /// Set the name used to identify the scheme in the OpenAPI document.
+    pub fn name(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Set a hint for the format of a bearer token (Eg. `JWT`).
+    ///
+    /// This has no effect on other schemes.
+    pub fn bearer_format(mut self, format: impl Into<Cow<'static, str>>) -> Self {
+        if let Kind::Bearer { format: f } = &mut self.kind {
+            *f = Some(format.into());
+        }
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<Cow<'static, str>>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub(crate) fn key(&self) -> &str {
+        &self.name
+    }
+
+    // The Security Scheme Object for the document.
+    pub(crate) fn schema(&self) -> Value {
+        let mut schema = match &self.kind {
+            Kind::Bearer { format } => {
+                let mut schema = json!({
+                    "type": "http",
+                    "scheme": "bearer",
+                });
+                if let Some(format) = format {
+                    schema["bearerFormat"] = format.to_string().into();
+                }
+                schema
+            }
+            Kind::ApiKey { name, location } => json!({
+                "type": "apiKey",
+                "name": name,
+                "in": match location {
+                    ApiKeyLocation::Header => "header",
+                    ApiKeyLocation::Query => "query",
+                    ApiKeyLocation::Cookie => "cookie",
+                },
+            }),
+        };
+        if let Some(description) = &self.description {
+            schema["description"] = description.to_string().into();
+        }
+        schema
+    }
+}
+
+/// Mark a procedure as requiring authentication using the given [`SecurityScheme`].
+///
+/// This can be applied multiple times where any of the schemes will be accepted.
+/// This is the same as [`OpenAPI::security`](crate::OpenAPI::security) but can be applied to procedures which are shared between many endpoints or from within your own middleware.
+pub fn security<TCtx, TInput, TResult>(scheme: SecurityScheme) -> Extension<TCtx, TInput, TResult> {
+    Extension::new().setup(move |state, meta| {
+        state
+            .get_mut_or_init::<OpenAPIState>(Default::default)
+            .security
+            .entry(meta.name().to_string())
+            .or_default()
+            .push(scheme);
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use rspc::{Procedure, ProcedureError, ResolverError, Router};
+    use serde::Serialize;
+    use specta::Type;
+
+    use super::*;
+    use crate::{Config, OpenAPI, RouteTable};
+
+    #[derive(Debug, Serialize, Type)]
+    struct Error;
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            ResolverError::new((), None::<std::io::Error>).into()
+        }
+    }
+
+    fn document(router: Router<()>) -> Value {
+        let (procedures, types) = router.build().unwrap();
+        RouteTable::new(&procedures, &types).document(&Config::new())
+    }
+
+    #[test]
+    fn schemes() {
+        assert_eq!(SecurityScheme::cookie("session").key(), "cookieAuth");
+        assert_eq!(
+            SecurityScheme::cookie("session").schema(),
+            json!({ "type": "apiKey", "name": "session", "in": "cookie" })
+        );
+
+        assert_eq!(SecurityScheme::bearer().key(), "bearerAuth");
+        assert_eq!(
+            SecurityScheme::bearer().bearer_format("JWT").schema(),
+            json!({ "type": "http", "scheme": "bearer", "bearerFormat": "JWT" })
+        );
+
+        let scheme = SecurityScheme::api_key("X-API-Key", ApiKeyLocation::Header)
+            .name("apiKey")
+            .description("Issued from the dashboard")
+            // Only applies to bearer tokens.
+            .bearer_format("JWT");
+        assert_eq!(scheme.key(), "apiKey");
+        assert_eq!(
+            scheme.schema(),
+            json!({
+                "type": "apiKey",
+                "name": "X-API-Key",
+                "in": "header",
+                "description": "Issued from the dashboard",
+            })
+        );
+        assert_eq!(
+            SecurityScheme::api_key("key", ApiKeyLocation::Query).schema()["in"],
+            "query"
+        );
+    }
+
+    #[test]
+    fn document_requirements() {
+        let document = document(
+            <Router>::new()
+                .procedure(
+                    "me",
+                    Procedure::builder::<Error>()
+                        .with(
+                            OpenAPI::get("/me")
+                                .security(SecurityScheme::cookie("session"))
+                                .build(),
+                        )
+                        // Applied separately, Eg. by a shared middleware.
+                        .with(security(SecurityScheme::bearer()))
+                        .query(|_, _: ()| async { Ok(()) }),
+                )
+                .procedure(
+                    "health",
+                    Procedure::builder::<Error>()
+                        .with(OpenAPI::get("/health").build())
+                        .query(|_, _: ()| async { Ok(()) }),
+                ),
+        );
+
+        assert_eq!(
+            document["components"]["securitySchemes"],
+            json!({
+                "bearerAuth": { "type": "http", "scheme": "bearer" },
+                "cookieAuth": { "type": "apiKey", "name": "session", "in": "cookie" },
+            })
+        );
+
+        let me = &document["paths"]["/me"]["get"];
+        assert_eq!(
+            me["security"],
+            json!([{ "bearerAuth": [] }, { "cookieAuth": [] }])
+        );
+        assert!(me["responses"]["401"].is_object());
+        assert!(me["responses"]["403"].is_object());
+
+        let health = &document["paths"]["/health"]["get"];
+        assert!(health.get("security").is_none());
+        assert!(health["responses"].get("401").is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "multiple security schemes are named 'cookieAuth'")]
+    fn duplicate_names() {
+        document(
+            <Router>::new()
+                .procedure(
+                    "a",
+                    Procedure::builder::<Error>()
+                        .with(
+                            OpenAPI::get("/a")
+                                .security(SecurityScheme::cookie("session"))
+                                .build(),
+                        )
+                        .query(|_, _: ()| async { Ok(()) }),
+                )
+                .procedure(
+                    "b",
+                    Procedure::builder::<Error>()
+                        .with(
+                            OpenAPI::get("/b")
+                                .security(SecurityScheme::cookie("token"))
+                                .build(),
+                        )
+                        .query(|_, _: ()| async { Ok(()) }),
+                ),
+        );
+    }
+}
@@ -37,6 +37,19 @@ impl ProcedureError {
         }
     }
 
+    /// The HTTP status code integrations should respond with.
+    ///
+    /// This is shared by every integration so the same error has the same status on any transport.
+    pub fn status(&self) -> u16 {
+        match self {
+            ProcedureError::NotFound => 404,
+            ProcedureError::Deserialize(_) => 400,
+            ProcedureError::Downcast(_) => 400,
+            ProcedureError::Resolver(err) => err.status(),
+            ProcedureError::Unwind(_) => 500,
+        }
+    }
+
     // TODO: This should be treated as sanitized and okay for the frontend right?
     pub fn message(&self) -> Cow<'static, str> {
         match self {
@@ -114,15 +127,30 @@ impl Serialize for ProcedureError {
 }
 
 /// TODO
-pub struct ResolverError(Box<dyn ErrorInternalExt>);
+pub struct ResolverError(Box<dyn ErrorInternalExt>, u16);
 
 impl ResolverError {
-    // Warning: Returning > 400 will fallback to `500`. As redirects would be invalid and `200` would break matching.
     pub fn new<T: Serialize + Send + 'static, E: error::Error + Send + 'static>(
         value: T,
         source: Option<E>,
     ) -> Self {
-        Self(Box::new(ErrorInternal { value, err: source }))
+        Self(Box::new(ErrorInternal { value, err: source }), 500)
+    }
+
+    /// Set the HTTP status code integrations should respond with. Defaults to `500`.
+    // Warning: Anything outside of `400..=599` will fallback to `500`. As redirects would be invalid and `200` would break matching.
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.1 = if (400..=599).contains(&status) {
+            status
+        } else {
+            500
+        };
+        self
+    }
+
+    /// The HTTP status code of the error.
+    pub fn status(&self) -> u16 {
+        self.1
     }
 
     /// TODO
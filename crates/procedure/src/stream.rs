@@ -433,8 +433,8 @@ impl ProcedureStream {
             },
             Inner::Value(v) => {
                 if self.flush.is_none() {
-                    // Poll::Ready(v.take().map(Err))
-                    todo!();
+                    // The error is taken by `Self::value` so this will only yield once.
+                    Poll::Ready(v.is_some().then_some(()))
                 } else {
                     Poll::Pending
                 }
@@ -447,24 +447,24 @@ impl ProcedureStream {
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<DynOutput<'_>, ProcedureError>>> {
-        self.poll_inner(cx).map(|v| {
-            v.map(|_: ()| {
-                let Inner::Dyn(s) = &mut self.inner else {
-                    unreachable!(); // TODO: Handle this?
-                };
-                s.as_mut().value()
-            })
-        })
+        self.poll_inner(cx).map(|v| v.map(|_: ()| self.value()))
     }
 
     /// TODO
     pub async fn next(&mut self) -> Option<Result<DynOutput<'_>, ProcedureError>> {
-        poll_fn(|cx| self.poll_inner(cx)).await.map(|_: ()| {
-            let Inner::Dyn(s) = &mut self.inner else {
-                unreachable!(); // TODO: Handle this?
-            };
-            s.as_mut().value()
-        })
+        poll_fn(|cx| self.poll_inner(cx))
+            .await
+            .map(|_: ()| self.value())
+    }
+
+    // Take the value yielded by the last successful call to `Self::poll_inner`.
+    fn value(&mut self) -> Result<DynOutput<'_>, ProcedureError> {
+        match &mut self.inner {
+            Inner::Dyn(s) => s.as_mut().value(),
+            Inner::Value(v) => Err(v
+                .take()
+                .expect("unreachable: `poll_inner` only yields when the error is present")),
+        }
     }
 
     /// TODO
@@ -515,11 +515,7 @@ impl<F: FnMut(Result<DynOutput, ProcedureError>) -> Result<T, String> + Unpin, T
 
         this.stream.poll_inner(cx).map(|v| {
             v.map(|_: ()| {
-                let Inner::Dyn(s) = &mut this.stream.inner else {
-                    unreachable!();
-                };
-
-                match (this.map)(s.as_mut().value()) {
+                match (this.map)(this.stream.value()) {
                     Ok(v) => v,
                     // TODO: Exposing this error to the client or not?
                     // TODO: Error type???
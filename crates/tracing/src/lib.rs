@@ -11,8 +11,13 @@ use std::{fmt, marker::PhantomData};
 use rspc::middleware::Middleware;
 use tracing::info;
 
+mod slow;
 mod traceable;
 
+pub use slow::{
+    slow_threshold, timed_layer, FirstItem, LayerTiming, SlowCall, SlowCallTimer, SlowProcedures,
+    SlowTraceable,
+};
 pub use traceable::{DebugMarker, StreamMarker, Traceable};
 use tracing_futures::Instrument;
 
@@ -0,0 +1,627 @@
+//! Detection and reporting of slow procedure calls.
+
+use std::{
+    any::type_name,
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+use pin_project_lite::pin_project;
+use rspc::{
+    middleware::{Middleware, Next},
+    Extension, Procedure, ProcedureKind,
+};
+use serde::Serialize;
+use specta::Type;
+use tracing::warn;
+
+use crate::{traceable, DebugMarker, StreamMarker, Traceable};
+
+thread_local! {
+    // The layer timings of the call being polled on this thread.
+    //
+    // This is only set while a `TimedCall` is being polled, like how a `tracing` span is entered, so it follows the call between threads.
+    // `timed_layer` takes the timings out of it when it's future is created and keeps them, so it never relies on which thread it's polled on.
+    static CURRENT_CALL: RefCell<Option<LayerTimings>> = RefCell::default();
+}
+
+/// A report of a single procedure call which exceeded it's latency threshold.
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct SlowCall {
+    pub procedure: String,
+    pub kind: String,
+    /// The Rust type of the input.
+    pub input_type: String,
+    /// The [`Debug`](fmt::Debug) representation of the input.
+    pub input: String,
+    /// The result formatted using [`Traceable`].
+    pub output: String,
+    pub elapsed_ms: f64,
+    pub threshold_ms: f64,
+    /// The time spent in each layer registered with [`timed_layer`], from the outermost to the innermost layer.
+    pub layers: Vec<LayerTiming>,
+    /// The time from the call starting until the first item was yielded. This is only set for streams.
+    pub time_to_first_item_ms: Option<f64>,
+}
+
+/// The time spent within a single middleware layer.
+///
+/// This excludes the time spent in any nested [`timed_layer`] so the innermost layer also accounts for the resolver.
+#[derive(Debug, Clone, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerTiming {
+    pub name: String,
+    pub elapsed_ms: f64,
+}
+
+/// Detect procedure calls which exceed their latency threshold.
+///
+/// When a call is slow a structured warning is emitted using [`tracing`] and the call is recorded in a bounded report of the slowest calls.
+/// The report can be queried with [`SlowProcedures::report`] or mounted onto your router using [`SlowProcedures::procedure`].
+///
+/// The default threshold can be overridden for a single procedure using [`slow_threshold`].
+#[derive(Clone)]
+pub struct SlowProcedures {
+    threshold: Duration,
+    capacity: usize,
+    calls: Arc<Mutex<Vec<SlowCall>>>,
+}
+
+impl fmt::Debug for SlowProcedures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowProcedures")
+            .field("threshold", &self.threshold)
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl SlowProcedures {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            capacity: 50,
+            calls: Default::default(),
+        }
+    }
+
+    /// Set the maximum number of calls kept in the report. Defaults to `50`.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Get the slowest calls recorded, ordered from slowest to fastest.
+    pub fn report(&self) -> Vec<SlowCall> {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Clear all recorded calls.
+    pub fn clear(&self) {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// A query which returns [`SlowProcedures::report`].
+    pub fn procedure<TCtx, TError>(&self) -> Procedure<TCtx, (), Vec<SlowCall>>
+    where
+        TCtx: Send + 'static,
+        TError: rspc::Error,
+    {
+        let this = self.clone();
+        Procedure::builder::<TError>().query(move |_, _: ()| {
+            let report = this.report();
+            async move { Ok(report) }
+        })
+    }
+
+    /// The middleware which times each call.
+    ///
+    /// This should be the first middleware on the procedure so the timing includes all other layers.
+    /// The input is cloned so it can be included in the report, but it's only formatted if the call was slow.
+    pub fn middleware<TError, TCtx, TInput, TResult, M>(
+        &self,
+    ) -> Middleware<TError, TCtx, TInput, TResult::Output, TCtx, TInput, TResult>
+    where
+        TError: fmt::Debug + Send + 'static,
+        TCtx: Send + 'static,
+        TInput: fmt::Debug + Clone + Send + 'static,
+        TResult: SlowTraceable<M> + Send + 'static,
+        M: 'static,
+    {
+        let this = self.clone();
+        Middleware::new(
+            move |ctx, input: TInput, next: Next<TError, TCtx, TInput, TResult>| {
+                let this = this.clone();
+                async move {
+                    let meta = next.meta();
+                    let threshold = meta
+                        .state()
+                        .get::<Thresholds>()
+                        .and_then(|t| t.0.get(meta.name()).copied())
+                        .unwrap_or(this.threshold);
+
+                    let input_clone = Box::new(input.clone());
+                    let layers = LayerTimings::default();
+                    let start = Instant::now();
+
+                    let result = TimedCall {
+                        fut: next.exec(ctx, input),
+                        layers: layers.clone(),
+                    }
+                    .await;
+
+                    let timer = SlowCallTimer {
+                        slow: this,
+                        procedure: meta.name().to_string(),
+                        kind: meta.kind(),
+                        input_type: type_name::<TInput>(),
+                        input: input_clone,
+                        start,
+                        elapsed: start.elapsed(),
+                        threshold,
+                        layers,
+                    };
+
+                    match result {
+                        Ok(result) => Ok(result.timed(timer)),
+                        Err(err) => {
+                            timer.finish(None, || traceable::format::<_, DebugMarker>(&err));
+                            Err(err)
+                        }
+                    }
+                }
+            },
+        )
+    }
+
+    fn record(&self, call: SlowCall) {
+        let mut calls = self.calls.lock().unwrap_or_else(PoisonError::into_inner);
+        let i = calls.partition_point(|c| c.elapsed_ms >= call.elapsed_ms);
+        if i < self.capacity {
+            calls.insert(i, call);
+            calls.truncate(self.capacity);
+        }
+    }
+}
+
+/// Override the threshold of [`SlowProcedures`] for a single procedure.
+pub fn slow_threshold<TCtx, TInput, TResult>(
+    threshold: Duration,
+) -> Extension<TCtx, TInput, TResult> {
+    Extension::new().setup(move |state, meta| {
+        state
+            .get_mut_or_init::<Thresholds>(Default::default)
+            .0
+            .insert(meta.name().to_string(), threshold);
+    })
+}
+
+/// Record the time spent within the following middleware into the current [`SlowProcedures`] call.
+///
+/// Apply this between your other middleware to get a breakdown of where the time of a slow call was spent.
+pub fn timed_layer<TError, TCtx, TInput, TResult>(
+    name: &'static str,
+) -> Middleware<TError, TCtx, TInput, TResult>
+where
+    TError: Send + 'static,
+    TCtx: Send + 'static,
+    TInput: Send + 'static,
+    TResult: Send + 'static,
+{
+    Middleware::new(move |ctx, input, next| {
+        // The future is created while the `TimedCall` of `SlowProcedures::middleware` is being polled so the current call is available.
+        let layers = CURRENT_CALL.with(|v| v.borrow().clone());
+
+        async move {
+            let Some(layers) = layers else {
+                return next.exec(ctx, input).await;
+            };
+
+            let i = layers.push(name);
+            let start = Instant::now();
+            let result = next.exec(ctx, input).await;
+            layers.set(i, start.elapsed());
+            result
+        }
+    })
+}
+
+// A map of procedure name to their threshold.
+#[derive(Default)]
+struct Thresholds(HashMap<String, Duration>);
+
+// The name and inclusive time of each layer. The time is `None` until the layer has completed.
+type Layer = (&'static str, Option<Duration>);
+
+#[derive(Default, Clone)]
+struct LayerTimings(Arc<Mutex<Vec<Layer>>>);
+
+impl LayerTimings {
+    fn enter(&self) -> LayerTimingsGuard {
+        LayerTimingsGuard(CURRENT_CALL.with(|v| v.replace(Some(self.clone()))))
+    }
+
+    fn push(&self, name: &'static str) -> usize {
+        let mut layers = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        layers.push((name, None));
+        layers.len() - 1
+    }
+
+    fn set(&self, i: usize, elapsed: Duration) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)[i].1 = Some(elapsed);
+    }
+
+    // Convert the inclusive time of each layer into the time spent exclusively in it.
+    fn collect(&self) -> Vec<LayerTiming> {
+        let layers = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        layers
+            .iter()
+            .enumerate()
+            .map(|(i, (name, elapsed))| {
+                let elapsed = elapsed.unwrap_or_default();
+                let inner = layers.get(i + 1).and_then(|(_, v)| *v).unwrap_or_default();
+
+                LayerTiming {
+                    name: name.to_string(),
+                    elapsed_ms: as_ms(elapsed.saturating_sub(inner)),
+                }
+            })
+            .collect()
+    }
+}
+
+// Restores the previous call when dropped so nested `SlowProcedures` work correctly.
+struct LayerTimingsGuard(Option<LayerTimings>);
+
+impl Drop for LayerTimingsGuard {
+    fn drop(&mut self) {
+        let prev = self.0.take();
+        CURRENT_CALL.with(|v| *v.borrow_mut() = prev);
+    }
+}
+
+pin_project! {
+    // The call being timed by `SlowProcedures::middleware`. It's timings are made current every time it's polled.
+    struct TimedCall<F> {
+        #[pin]
+        fut: F,
+        layers: LayerTimings,
+    }
+}
+
+impl<F: Future> Future for TimedCall<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let _guard = this.layers.enter();
+        this.fut.poll(cx)
+    }
+}
+
+/// The timing of a call which hasn't been reported yet.
+///
+/// This is completed once the value is returned or, for streams, when the first item is yielded.
+pub struct SlowCallTimer {
+    slow: SlowProcedures,
+    procedure: String,
+    kind: ProcedureKind,
+    input_type: &'static str,
+    input: Box<dyn fmt::Debug + Send>,
+    start: Instant,
+    elapsed: Duration,
+    threshold: Duration,
+    layers: LayerTimings,
+}
+
+impl fmt::Debug for SlowCallTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowCallTimer")
+            .field("procedure", &self.procedure)
+            .finish()
+    }
+}
+
+impl SlowCallTimer {
+    // Formatting is deferred until here so fast calls don't pay for it.
+    fn finish(self, time_to_first_item: Option<Duration>, output: impl FnOnce() -> String) {
+        if self.elapsed.max(time_to_first_item.unwrap_or_default()) <= self.threshold {
+            return;
+        }
+
+        let call = SlowCall {
+            procedure: self.procedure,
+            kind: self.kind.to_string(),
+            input_type: self.input_type.to_string(),
+            input: format!("{:?}", self.input),
+            output: output(),
+            elapsed_ms: as_ms(self.elapsed),
+            threshold_ms: as_ms(self.threshold),
+            layers: self.layers.collect(),
+            time_to_first_item_ms: time_to_first_item.map(as_ms),
+        };
+
+        warn!(
+            procedure = %call.procedure,
+            kind = %call.kind,
+            input_type = %call.input_type,
+            input = %call.input,
+            output = %call.output,
+            elapsed_ms = call.elapsed_ms,
+            threshold_ms = call.threshold_ms,
+            layers = ?call.layers,
+            time_to_first_item_ms = ?call.time_to_first_item_ms,
+            "slow procedure call"
+        );
+
+        self.slow.record(call);
+    }
+}
+
+/// A result which can be timed by [`SlowProcedures`].
+///
+/// This is implemented for all [`Traceable`] values. For a [`rspc::Stream`] the time to the first item is also recorded.
+pub trait SlowTraceable<M>: Traceable<M> {
+    type Output;
+
+    #[doc(hidden)]
+    fn timed(self, timer: SlowCallTimer) -> Self::Output;
+}
+
+impl<T: fmt::Debug> SlowTraceable<DebugMarker> for T {
+    type Output = T;
+
+    fn timed(self, timer: SlowCallTimer) -> Self::Output {
+        timer.finish(None, || traceable::format::<_, DebugMarker>(&self));
+        self
+    }
+}
+
+impl<S> SlowTraceable<StreamMarker> for rspc::Stream<S>
+where
+    S: futures::Stream,
+    S::Item: fmt::Debug,
+{
+    type Output = rspc::Stream<FirstItem<S>>;
+
+    fn timed(self, timer: SlowCallTimer) -> Self::Output {
+        rspc::Stream(FirstItem {
+            stream: self,
+            timer: Some(timer),
+        })
+    }
+}
+
+pin_project! {
+    /// A stream which reports the time until it yielded it's first item to [`SlowProcedures`].
+    pub struct FirstItem<S: futures::Stream> {
+        #[pin]
+        stream: rspc::Stream<S>,
+        timer: Option<SlowCallTimer>,
+    }
+}
+
+impl<S> futures::Stream for FirstItem<S>
+where
+    S: futures::Stream,
+    S::Item: fmt::Debug,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        let item = ready!(this.stream.as_mut().poll_next(cx));
+        if let Some(timer) = this.timer.take() {
+            let time_to_first_item = timer.start.elapsed();
+            let stream = &*this.stream;
+            timer.finish(Some(time_to_first_item), || {
+                traceable::format::<_, StreamMarker>(stream)
+            });
+        }
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use rspc::{ProcedureError, Procedures, Router};
+    use serde::de::value::{Error as DeError, UnitDeserializer};
+
+    use super::*;
+
+    #[derive(Debug, Type, Serialize)]
+    struct Error;
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            rspc::ResolverError::new(self, None::<std::io::Error>).into()
+        }
+    }
+
+    async fn call(procedures: Procedures<()>, name: &str) {
+        let mut stream =
+            procedures[name].exec_with_deserializer((), UnitDeserializer::<DeError>::new());
+        while stream.next().await.is_some() {}
+    }
+
+    #[tokio::test]
+    async fn slow_calls() {
+        let slow = SlowProcedures::new(Duration::from_millis(20));
+        let router = <Router>::new()
+            .procedure(
+                "fast",
+                Procedure::builder::<Error>()
+                    .with(slow.middleware())
+                    .query(|_, _: ()| async { Ok("fast") }),
+            )
+            .procedure(
+                "slow",
+                Procedure::builder::<Error>()
+                    .with(slow.middleware())
+                    .query(|_, _: ()| async {
+                        tokio::time::sleep(Duration::from_millis(30)).await;
+                        Ok("slow")
+                    }),
+            );
+        let (procedures, _) = router.build().unwrap();
+
+        call(procedures.clone(), "fast").await;
+        assert!(slow.report().is_empty());
+
+        call(procedures, "slow").await;
+        let report = slow.report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].procedure, "slow");
+        assert_eq!(report[0].kind, "Query");
+        assert_eq!(report[0].input_type, "()");
+        assert_eq!(report[0].input, "()");
+        assert_eq!(report[0].output, "\"slow\"");
+        assert!(report[0].elapsed_ms >= 30.0);
+        assert_eq!(report[0].threshold_ms, 20.0);
+        assert_eq!(report[0].time_to_first_item_ms, None);
+
+        slow.clear();
+        assert!(slow.report().is_empty());
+    }
+
+    #[tokio::test]
+    async fn threshold_override() {
+        let slow = SlowProcedures::new(Duration::from_secs(60));
+        let router = <Router>::new().procedure(
+            "a",
+            Procedure::builder::<Error>()
+                .with(slow_threshold(Duration::ZERO))
+                .with(slow.middleware())
+                .query(|_, _: ()| async {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    Ok(())
+                }),
+        );
+
+        call(router.build().unwrap().0, "a").await;
+        assert_eq!(slow.report()[0].threshold_ms, 0.0);
+    }
+
+    #[tokio::test]
+    async fn errors_and_streams_use_traceable() {
+        let slow = SlowProcedures::new(Duration::ZERO);
+        let router = <Router>::new()
+            .procedure(
+                "error",
+                Procedure::builder::<Error>()
+                    .with(slow.middleware())
+                    .query(|_, _: ()| async {
+                        tokio::time::sleep(Duration::from_millis(1)).await;
+                        Err::<(), _>(Error)
+                    }),
+            )
+            .procedure(
+                "stream",
+                Procedure::builder::<Error>()
+                    .with(slow.middleware())
+                    .query(|_, _: ()| async {
+                        Ok(rspc::Stream(futures::stream::once(async {
+                            tokio::time::sleep(Duration::from_millis(1)).await;
+                            Ok::<_, Error>(1i32)
+                        })))
+                    }),
+            );
+
+        let (procedures, _) = router.build().unwrap();
+        call(procedures.clone(), "error").await;
+        call(procedures, "stream").await;
+
+        let report = slow.report();
+        let error = report.iter().find(|c| c.procedure == "error").unwrap();
+        assert_eq!(error.output, "Error");
+        let stream = report.iter().find(|c| c.procedure == "stream").unwrap();
+        assert_eq!(
+            stream.output,
+            format!("Stream<{}>", type_name::<Result<i32, Error>>())
+        );
+        assert!(stream.time_to_first_item_ms.unwrap() >= 1.0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn layers_across_threads() {
+        let slow = SlowProcedures::new(Duration::ZERO);
+        let router = <Router>::new().procedure(
+            "a",
+            Procedure::builder::<Error>()
+                .with(slow.middleware())
+                .with(timed_layer("outer"))
+                .with(timed_layer("inner"))
+                .query(|_, _: ()| async {
+                    // Give the runtime a chance to move the call to another worker between polls.
+                    for _ in 0..10 {
+                        tokio::task::yield_now().await;
+                        tokio::time::sleep(Duration::from_millis(1)).await;
+                    }
+                    Ok(())
+                }),
+        );
+
+        let (procedures, _) = router.build().unwrap();
+        let calls = (0..8)
+            .map(|_| tokio::spawn(call(procedures.clone(), "a")))
+            .collect::<Vec<_>>();
+        for call in calls {
+            call.await.unwrap();
+        }
+
+        let report = slow.report();
+        assert_eq!(report.len(), 8);
+        for call in report {
+            let names = call.layers.iter().map(|l| &*l.name).collect::<Vec<_>>();
+            assert_eq!(names, ["outer", "inner"]);
+            // The resolver is accounted to the innermost layer.
+            assert!(call.layers[1].elapsed_ms >= 10.0);
+        }
+    }
+
+    #[test]
+    fn report_is_bounded_and_ordered() {
+        let slow = SlowProcedures::new(Duration::ZERO).capacity(2);
+        for elapsed_ms in [1.0, 3.0, 2.0] {
+            slow.record(SlowCall {
+                procedure: elapsed_ms.to_string(),
+                kind: "query".into(),
+                input_type: "()".into(),
+                input: "()".into(),
+                output: "()".into(),
+                elapsed_ms,
+                threshold_ms: 0.0,
+                layers: vec![],
+                time_to_first_item_ms: None,
+            });
+        }
+
+        let report = slow.report();
+        assert_eq!(
+            report.iter().map(|c| c.elapsed_ms).collect::<Vec<_>>(),
+            [3.0, 2.0]
+        );
+    }
+}
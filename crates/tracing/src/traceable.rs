@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{any::type_name, fmt, marker::PhantomData};
 
 pub trait Traceable<M> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
@@ -21,6 +21,20 @@ where
     S::Item: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        todo!(); // TODO: Finish this
+        // The items are only known once the stream is polled
+        write!(f, "Stream<{}>", type_name::<S::Item>())
     }
 }
+
+// Format a value using it's `Traceable` implementation.
+pub(crate) fn format<T: Traceable<M>, M>(value: &T) -> String {
+    struct Wrapper<'a, T, M>(&'a T, PhantomData<M>);
+
+    impl<T: Traceable<M>, M> fmt::Debug for Wrapper<'_, T, M> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            Traceable::fmt(self.0, f)
+        }
+    }
+
+    format!("{:?}", Wrapper(value, PhantomData))
+}
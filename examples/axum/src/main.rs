@@ -35,13 +35,13 @@ async fn main() {
         .unwrap();
 
     // Be aware this is very experimental and doesn't support many types yet.
-    // rspc::Rust::default()
-    //     // .header("// My custom header")
-    //     .export_to(
-    //         PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../client/src/bindings.rs"),
-    //         &types,
-    //     )
-    //     .unwrap();
+    rspc::Rust::default()
+        // .header("// My custom header")
+        .export_to(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../client/src/bindings.rs"),
+            &types,
+        )
+        .unwrap();
 
     // let procedures = rspc_devtools::mount(procedures, &types); // TODO
 
@@ -3,29 +3,26 @@
 
 use axum::{
     body::Body,
-    http::{header, request::Parts, HeaderMap},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
 };
-use futures::TryStreamExt;
-use rspc::{DynInput, Procedure, ProcedureBuilder, Procedures, ResolverInput, ResolverOutput};
+use futures::{StreamExt, TryStreamExt};
+use rspc::{
+    DynInput, DynOutput, Procedure, ProcedureBuilder, ProcedureError, Procedures, ResolverError,
+    ResolverInput, ResolverOutput,
+};
 use rspc_binario::BinarioOutput;
 use specta::Type;
-use std::{convert::Infallible, marker::PhantomData};
+use std::marker::PhantomData;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use tower_http::cors::{Any, CorsLayer};
 
 #[derive(Type)]
-pub enum Error {
-    Binario(#[specta(skip)] rspc_binario::DeserializeError),
-}
-impl From<rspc_binario::DeserializeError> for Error {
-    fn from(err: rspc_binario::DeserializeError) -> Self {
-        Error::Binario(err)
-    }
-}
+pub enum Error {}
 impl rspc::Error for Error {
     fn into_procedure_error(self) -> rspc::ProcedureError {
-        todo!(); // TODO: Work this out
+        match self {}
     }
 }
 
@@ -99,56 +96,85 @@ pub fn rspc_binario_handler(procedures: Procedures<()>) -> axum::Router {
             let procedures = procedures.clone();
 
             move |parts: Parts, body: Body| async move {
-                let ctx = ();
-
                 // if parts.headers.get("Content-Type") != Some(&"text/x-binario".parse().unwrap()) {
                 //     // TODO: Error handling
                 // }
 
                 let mut params = form_urlencoded::parse(parts.uri.query().unwrap_or("").as_bytes());
-                let procedure_name = params
-                    .find(|(key, _)| key == "procedure")
-                    .map(|(_, value)| value)
-                    .unwrap(); // TODO: Error handling
-
-                let procedure = procedures.get(&procedure_name).unwrap(); // TODO: Error handling
+                let Some((_, procedure_name)) = params.find(|(key, _)| key == "procedure") else {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        "missing the 'procedure' query parameter",
+                    )
+                        .into_response();
+                };
+                let Some(procedure) = procedures.get(&procedure_name) else {
+                    return (StatusCode::NOT_FOUND, "procedure not found").into_response();
+                };
 
                 let mut input = Some(rspc_binario::BinarioInput::from_stream(
                     body.into_data_stream()
-                        .map_err(|_err| todo!()) // TODO: Error handling
+                        .map_err(std::io::Error::other)
                         .into_async_read()
                         .compat(),
                 ));
-                let stream = procedure.exec(ctx.clone(), DynInput::new_value(&mut input));
+                let mut stream = procedure.exec((), DynInput::new_value(&mut input));
+
+                // Errors can only be reported with a status code before the body has started.
+                let first = match stream.next().await.map(into_binario) {
+                    Some(Ok(v)) => v,
+                    Some(Err(err)) => return error_response(err),
+                    None => return StatusCode::NO_CONTENT.into_response(),
+                };
+
                 let mut headers = HeaderMap::new();
-                headers.insert(header::CONTENT_TYPE, "text/x-binario".parse().unwrap());
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/x-binario"),
+                );
 
-                let mut first = true;
                 (
                     headers,
-                    Body::from_stream(stream.map(move |v| {
-                        let buf = match v {
-                            Ok(v) => Ok(Ok::<_, Infallible>(
-                                v.as_value::<BinarioOutput>().unwrap().0,
-                            )),
-                            Err(err) => todo!("{err:?}"),
-                        };
-
-                        if first {
-                            first = false;
-                            buf
-                        } else {
-                            buf.map(|v| {
-                                v.map(|mut v| {
-                                    let mut buf = vec!['\n' as u8, '\n' as u8];
-                                    buf.append(&mut v);
-                                    buf
-                                })
+                    // Binario is self-delimiting so the values of a stream are written back to back.
+                    Body::from_stream(
+                        futures::stream::once(async move { Ok(first) })
+                            .chain(stream.map(|v| Ok(into_binario(v))))
+                            .map(|v| match v {
+                                Ok(v) => v.into_stream().left_stream(),
+                                Err(err) => {
+                                    futures::stream::once(async move { Err(err) }).right_stream()
+                                }
                             })
-                        }
-                    })),
+                            .flatten()
+                            // This will abort the response so the client knows it's incomplete.
+                            .map_err(|err| std::io::Error::other(err.to_string())),
+                    ),
                 )
+                    .into_response()
             }
         }),
     )
 }
+
+fn into_binario(value: Result<DynOutput, ProcedureError>) -> Result<BinarioOutput, ProcedureError> {
+    value?.as_value::<BinarioOutput>().ok_or_else(|| {
+        ResolverError::new(
+            "procedure doesn't return a Binario value",
+            None::<std::io::Error>,
+        )
+        .into()
+    })
+}
+
+fn error_response(err: ProcedureError) -> Response {
+    let status = match &err {
+        ProcedureError::NotFound => StatusCode::NOT_FOUND,
+        ProcedureError::Deserialize(_) | ProcedureError::Downcast(_) => StatusCode::BAD_REQUEST,
+        ProcedureError::Resolver(err) => {
+            StatusCode::from_u16(err.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+        ProcedureError::Unwind(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    (status, err.to_string()).into_response()
+}
@@ -3,7 +3,7 @@ mod bindings;
 
 #[tokio::main]
 async fn main() {
-    let client = rspc_client::Client::new("http://[::]:4000/rspc");
+    let client = rspc_client::Client::new("http://[::]:4000/rspc").expect("error creating client");
 
     println!("{:?}", client.exec::<bindings::version>(()).await);
     println!(
@@ -0,0 +1,515 @@
+use std::{borrow::Cow, convert::Infallible};
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::Request,
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::{
+    stream::{self, FuturesUnordered},
+    StreamExt,
+};
+use rspc_procedure::Procedures;
+use serde_json::{json, Value};
+
+use crate::{
+    codec::Codec,
+    extractors::TCtxFunc,
+    jsonrpc::{self, JsonRPCError, RequestId, RequestInner, ResponseInner},
+    ndjson::{self, wait_for_headers, NDJSON},
+    shutdown, Config, ResponseHeaders,
+};
+
+// Execute an array of JSON-RPC requests concurrently. Subscriptions are not supported.
+//
+// Responses are streamed back as each procedure resolves so they may be out of order.
+// JSON responses are sent as newline delimited JSON if the client accepts it, otherwise as an array.
+// Other formats are sent as a sequence of values one after another.
+pub(crate) async fn handle_batch<TCtx, TCtxFn, TCtxFnMarker, TState>(
+    ctx_fn: TCtxFn,
+    req: Request,
+    procedures: &Procedures<TCtx>,
+    state: TState,
+    headers: ResponseHeaders,
+    config: &Config,
+) -> Response<Body>
+where
+    TCtx: Send + Sync + 'static,
+    TCtxFn: TCtxFunc<TCtx, TState, TCtxFnMarker>,
+    TState: Send + Sync + 'static,
+{
+    let (parts, body) = req.into_parts();
+    let ndjson = ndjson::accepts(&parts.headers);
+    let accept = match ndjson {
+        true => Codec::Json,
+        false => Codec::from_accept(&parts.headers),
+    };
+
+    let Some(codec) = Codec::from_content_type(&parts.headers) else {
+        // #[cfg(feature = "tracing")]
+        // tracing::error!("Unsupported content type for batch");
+
+        return reject(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            JsonRPCError {
+                code: 415,
+                message: "unsupported content type".into(),
+                data: Some(json!({ "~rspc": true })),
+            },
+            accept,
+            config,
+        );
+    };
+
+    let requests = match to_bytes(body, config.limits.body_size).await {
+        Ok(body) => codec.decode::<Vec<jsonrpc::Request>>(&body),
+        Err(_err) => {
+            // #[cfg(feature = "tracing")]
+            // tracing::error!("Error reading batch body: {_err}");
+
+            let exceeded = config.limits.body_too_large();
+            return reject(exceeded.status(), exceeded.error(), accept, config);
+        }
+    };
+    let requests = match requests {
+        Ok(requests) => requests,
+        Err(err) => {
+            // #[cfg(feature = "tracing")]
+            // tracing::error!("Error parsing batch: {err}");
+
+            return reject(
+                StatusCode::BAD_REQUEST,
+                JsonRPCError {
+                    code: 400,
+                    message: "error parsing batch".into(),
+                    data: Some(json!({ "~rspc": true, "detail": err })),
+                },
+                accept,
+                config,
+            );
+        }
+    };
+
+    if requests.len() > config.max_batch_size {
+        // We respond to every request so the client knows why each of them failed.
+        let message = format!(
+            "the batch contains {} requests but the limit is {}",
+            requests.len(),
+            config.max_batch_size
+        );
+        let responses = requests
+            .into_iter()
+            .map(|request| error(request.id, 413, &message))
+            .collect::<Vec<_>>();
+
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            [(header::CONTENT_TYPE, accept.mime())],
+            Body::from(accept.encode(&responses).unwrap_or_else(|_| b"[]".to_vec())),
+        )
+            .into_response();
+    }
+
+    // Responses which are known before executing anything.
+    let mut responses = Vec::new();
+    let mut streams = Vec::with_capacity(requests.len());
+    for mut request in requests {
+        if let Err(not_allowed) = config
+            .persisted_queries
+            .as_ref()
+            .map_or(Ok(()), |p| p.resolve_request(&mut request))
+        {
+            responses.push(not_allowed.response(request.id));
+            continue;
+        }
+
+        if let Err(exceeded) = config.limits.check_request(procedures, &request) {
+            responses.push(exceeded.response(request.id));
+            continue;
+        }
+
+        let (path, input) = match request.inner {
+            RequestInner::Query { path, input } | RequestInner::Mutation { path, input } => {
+                (path, input)
+            }
+            RequestInner::Subscription { .. } | RequestInner::SubscriptionStop { .. } => {
+                responses.push(error(
+                    request.id,
+                    400,
+                    "subscriptions are not supported over HTTP",
+                ));
+                continue;
+            }
+        };
+
+        let Some(procedure) = procedures.get(&Cow::Borrowed(&*path)) else {
+            // #[cfg(feature = "tracing")]
+            // tracing::error!("Error executing operation: the requested operation '{path}' is not supported by this server");
+
+            responses.push(error(
+                request.id,
+                404,
+                "the requested operation is not supported by this server",
+            ));
+            continue;
+        };
+
+        let ctx = match ctx_fn.exec(parts.clone(), &state).await {
+            Ok(ctx) => ctx,
+            Err(rejection) => {
+                // #[cfg(feature = "tracing")]
+                // tracing::error!("Error executing context function: {}", rejection.status());
+
+                responses.push(jsonrpc::Response {
+                    jsonrpc: "2.0",
+                    id: request.id,
+                    result: ResponseInner::Error(crate::error::rejection(rejection).await),
+                });
+                continue;
+            }
+        };
+
+        streams.push((
+            request.id,
+            procedure
+                .exec_with_deserializer(ctx, input.unwrap_or(Value::Null))
+                .require_manual_stream(),
+        ));
+    }
+
+    let ended = wait_for_headers(
+        &mut streams
+            .iter_mut()
+            .map(|(_, stream)| stream)
+            .collect::<Vec<_>>(),
+    )
+    .await;
+
+    let running = streams
+        .into_iter()
+        .zip(ended)
+        .map(|((id, mut stream), ended)| async move {
+            stream.stream();
+            match ended {
+                true => accept.encode_result(&id, None),
+                false => accept.encode_result(&id, stream.next().await),
+            }
+        })
+        .collect::<FuturesUnordered<_>>();
+
+    let responses = stream::iter(responses).map(move |response| {
+        accept.encode(&response).unwrap_or_else(|_err| {
+            // #[cfg(feature = "tracing")]
+            // tracing::error!("Error serializing response: {}", _err);
+
+            accept
+                .encode(&error(response.id, 500, "error serializing response"))
+                .expect("error responses are always serializable")
+        })
+    });
+
+    let json_array = accept == Codec::Json && !ndjson;
+    let mut first = true;
+    // If the server is shutting down the last response is an error telling the client to reconnect elsewhere.
+    let chunks = shutdown::until_expired(
+        responses.chain(running),
+        config.shutdown.as_ref(),
+        shutdown::cut_off(accept),
+    );
+    let chunks = chunks.map(move |mut chunk| {
+        if json_array && !first {
+            chunk.insert(0, b',');
+        }
+        first = false;
+
+        if ndjson {
+            chunk.push(b'\n');
+        }
+        Bytes::from(chunk)
+    });
+
+    let (open, close) = match json_array {
+        true => (
+            Some(Bytes::from_static(b"[")),
+            Some(Bytes::from_static(b"]")),
+        ),
+        false => (None, None),
+    };
+    let body = stream::iter(open)
+        .chain(chunks)
+        .chain(stream::iter(close))
+        .map(Ok::<_, Infallible>);
+
+    let mut response = (
+        StatusCode::OK,
+        [(
+            header::CONTENT_TYPE,
+            match ndjson {
+                true => NDJSON,
+                false => accept.mime(),
+            },
+        )],
+        Body::from_stream(body),
+    )
+        .into_response();
+    headers.apply(response.headers_mut());
+    response
+}
+
+// An error with the whole batch. This is a batch containing a single response without an id so it applies to every request.
+fn reject(
+    status: StatusCode,
+    error: JsonRPCError,
+    accept: Codec,
+    config: &Config,
+) -> Response<Body> {
+    if config.problem_details {
+        return crate::error::response(status, error, accept, true);
+    }
+
+    let response = jsonrpc::Response {
+        jsonrpc: "2.0",
+        id: RequestId::Null,
+        result: ResponseInner::Error(error),
+    };
+    (
+        status,
+        [(header::CONTENT_TYPE, accept.mime())],
+        accept
+            .encode(&[response])
+            .unwrap_or_else(|_| b"[]".to_vec()),
+    )
+        .into_response()
+}
+
+fn error(id: RequestId, code: i32, message: &str) -> jsonrpc::Response {
+    jsonrpc::Response {
+        jsonrpc: "2.0",
+        id,
+        result: ResponseInner::Error(JsonRPCError {
+            code,
+            message: message.into(),
+            data: None,
+        }),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{http::HeaderValue, Extension};
+    use rspc::{Procedure, ProcedureError, Router};
+    use serde_json::json;
+    use specta::Type;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::endpoint_with_config;
+
+    #[derive(Type)]
+    enum Error {}
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            match self {}
+        }
+    }
+
+    fn app(config: Config) -> axum::Router {
+        let (procedures, _) = Router::<ResponseHeaders>::new()
+            .procedure(
+                "sleep",
+                Procedure::builder::<Error>().query(|_, ms: u64| async move {
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                    Ok(ms)
+                }),
+            )
+            .procedure(
+                "login",
+                Procedure::builder::<Error>().mutation(
+                    |headers: ResponseHeaders, _: ()| async move {
+                        headers.append(header::SET_COOKIE, HeaderValue::from_static("session=1"));
+                        Ok(())
+                    },
+                ),
+            )
+            .build()
+            .unwrap();
+
+        axum::Router::new().nest(
+            "/rspc",
+            endpoint_with_config(
+                procedures,
+                |Extension(headers): Extension<ResponseHeaders>| headers,
+                config,
+            ),
+        )
+    }
+
+    async fn batch(
+        config: Config,
+        accept: &str,
+        body: Value,
+    ) -> (StatusCode, axum::http::HeaderMap, String) {
+        send(config, "application/json", accept, body.to_string()).await
+    }
+
+    async fn send(
+        config: Config,
+        content_type: &str,
+        accept: &str,
+        body: String,
+    ) -> (StatusCode, axum::http::HeaderMap, String) {
+        let response = app(config)
+            .oneshot(
+                Request::post("/rspc/_batch")
+                    .header(header::CONTENT_TYPE, content_type)
+                    .header(header::ACCEPT, accept)
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let (parts, body) = response.into_parts();
+        let body = to_bytes(body, usize::MAX).await.unwrap();
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    fn request(id: u32, method: &str, path: &str, input: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": { "path": path, "input": input },
+        })
+    }
+
+    fn query(id: u32, path: &str, input: Value) -> Value {
+        request(id, "query", path, input)
+    }
+
+    #[tokio::test]
+    async fn concurrent() {
+        let (status, headers, body) = batch(
+            Config::new(),
+            "application/json",
+            json!([query(1, "sleep", json!(100)), query(2, "sleep", json!(0))]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+
+        // The fast procedure doesn't wait for the slow one.
+        let body: Vec<Value> = serde_json::from_str(&body).unwrap();
+        let ids = body.iter().map(|r| r["id"].clone()).collect::<Vec<_>>();
+        assert_eq!(ids, vec![json!(2), json!(1)]);
+        assert_eq!(
+            body[1]["result"],
+            json!({ "type": "response", "data": 100 })
+        );
+    }
+
+    #[tokio::test]
+    async fn ndjson() {
+        let (status, headers, body) = batch(
+            Config::new(),
+            NDJSON,
+            json!([
+                query(1, "sleep", json!(0)),
+                query(2, "missing", json!(null)),
+                request(3, "subscription", "sleep", json!([3, 0])),
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], NDJSON);
+
+        let mut lines = body
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .map(|resp| (resp["id"].clone(), resp["result"]["data"]["code"].clone()))
+            .collect::<Vec<_>>();
+        lines.sort_by_key(|(id, _)| id.as_u64());
+        assert_eq!(
+            lines,
+            vec![
+                (json!(1), Value::Null),
+                (json!(2), json!(404)),
+                (json!(3), json!(400))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn headers() {
+        let login = request(1, "mutation", "login", Value::Null);
+        let (status, headers, _) = batch(
+            Config::new(),
+            "application/json",
+            json!([login, query(2, "sleep", json!(10))]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        // Headers set by any procedure in the batch are sent on the response.
+        assert_eq!(headers[header::SET_COOKIE], "session=1");
+    }
+
+    #[tokio::test]
+    async fn batch_size() {
+        let (status, _, body) = batch(
+            Config::new().max_batch_size(1),
+            "application/json",
+            json!([query(1, "sleep", json!(0)), query(2, "sleep", json!(0))]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Every request gets an error.
+        let body: Vec<Value> = serde_json::from_str(&body).unwrap();
+        assert_eq!(body.len(), 2);
+        for resp in body {
+            assert_eq!(resp["result"]["data"]["code"], 413);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_batches() {
+        let json = "application/json";
+
+        // The error applies to every request so it has no id.
+        let (status, headers, body) = send(Config::new(), "text/plain", json, "[]".into()).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(headers[header::CONTENT_TYPE], json);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body[0]["id"], Value::Null);
+        assert_eq!(body[0]["result"]["data"]["code"], 415);
+        assert_eq!(body[0]["result"]["data"]["data"]["~rspc"], true);
+
+        let (status, _, body) = send(Config::new(), json, json, "{".into()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body[0]["result"]["data"]["code"], 400);
+        assert_eq!(body[0]["result"]["data"]["message"], "error parsing batch");
+
+        let config = Config::new().limits(crate::Limits::new().body_size(1));
+        let (status, _, body) = send(config, json, json, "[]".into()).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body[0]["result"]["data"]["data"]["limit"], "bodySize");
+
+        let config = Config::new().problem_details(true);
+        let (status, headers, body) = send(config, "text/plain", json, "[]".into()).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/problem+json");
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["status"], 415);
+        assert_eq!(body["detail"], "unsupported content type");
+    }
+}
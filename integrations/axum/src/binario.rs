@@ -0,0 +1,643 @@
+//! Execute Binario procedures from websocket binary frames.
+//!
+//! Binary frames are only handled as Binario on websockets opened with the `rspc.binario` subprotocol.
+//! Text frames are still handled as JSON-RPC so both kinds of procedures can share a single socket.
+//!
+//! A request frame is `[kind: u8][id: u32][key length: u16][key: utf-8][input: binario]` where `kind` is:
+//!  - `0` query
+//!  - `1` mutation
+//!  - `2` subscription
+//!  - `3` stop the subscription with `id` (the key and input are empty)
+//!
+//! A response frame is `[kind: u8][id: u32][payload]` where `kind` is:
+//!  - `0` the result of a query or mutation (binario)
+//!  - `1` a subscription item (binario)
+//!  - `2` an error (a JSON-RPC error as JSON)
+//!  - `3` the subscription has ended (the payload is empty)
+//!
+//! A request frame which can't be parsed gets an error with it's id, or `0` if the frame is too short to contain one.
+//!
+//! All integers are little endian.
+//!
+//! Binario subscriptions are tracked separately from JSON-RPC ones so their ids can't collide.
+
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use rspc_binario::{BinarioInput, BinarioOutput};
+use rspc_procedure::{DynInput, ProcedureError, ProcedureStream, Procedures};
+use tokio::sync::oneshot;
+
+use crate::{
+    jsonrpc::JsonRPCError,
+    outbox::{Outbox, Outgoing, Queued},
+    Config,
+};
+
+// The websocket subprotocol a client uses to send Binario requests.
+pub const SUBPROTOCOL: &str = "rspc.binario";
+
+const QUERY: u8 = 0;
+const MUTATION: u8 = 1;
+const SUBSCRIPTION: u8 = 2;
+const SUBSCRIPTION_STOP: u8 = 3;
+
+const RESPONSE: u8 = 0;
+const EVENT: u8 = 1;
+const ERROR: u8 = 2;
+const COMPLETE: u8 = 3;
+
+struct Request<'a> {
+    kind: u8,
+    id: u32,
+    key: &'a str,
+    input: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    fn parse(frame: &'a [u8]) -> Option<Self> {
+        let (&kind, rest) = frame.split_first()?;
+        let (id, rest) = rest.split_first_chunk::<4>()?;
+        let (key_len, rest) = rest.split_first_chunk::<2>()?;
+        let key_len = u16::from_le_bytes(*key_len) as usize;
+        if rest.len() < key_len {
+            return None;
+        }
+        let (key, input) = rest.split_at(key_len);
+
+        Some(Self {
+            kind,
+            id: u32::from_le_bytes(*id),
+            key: std::str::from_utf8(key).ok()?,
+            input,
+        })
+    }
+}
+
+// The running Binario subscriptions on a connection keyed by their id.
+#[derive(Default)]
+pub struct Subscriptions(HashMap<u32, oneshot::Sender<()>>);
+
+impl Subscriptions {
+    // The number of running subscriptions.
+    pub fn len(&mut self) -> usize {
+        // Subscriptions which have finished are still in the map so they must be removed.
+        self.0.retain(|_, tx| !tx.is_closed());
+        self.0.len()
+    }
+
+    // Stop every subscription, returning the ids of the ones which were still running.
+    pub fn drain(&mut self) -> impl Iterator<Item = u32> + '_ {
+        self.0
+            .drain()
+            .filter(|(_, tx)| !tx.is_closed())
+            .map(|(id, _)| id)
+    }
+}
+
+// Get the id of a request frame so an error can be sent for it, even if the rest of the frame is invalid.
+pub fn request_id(frame: &[u8]) -> Option<u32> {
+    let (_, rest) = frame.split_first()?;
+    let (id, _) = rest.split_first_chunk::<4>()?;
+    Some(u32::from_le_bytes(*id))
+}
+
+// An encoded response frame.
+pub struct Frame(Vec<u8>);
+
+impl Frame {
+    fn new(kind: u8, id: u32) -> Self {
+        let mut frame = Vec::with_capacity(5);
+        frame.push(kind);
+        frame.extend_from_slice(&id.to_le_bytes());
+        Self(frame)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Queued for Frame {
+    fn is_event_of(&self, event: &Self) -> bool {
+        matches!(self.0.first(), Some(&EVENT | &ERROR)) && self.0.get(1..5) == event.0.get(1..5)
+    }
+}
+
+// `max_subscriptions` is the number of Binario subscriptions which can still be created on this connection.
+pub async fn handle_binario<TCtx>(
+    ctx: TCtx,
+    frame: &[u8],
+    procedures: &Procedures<TCtx>,
+    config: &Config,
+    outbox: &Arc<Outbox<Outgoing>>,
+    subscriptions: &mut Subscriptions,
+    max_subscriptions: usize,
+) where
+    TCtx: 'static,
+{
+    let Some(req) = Request::parse(frame) else {
+        // #[cfg(feature = "tracing")]
+        // tracing::error!("Error parsing binario frame");
+
+        outbox.push(error_frame(
+            request_id(frame).unwrap_or_default(),
+            JsonRPCError {
+                code: 400,
+                message: "error parsing binario frame".into(),
+                data: None,
+            },
+        ));
+        return;
+    };
+
+    match req.kind {
+        QUERY | MUTATION | SUBSCRIPTION => {}
+        SUBSCRIPTION_STOP => {
+            if let Some(tx) = subscriptions.0.remove(&req.id) {
+                let _ = tx.send(());
+            }
+            return;
+        }
+        _ => {
+            outbox.push(error_frame(
+                req.id,
+                JsonRPCError {
+                    code: 400,
+                    message: "invalid procedure kind".into(),
+                    data: None,
+                },
+            ));
+            return;
+        }
+    }
+
+    let key = match config
+        .persisted_queries
+        .as_ref()
+        .map_or(Ok(Cow::Borrowed(req.key)), |p| p.resolve(req.key))
+    {
+        Ok(key) => key,
+        Err(not_allowed) => {
+            outbox.push(error_frame(req.id, not_allowed.error()));
+            return;
+        }
+    };
+
+    let Some(procedure) = procedures.get(&key) else {
+        outbox.push(error_frame(
+            req.id,
+            JsonRPCError {
+                code: 404,
+                message: "the requested operation is not supported by this server".into(),
+                data: None,
+            },
+        ));
+        return;
+    };
+
+    // The input isn't JSON so only the size can be checked.
+    let limits = config.limits.resolve(procedures, &key);
+    if req.input.len() > limits.body_size {
+        outbox.push(error_frame(req.id, limits.body_too_large().error()));
+        return;
+    }
+
+    if req.kind != SUBSCRIPTION {
+        let mut input = Some(BinarioInput::from_bytes(req.input.to_vec()));
+        let mut stream = procedure.exec(ctx, DynInput::new_value(&mut input));
+        let frame = match next(&mut stream, RESPONSE, req.id).await {
+            Some(frame) => frame,
+            None => error_frame(
+                req.id,
+                JsonRPCError {
+                    code: 500,
+                    message: "procedure returned no value".into(),
+                    data: None,
+                },
+            ),
+        };
+        outbox.push(frame);
+        return;
+    }
+
+    if subscriptions
+        .0
+        .get(&req.id)
+        .is_some_and(|tx| !tx.is_closed())
+    {
+        outbox.push(error_frame(
+            req.id,
+            JsonRPCError {
+                code: 400,
+                message: "error creating subscription with duplicate id".into(),
+                data: None,
+            },
+        ));
+        return;
+    }
+
+    if subscriptions.len() >= max_subscriptions {
+        outbox.push(error_frame(
+            req.id,
+            JsonRPCError {
+                code: 429,
+                message: "error creating subscription as the connection has too many subscriptions"
+                    .into(),
+                data: None,
+            },
+        ));
+        outbox.push(Frame::new(COMPLETE, req.id));
+        return;
+    }
+
+    let mut input = Some(BinarioInput::from_bytes(req.input.to_vec()));
+    let mut stream = procedure.exec(ctx, DynInput::new_value(&mut input));
+    let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+    subscriptions.0.insert(req.id, shutdown_tx);
+    let outbox = outbox.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                biased; // Note: Order matters
+                _ = &mut shutdown_rx => break,
+                frame = next(&mut stream, EVENT, req.id) => match frame {
+                    Some(frame) => {
+                        if outbox.push_event(frame).await.is_err() {
+                            // #[cfg(feature = "tracing")]
+                            // tracing::debug!("Closing binario subscription '{}' as the client isn't reading fast enough", req.id);
+
+                            outbox.push(error_frame(
+                                req.id,
+                                JsonRPCError {
+                                    code: 503,
+                                    message: "the subscription was closed as the client isn't reading it fast enough".into(),
+                                    data: None,
+                                },
+                            ));
+                            outbox.push(Frame::new(COMPLETE, req.id));
+                            break;
+                        }
+                    }
+                    None => {
+                        outbox.push(Frame::new(COMPLETE, req.id));
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+// Get the next value from the stream as a response frame.
+async fn next(stream: &mut ProcedureStream, kind: u8, id: u32) -> Option<Frame> {
+    let output = match stream.next().await? {
+        Ok(v) => match v.as_value::<BinarioOutput>() {
+            Some(output) => output,
+            None => {
+                return Some(error_frame(
+                    id,
+                    JsonRPCError {
+                        code: 500,
+                        message: "procedure is not a binario procedure".into(),
+                        data: None,
+                    },
+                ))
+            }
+        },
+        Err(err) => return Some(error_frame(id, procedure_error(err))),
+    };
+
+    let mut frame = Frame::new(kind, id);
+    Some(match output.write_to(&mut frame.0).await {
+        Ok(_) => frame,
+        Err(err) => error_frame(
+            id,
+            JsonRPCError {
+                code: 500,
+                message: format!("error encoding value: {err}"),
+                data: None,
+            },
+        ),
+    })
+}
+
+fn procedure_error(err: ProcedureError) -> JsonRPCError {
+    JsonRPCError {
+        code: crate::error::status(&err).as_u16() as i32,
+        message: err.to_string(),
+        data: serde_json::to_value(&err).ok(),
+    }
+}
+
+pub fn error_frame(id: u32, err: JsonRPCError) -> Frame {
+    let mut frame = Frame::new(ERROR, id);
+    // `JsonRPCError` only contains JSON values so this can't fail.
+    serde_json::to_writer(&mut frame.0, &err)
+        .expect("unreachable: error serializing `JsonRPCError`");
+    frame
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use rspc::{Procedure, ProcedureError, Router};
+    use specta::Type;
+
+    use super::*;
+    use crate::Overflow;
+
+    fn request(kind: u8, id: u32, key: &str, input: &[u8]) -> Vec<u8> {
+        let mut frame = Frame::new(kind, id).0;
+        frame.extend_from_slice(&(key.len() as u16).to_le_bytes());
+        frame.extend_from_slice(key.as_bytes());
+        frame.extend_from_slice(input);
+        frame
+    }
+
+    #[test]
+    fn parse_request() {
+        let frame = request(SUBSCRIPTION, 0x01020304, "todos.list", &[1, 2, 3]);
+        assert_eq!(&frame[..5], &[SUBSCRIPTION, 4, 3, 2, 1]);
+
+        let req = Request::parse(&frame).unwrap();
+        assert_eq!(req.kind, SUBSCRIPTION);
+        assert_eq!(req.id, 0x01020304);
+        assert_eq!(req.key, "todos.list");
+        assert_eq!(req.input, &[1, 2, 3]);
+        assert_eq!(request_id(&frame), Some(0x01020304));
+
+        let frame = request(SUBSCRIPTION_STOP, 7, "", &[]);
+        let req = Request::parse(&frame).unwrap();
+        assert_eq!(
+            (req.kind, req.id, req.key, req.input),
+            (SUBSCRIPTION_STOP, 7, "", &[][..])
+        );
+    }
+
+    #[test]
+    fn parse_invalid_request() {
+        // Too short for the header
+        assert!(Request::parse(&[]).is_none());
+        assert!(Request::parse(&[QUERY, 1, 0, 0]).is_none());
+        assert!(Request::parse(&[QUERY, 1, 0, 0, 0, 5]).is_none());
+
+        // The key is longer than the frame
+        let mut frame = request(QUERY, 1, "abc", &[]);
+        frame.truncate(frame.len() - 1);
+        assert!(Request::parse(&frame).is_none());
+
+        // The key isn't UTF-8
+        let mut frame = request(QUERY, 1, "a", &[]);
+        *frame.last_mut().unwrap() = 0xff;
+        assert!(Request::parse(&frame).is_none());
+
+        // The id can still be read to respond with an error.
+        assert_eq!(request_id(&frame), Some(1));
+        assert_eq!(request_id(&[QUERY, 1, 0]), None);
+    }
+
+    #[test]
+    fn error_frames() {
+        let frame = error_frame(
+            9,
+            JsonRPCError {
+                code: 404,
+                message: "not found".into(),
+                data: None,
+            },
+        )
+        .0;
+        assert_eq!(&frame[..5], &[ERROR, 9, 0, 0, 0]);
+
+        let err: serde_json::Value = serde_json::from_slice(&frame[5..]).unwrap();
+        assert_eq!(err["code"], 404);
+        assert_eq!(err["message"], "not found");
+
+        // A subscription's errors can be dropped like it's events, but not it's completion.
+        let err = |id| {
+            error_frame(
+                id,
+                JsonRPCError {
+                    code: 500,
+                    message: "error".into(),
+                    data: None,
+                },
+            )
+        };
+        assert!(err(9).is_event_of(&Frame::new(EVENT, 9)));
+        assert!(!err(8).is_event_of(&Frame::new(EVENT, 9)));
+        assert!(!Frame::new(COMPLETE, 9).is_event_of(&Frame::new(EVENT, 9)));
+    }
+
+    #[test]
+    fn finished_subscriptions() {
+        let mut subscriptions = Subscriptions::default();
+        let (running, _running_rx) = oneshot::channel();
+        let (finished, finished_rx) = oneshot::channel();
+        subscriptions.0.insert(1, running);
+        subscriptions.0.insert(2, finished);
+        drop(finished_rx);
+
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions.drain().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[derive(Type)]
+    enum Error {}
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            match self {}
+        }
+    }
+
+    fn procedures() -> Procedures<()> {
+        let (procedures, _) = <Router>::new()
+            .procedure(
+                "echo",
+                Procedure::builder::<Error>()
+                    .with(rspc_binario::binario())
+                    .query(|_, input: String| async move { Ok(input) }),
+            )
+            .procedure(
+                "small",
+                Procedure::builder::<Error>()
+                    .with(crate::limits(crate::Limits::new().body_size(16)))
+                    .with(rspc_binario::binario())
+                    .query(|_, input: String| async move { Ok(input) }),
+            )
+            .procedure(
+                "repeat",
+                Procedure::builder::<Error>()
+                    .with(rspc_binario::binario())
+                    .query(|_, input: String| async move {
+                        Ok(rspc::Stream(futures::stream::iter([
+                            input.clone(),
+                            input.clone(),
+                            input,
+                        ])))
+                    }),
+            )
+            .build()
+            .unwrap();
+        procedures
+    }
+
+    async fn encode(value: &str) -> Vec<u8> {
+        let mut buf = Vec::new();
+        binario::encode(&value.to_string(), &mut buf).await.unwrap();
+        buf
+    }
+
+    async fn send(outbox: &Arc<Outbox<Outgoing>>, subscriptions: &mut Subscriptions, frame: &[u8]) {
+        handle_binario(
+            (),
+            frame,
+            &procedures(),
+            &Config::new(),
+            outbox,
+            subscriptions,
+            10,
+        )
+        .await;
+    }
+
+    // The kind, id and payload of the next frame.
+    async fn pop(outbox: &Outbox<Outgoing>) -> (u8, u32, Vec<u8>) {
+        #[allow(irrefutable_let_patterns)]
+        let Outgoing::Binario(Frame(frame)) = outbox.pop().await
+        else {
+            unreachable!("expected a binario frame");
+        };
+        (frame[0], request_id(&frame).unwrap(), frame[5..].to_vec())
+    }
+
+    async fn decode(payload: Vec<u8>) -> String {
+        binario::decode::<String, _>(payload.as_slice())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn query() {
+        let outbox = Arc::new(Outbox::<Outgoing>::new(10, Overflow::Backpressure));
+        let input = encode("hello").await;
+        send(
+            &outbox,
+            &mut Default::default(),
+            &request(QUERY, 1, "echo", &input),
+        )
+        .await;
+
+        let (kind, id, payload) = pop(&outbox).await;
+        assert_eq!((kind, id), (RESPONSE, 1));
+        assert_eq!(decode(payload).await, "hello");
+
+        send(
+            &outbox,
+            &mut Default::default(),
+            &request(QUERY, 2, "missing", &input),
+        )
+        .await;
+        let (kind, id, payload) = pop(&outbox).await;
+        assert_eq!((kind, id), (ERROR, 2));
+        let err: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(err["code"], 404);
+    }
+
+    #[tokio::test]
+    async fn subscription_completes() {
+        let outbox = Arc::new(Outbox::<Outgoing>::new(10, Overflow::Backpressure));
+        let mut subscriptions = Subscriptions::default();
+        let input = encode("a").await;
+        send(
+            &outbox,
+            &mut subscriptions,
+            &request(SUBSCRIPTION, 3, "repeat", &input),
+        )
+        .await;
+
+        for _ in 0..3 {
+            let (kind, id, payload) = pop(&outbox).await;
+            assert_eq!((kind, id), (EVENT, 3));
+            assert_eq!(decode(payload).await, "a");
+        }
+        assert_eq!(pop(&outbox).await, (COMPLETE, 3, vec![]));
+    }
+
+    #[tokio::test]
+    async fn subscription_overflow() {
+        let outbox = Arc::new(Outbox::<Outgoing>::new(1, Overflow::CloseSubscription));
+        let mut subscriptions = Subscriptions::default();
+        let input = encode("a").await;
+        send(
+            &outbox,
+            &mut subscriptions,
+            &request(SUBSCRIPTION, 4, "repeat", &input),
+        )
+        .await;
+        // Nothing is read until the subscription has stopped.
+        while subscriptions.len() != 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+
+        assert_eq!(pop(&outbox).await.0, EVENT);
+        let (kind, id, payload) = pop(&outbox).await;
+        assert_eq!((kind, id), (ERROR, 4));
+        let err: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(err["code"], 503);
+        assert_eq!(pop(&outbox).await, (COMPLETE, 4, vec![]));
+        assert!(outbox.try_pop().is_none());
+    }
+
+    #[tokio::test]
+    async fn malformed_frames() {
+        let outbox = Arc::new(Outbox::<Outgoing>::new(10, Overflow::Backpressure));
+
+        // The key length is missing.
+        send(&outbox, &mut Default::default(), &[QUERY, 5, 0, 0, 0]).await;
+        let (kind, id, payload) = pop(&outbox).await;
+        assert_eq!((kind, id), (ERROR, 5));
+        let err: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(err["code"], 400);
+
+        // Too short to contain an id.
+        send(&outbox, &mut Default::default(), &[QUERY]).await;
+        assert_eq!(pop(&outbox).await.0, ERROR);
+
+        send(
+            &outbox,
+            &mut Default::default(),
+            &request(9, 6, "echo", &[]),
+        )
+        .await;
+        assert_eq!(pop(&outbox).await.0, ERROR);
+    }
+
+    #[tokio::test]
+    async fn procedure_limits() {
+        let outbox = Arc::new(Outbox::<Outgoing>::new(10, Overflow::Backpressure));
+
+        let input = encode("a").await;
+        send(
+            &outbox,
+            &mut Default::default(),
+            &request(QUERY, 1, "small", &input),
+        )
+        .await;
+        assert_eq!(pop(&outbox).await.0, RESPONSE);
+
+        let input = encode("this is too long for the limit").await;
+        send(
+            &outbox,
+            &mut Default::default(),
+            &request(QUERY, 2, "small", &input),
+        )
+        .await;
+        let (kind, id, payload) = pop(&outbox).await;
+        assert_eq!((kind, id), (ERROR, 2));
+        let err: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(err["code"], 413);
+        assert_eq!(err["data"]["limit"], "bodySize");
+    }
+}
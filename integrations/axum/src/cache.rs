@@ -0,0 +1,205 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::Hasher,
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+};
+use rspc::Extension;
+use rspc_procedure::Procedures;
+
+/// How the response to a `GET` request for a query can be cached by browsers and CDNs.
+///
+/// Apply it to a procedure with [`cache`]. Successful responses are sent with a `Cache-Control` header built from the policy, `Vary: Accept` and a strong `ETag` of the output.
+/// A request whose `If-None-Match` matches the `ETag` is answered with a `304 Not Modified` and no body.
+///
+/// Headers set using [`ResponseHeaders`](crate::ResponseHeaders) take precedence over the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy {
+    max_age: Duration,
+    public: bool,
+    stale_while_revalidate: Option<Duration>,
+}
+
+impl CachePolicy {
+    /// Allow the response to be reused for `max_age`.
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            public: false,
+            stale_while_revalidate: None,
+        }
+    }
+
+    /// Allow shared caches, like a CDN, to store the response. Defaults to `false` so only the user's browser caches it.
+    ///
+    /// Only enable this when the output doesn't depend on who made the request.
+    pub fn public(mut self, public: bool) -> Self {
+        self.public = public;
+        self
+    }
+
+    /// How long a stale response can still be used while it's revalidated in the background. Disabled by default.
+    pub fn stale_while_revalidate(mut self, stale_while_revalidate: Option<Duration>) -> Self {
+        self.stale_while_revalidate = stale_while_revalidate;
+        self
+    }
+
+    // Get the policy for a procedure, if it has one.
+    pub(crate) fn resolve<TCtx>(procedures: &Procedures<TCtx>, path: &str) -> Option<Self> {
+        procedures
+            .state()
+            .get::<ProcedureCache>()
+            .and_then(|cache| cache.0.get(path))
+            .copied()
+    }
+
+    fn cache_control(&self) -> HeaderValue {
+        let mut value = format!(
+            "{}, max-age={}",
+            if self.public { "public" } else { "private" },
+            self.max_age.as_secs()
+        );
+        if let Some(swr) = self.stale_while_revalidate {
+            value.push_str(&format!(", stale-while-revalidate={}", swr.as_secs()));
+        }
+        HeaderValue::try_from(value).expect("cache control is always a valid header")
+    }
+
+    // Respond with the output of a query, or a `304 Not Modified` if the client already has it.
+    //
+    // The output must be fully serialized before this is called so the `ETag` is known before any headers are sent.
+    pub(crate) fn response(
+        &self,
+        request: &HeaderMap,
+        mime: &'static str,
+        body: Vec<u8>,
+    ) -> Response<Body> {
+        let etag = etag(&body);
+        let mut response = match not_modified(request, &etag) {
+            true => StatusCode::NOT_MODIFIED.into_response(),
+            false => ([(header::CONTENT_TYPE, mime)], body).into_response(),
+        };
+
+        let headers = response.headers_mut();
+        headers.insert(
+            header::ETAG,
+            HeaderValue::try_from(etag).expect("etags are always a valid header"),
+        );
+        headers.insert(header::CACHE_CONTROL, self.cache_control());
+        headers.insert(header::VARY, HeaderValue::from_static("Accept"));
+        response
+    }
+}
+
+// If any of the `If-None-Match` headers match the `ETag`. Weak comparison is used as required for `GET` requests.
+fn not_modified(request: &HeaderMap, etag: &str) -> bool {
+    request
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+// A strong validator for the serialized output. `DefaultHasher::new` isn't randomly seeded so every server running the same build agrees on it.
+fn etag(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(body);
+    format!("\"{:x}-{:016x}\"", body.len(), hasher.finish())
+}
+
+/// Set the [`CachePolicy`] for a query.
+///
+/// This only applies to `GET` requests. Batches, websockets and streamed responses are never cached.
+pub fn cache<TCtx, TInput, TResult>(policy: CachePolicy) -> Extension<TCtx, TInput, TResult> {
+    Extension::new().setup(move |state, meta| {
+        state
+            .get_mut_or_init::<ProcedureCache>(Default::default)
+            .0
+            .insert(meta.name().to_string(), policy);
+    })
+}
+
+// The per-procedure cache policies which are stored into rspc.
+#[derive(Default)]
+struct ProcedureCache(HashMap<String, CachePolicy>);
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn if_none_match(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(
+                header::IF_NONE_MATCH,
+                HeaderValue::try_from(*value).unwrap(),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn etags() {
+        assert_eq!(etag(b"hello"), etag(b"hello"));
+        assert_ne!(etag(b"hello"), etag(b"world"));
+        assert!(etag(b"hello").starts_with("\"5-"));
+        assert!(etag(b"hello").ends_with('"'));
+    }
+
+    #[test]
+    fn if_none_match_parsing() {
+        let etag = etag(b"hello");
+
+        assert!(!not_modified(&HeaderMap::new(), &etag));
+        assert!(not_modified(&if_none_match(&[&etag]), &etag));
+        assert!(not_modified(&if_none_match(&[&format!("W/{etag}")]), &etag));
+        assert!(not_modified(
+            &if_none_match(&[&format!("\"other\", {etag}")]),
+            &etag
+        ));
+        assert!(not_modified(&if_none_match(&["\"other\"", &etag]), &etag));
+        assert!(not_modified(&if_none_match(&["*"]), &etag));
+        assert!(!not_modified(&if_none_match(&["\"other\""]), &etag));
+        // The quotes are part of the tag.
+        assert!(!not_modified(
+            &if_none_match(&[etag.trim_matches('"')]),
+            &etag
+        ));
+    }
+
+    #[test]
+    fn responses() {
+        let policy = CachePolicy::new(Duration::from_secs(60))
+            .public(true)
+            .stale_while_revalidate(Some(Duration::from_secs(30)));
+
+        let response = policy.response(&HeaderMap::new(), "application/json", b"{}".to_vec());
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(etag, super::etag(b"{}"));
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=60, stale-while-revalidate=30"
+        );
+        assert_eq!(response.headers()[header::VARY], "Accept");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+
+        let mut request = HeaderMap::new();
+        request.insert(header::IF_NONE_MATCH, HeaderValue::try_from(&etag).unwrap());
+        let response = policy.response(&request, "application/json", b"{}".to_vec());
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        assert!(response.headers().get(header::CONTENT_TYPE).is_none());
+    }
+}
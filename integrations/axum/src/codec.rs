@@ -0,0 +1,397 @@
+use axum::http::{header, HeaderMap, HeaderValue};
+use rspc_procedure::{DynOutput, Procedure, ProcedureError, ProcedureStream};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    jsonrpc::{self, JsonRPCError, RequestId, ResponseInnerRef, ResponseRef},
+    jsonrpc_exec::to_error,
+    limits::{Exceeded, Limits},
+};
+
+// The formats requests and responses can be encoded in.
+//
+// Over HTTP this is picked using the `Content-Type` and `Accept` headers and for websockets it's negotiated using the subprotocol.
+// JSON is used when nothing else is requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Codec {
+    Json,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+// Subprotocols are only used by websockets.
+#[cfg_attr(not(feature = "ws"), allow(dead_code))]
+impl Codec {
+    pub(crate) const ALL: &'static [Self] = &[
+        Self::Json,
+        #[cfg(feature = "msgpack")]
+        Self::MessagePack,
+        #[cfg(feature = "cbor")]
+        Self::Cbor,
+    ];
+
+    pub(crate) fn mime(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => "application/msgpack",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "application/cbor",
+        }
+    }
+
+    pub(crate) fn subprotocol(&self) -> &'static str {
+        match self {
+            Self::Json => "rspc.json",
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => "rspc.msgpack",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "rspc.cbor",
+        }
+    }
+
+    fn from_mime(mime: &str) -> Option<Self> {
+        match mime.split(';').next().unwrap_or_default().trim() {
+            "application/json" => Some(Self::Json),
+            #[cfg(feature = "msgpack")]
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Self::MessagePack)
+            }
+            #[cfg(feature = "cbor")]
+            "application/cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    // The format of the request body. `None` if the client sent a format we don't support.
+    pub(crate) fn from_content_type(headers: &HeaderMap) -> Option<Self> {
+        match headers.get(header::CONTENT_TYPE) {
+            Some(v) => Self::from_mime(v.to_str().ok()?),
+            // Clients have historically not set a `Content-Type`.
+            None => Some(Self::Json),
+        }
+    }
+
+    // The format for the response. This is the first supported format from the `Accept` header.
+    pub(crate) fn from_accept(headers: &HeaderMap) -> Self {
+        headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .find_map(Self::from_mime)
+            .unwrap_or(Self::Json)
+    }
+
+    pub(crate) fn from_subprotocol(protocol: Option<&HeaderValue>) -> Self {
+        protocol
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| Self::ALL.iter().find(|c| c.subprotocol() == v))
+            .copied()
+            .unwrap_or(Self::Json)
+    }
+
+    pub(crate) fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(|err| err.to_string()),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|err| err.to_string()),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => cbor4ii::serde::to_vec(Vec::new(), value).map_err(|err| err.to_string()),
+        }
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(|err| err.to_string()),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => rmp_serde::from_slice(bytes).map_err(|err| err.to_string()),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => cbor4ii::serde::from_slice(bytes).map_err(|err| err.to_string()),
+        }
+    }
+
+    // Execute a procedure, deserializing it's input directly from the request body.
+    pub(crate) fn exec<TCtx>(
+        &self,
+        procedure: &Procedure<TCtx>,
+        ctx: TCtx,
+        input: &[u8],
+    ) -> ProcedureStream {
+        if input.is_empty() {
+            return procedure.exec_with_deserializer(ctx, Value::Null);
+        }
+
+        match self {
+            Self::Json => procedure
+                .exec_with_deserializer(ctx, &mut serde_json::Deserializer::from_slice(input)),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => procedure
+                .exec_with_deserializer(ctx, &mut rmp_serde::Deserializer::from_read_ref(input)),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => procedure.exec_with_deserializer(
+                ctx,
+                &mut cbor4ii::serde::Deserializer::new(cbor4ii::core::utils::SliceReader::new(
+                    input,
+                )),
+            ),
+        }
+    }
+
+    pub(crate) fn check(&self, limits: &Limits, input: &[u8]) -> Result<(), Exceeded> {
+        if input.is_empty() {
+            return Ok(());
+        }
+
+        match self {
+            Self::Json => limits.check(&mut serde_json::Deserializer::from_slice(input)),
+            #[cfg(feature = "msgpack")]
+            Self::MessagePack => limits.check(&mut rmp_serde::Deserializer::from_read_ref(input)),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => limits.check(&mut cbor4ii::serde::Deserializer::new(
+                cbor4ii::core::utils::SliceReader::new(input),
+            )),
+        }
+    }
+
+    // Encode the result of a procedure, serializing it's output directly into the response.
+    pub(crate) fn encode_result(
+        &self,
+        id: &RequestId,
+        result: Option<Result<DynOutput<'_>, ProcedureError>>,
+    ) -> Vec<u8> {
+        self.try_encode_result(id, result).unwrap_or_else(|_err| {
+            // #[cfg(feature = "tracing")]
+            // tracing::error!("Error serializing response: {}", _err);
+
+            self.encode(&jsonrpc::Response {
+                jsonrpc: "2.0",
+                id: id.clone(),
+                result: jsonrpc::ResponseInner::Error(JsonRPCError {
+                    code: 500,
+                    message: "error serializing response".into(),
+                    data: None,
+                }),
+            })
+            .expect("error responses are always serializable")
+        })
+    }
+
+    // Same as `encode_result` but the caller decides how to respond if the output can't be serialized.
+    pub(crate) fn try_encode_result(
+        &self,
+        id: &RequestId,
+        result: Option<Result<DynOutput<'_>, ProcedureError>>,
+    ) -> Result<Vec<u8>, String> {
+        match result {
+            Some(Ok(output)) => match output.as_serialize() {
+                Some(output) => self.encode(&ResponseRef {
+                    jsonrpc: "2.0",
+                    id,
+                    result: ResponseInnerRef::Response(output),
+                }),
+                None => Err("the procedure returned a value which can't be serialized".into()),
+            },
+            Some(Err(err)) => self.encode(&ResponseRef::<()> {
+                jsonrpc: "2.0",
+                id,
+                result: ResponseInnerRef::Error(to_error(err)),
+            }),
+            None => self.encode(&ResponseRef {
+                jsonrpc: "2.0",
+                id,
+                result: ResponseInnerRef::Response(()),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        http::Request,
+    };
+    use rspc::Router;
+    use serde::Deserialize;
+    use serde_json::json;
+    use specta::Type;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn headers(name: header::HeaderName, values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(&name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn negotiation() {
+        assert_eq!(
+            Codec::from_content_type(&HeaderMap::new()),
+            Some(Codec::Json)
+        );
+        assert_eq!(
+            Codec::from_content_type(&headers(
+                header::CONTENT_TYPE,
+                &["application/json; charset=utf-8"]
+            )),
+            Some(Codec::Json)
+        );
+        assert_eq!(
+            Codec::from_content_type(&headers(header::CONTENT_TYPE, &["text/plain"])),
+            None
+        );
+
+        assert_eq!(Codec::from_accept(&HeaderMap::new()), Codec::Json);
+        assert_eq!(
+            Codec::from_accept(&headers(header::ACCEPT, &["text/html, */*"])),
+            Codec::Json
+        );
+        assert_eq!(Codec::from_subprotocol(None), Codec::Json);
+        assert_eq!(
+            Codec::from_subprotocol(Some(&HeaderValue::from_static("unknown"))),
+            Codec::Json
+        );
+
+        #[cfg(feature = "msgpack")]
+        {
+            assert_eq!(
+                Codec::from_content_type(&headers(
+                    header::CONTENT_TYPE,
+                    &["application/x-msgpack"]
+                )),
+                Some(Codec::MessagePack)
+            );
+            // The first supported format wins.
+            assert_eq!(
+                Codec::from_accept(&headers(
+                    header::ACCEPT,
+                    &["text/html", "application/msgpack, application/json"]
+                )),
+                Codec::MessagePack
+            );
+            assert_eq!(
+                Codec::from_subprotocol(Some(&HeaderValue::from_static("rspc.msgpack"))),
+                Codec::MessagePack
+            );
+        }
+
+        #[cfg(feature = "cbor")]
+        {
+            assert_eq!(
+                Codec::from_accept(&headers(header::ACCEPT, &["application/cbor"])),
+                Codec::Cbor
+            );
+            assert_eq!(
+                Codec::from_subprotocol(Some(&HeaderValue::from_static("rspc.cbor"))),
+                Codec::Cbor
+            );
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize, Type)]
+    struct Point {
+        x: i32,
+        label: String,
+    }
+
+    #[derive(Type)]
+    enum Error {}
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            match self {}
+        }
+    }
+
+    fn router() -> axum::Router {
+        let (procedures, _) = <Router>::new()
+            .procedure(
+                "flip",
+                rspc::Procedure::builder::<Error>().mutation(|_, point: Point| async move {
+                    Ok(Point {
+                        x: -point.x,
+                        label: point.label,
+                    })
+                }),
+            )
+            .build()
+            .unwrap();
+        axum::Router::new().nest("/rspc", crate::endpoint(procedures, || ()))
+    }
+
+    // Call the procedure with the input and output in the codec's format.
+    async fn flip(codec: Codec) -> Point {
+        let point = Point {
+            x: 1,
+            label: "a".into(),
+        };
+        let response = router()
+            .oneshot(
+                Request::post("/rspc/flip")
+                    .header(header::CONTENT_TYPE, codec.mime())
+                    .header(header::ACCEPT, codec.mime())
+                    .body(Body::from(codec.encode(&point).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], codec.mime());
+
+        #[derive(Deserialize)]
+        struct Response {
+            result: Result,
+        }
+
+        #[derive(Deserialize)]
+        struct Result {
+            data: Point,
+        }
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        codec.decode::<Response>(&body).unwrap().result.data
+    }
+
+    #[tokio::test]
+    async fn codecs() {
+        for codec in Codec::ALL {
+            let value = json!({ "a": [1, "b", 2.5], "c": { "d": true } });
+            assert_eq!(
+                codec
+                    .decode::<Value>(&codec.encode(&value).unwrap())
+                    .unwrap(),
+                value
+            );
+
+            assert_eq!(
+                flip(*codec).await,
+                Point {
+                    x: -1,
+                    label: "a".into()
+                }
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn unsupported_content_type() {
+        let response = router()
+            .oneshot(
+                Request::post("/rspc/flip")
+                    .header(header::CONTENT_TYPE, "text/plain")
+                    .body(Body::from("{}"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 415);
+    }
+}
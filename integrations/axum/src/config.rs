@@ -0,0 +1,258 @@
+#[cfg(feature = "ws")]
+use std::any::Any;
+use std::time::Duration;
+
+#[cfg(feature = "ws")]
+use crate::{Connection, Overflow};
+use crate::{Limits, PersistedQueries, Shutdown};
+
+/// Configure the behaviour of [`endpoint_with_config`](crate::endpoint_with_config).
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub(crate) max_batch_size: usize,
+    pub(crate) limits: Limits,
+    pub(crate) problem_details: bool,
+    pub(crate) sse_keep_alive: Duration,
+    pub(crate) replay_buffer: Option<usize>,
+    pub(crate) replay_retention: Duration,
+    pub(crate) replay_max_retained: usize,
+    pub(crate) shutdown: Option<Shutdown>,
+    pub(crate) persisted_queries: Option<PersistedQueries>,
+    #[cfg(feature = "ws")]
+    pub(crate) ws_ping_interval: Option<Duration>,
+    #[cfg(feature = "ws")]
+    pub(crate) ws_idle_timeout: Option<Duration>,
+    #[cfg(feature = "ws")]
+    pub(crate) ws_send_buffer: usize,
+    #[cfg(feature = "ws")]
+    pub(crate) ws_overflow: Overflow,
+    #[cfg(feature = "ws")]
+    pub(crate) ws_max_subscriptions: usize,
+    #[cfg(feature = "ws")]
+    pub(crate) on_connect: Option<ConnectionHook>,
+    #[cfg(feature = "ws")]
+    pub(crate) on_disconnect: Option<ConnectionHook>,
+    #[cfg(feature = "ws")]
+    pub(crate) ws_context_fn: Option<Hook<dyn Any + Send + Sync>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 100,
+            limits: Limits::default(),
+            problem_details: false,
+            sse_keep_alive: Duration::from_secs(15),
+            replay_buffer: None,
+            replay_retention: Duration::from_secs(30),
+            replay_max_retained: 100,
+            shutdown: None,
+            persisted_queries: None,
+            #[cfg(feature = "ws")]
+            ws_ping_interval: Some(Duration::from_secs(30)),
+            #[cfg(feature = "ws")]
+            ws_idle_timeout: Some(Duration::from_secs(60)),
+            #[cfg(feature = "ws")]
+            ws_send_buffer: 128,
+            #[cfg(feature = "ws")]
+            ws_overflow: Overflow::default(),
+            #[cfg(feature = "ws")]
+            ws_max_subscriptions: 100,
+            #[cfg(feature = "ws")]
+            on_connect: None,
+            #[cfg(feature = "ws")]
+            on_disconnect: None,
+            #[cfg(feature = "ws")]
+            ws_context_fn: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The maximum number of requests which can be sent in a single batch.
+    ///
+    /// Larger batches are rejected with a `413 Payload Too Large` and an error for every request. Defaults to `100`.
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    /// The default [`Limits`] for every procedure.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Respond to failed HTTP requests with an [RFC 9457](https://www.rfc-editor.org/rfc/rfc9457) `application/problem+json` body instead of a JSON-RPC response.
+    ///
+    /// This is useful when the endpoint is called by something other than an rspc client. Batches and websockets always use JSON-RPC. Defaults to `false`.
+    pub fn problem_details(mut self, problem_details: bool) -> Self {
+        self.problem_details = problem_details;
+        self
+    }
+
+    /// How often a keep-alive comment is sent on an idle Server-Sent Events stream. Defaults to 15 seconds.
+    pub fn sse_keep_alive(mut self, sse_keep_alive: Duration) -> Self {
+        self.sse_keep_alive = sse_keep_alive;
+        self
+    }
+
+    /// Make subscriptions resumable by keeping the last `n` events of each one. Disabled by default.
+    ///
+    /// Every event is given an id and subscriptions keep running for [`Config::replay_retention`] after their client disconnects.
+    /// A websocket client which resubscribes with the `lastEventId` of the last event it received, or a Server-Sent Events client reconnecting with `Last-Event-ID`, is sent the events it missed before the live ones.
+    ///
+    /// A client which fell further behind than the buffer, or tries to resume a subscription which has stopped, is sent an error with the code `410` before the events it can still receive.
+    ///
+    /// A subscription can only be resumed with the same input and the same `Authorization` and `Cookie` headers it was started with.
+    /// The subscriptions a client has left running count towards [`Config::ws_max_subscriptions`] for clients which send either header.
+    pub fn replay_buffer(mut self, replay_buffer: Option<usize>) -> Self {
+        self.replay_buffer = replay_buffer;
+        self
+    }
+
+    /// How long a resumable subscription keeps running after its client disconnects. Defaults to 30 seconds.
+    pub fn replay_retention(mut self, replay_retention: Duration) -> Self {
+        self.replay_retention = replay_retention;
+        self
+    }
+
+    /// The maximum number of resumable subscriptions a client can leave running after disconnecting. Defaults to `100`.
+    ///
+    /// Clients are told apart using their `Authorization` and `Cookie` headers and all clients sending neither share this limit.
+    /// Once it's reached the subscription left running the longest is stopped. Server-Sent Events clients which send either header can't start more subscriptions while they are at the limit.
+    pub fn replay_max_retained(mut self, replay_max_retained: usize) -> Self {
+        self.replay_max_retained = replay_max_retained;
+        self
+    }
+
+    /// Gracefully shut down the endpoint using a [`Shutdown`] handle.
+    pub fn shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Allow procedures to be called using a hash instead of their name, optionally rejecting anything else. Disabled by default.
+    pub fn persisted_queries(mut self, persisted_queries: PersistedQueries) -> Self {
+        self.persisted_queries = Some(persisted_queries);
+        self
+    }
+
+    /// How often a ping is sent on an idle websocket. Defaults to 30 seconds.
+    ///
+    /// This stops proxies from closing the connection and allows [`Config::ws_idle_timeout`] to detect dead connections.
+    #[cfg(feature = "ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+    pub fn ws_ping_interval(mut self, ws_ping_interval: Option<Duration>) -> Self {
+        self.ws_ping_interval = ws_ping_interval;
+        self
+    }
+
+    /// Close a websocket if nothing, including a pong, has been received from the client for this long. Defaults to 60 seconds.
+    #[cfg(feature = "ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+    pub fn ws_idle_timeout(mut self, ws_idle_timeout: Option<Duration>) -> Self {
+        self.ws_idle_timeout = ws_idle_timeout;
+        self
+    }
+
+    /// The maximum number of subscription events which can be waiting to be sent on a websocket. Defaults to `128`.
+    ///
+    /// When a client isn't reading fast enough the buffer fills up and [`Config::ws_overflow`] decides what happens.
+    #[cfg(feature = "ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+    pub fn ws_send_buffer(mut self, ws_send_buffer: usize) -> Self {
+        self.ws_send_buffer = ws_send_buffer;
+        self
+    }
+
+    /// What to do when the websocket send buffer is full. Defaults to [`Overflow::Backpressure`].
+    #[cfg(feature = "ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+    pub fn ws_overflow(mut self, ws_overflow: Overflow) -> Self {
+        self.ws_overflow = ws_overflow;
+        self
+    }
+
+    /// The maximum number of subscriptions a single websocket can have running at once. Defaults to `100`.
+    #[cfg(feature = "ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+    pub fn ws_max_subscriptions(mut self, ws_max_subscriptions: usize) -> Self {
+        self.ws_max_subscriptions = ws_max_subscriptions;
+        self
+    }
+
+    /// Called when a websocket connection is accepted.
+    ///
+    /// This runs after the context function so any values it stored on the [`Connection`] are available.
+    #[cfg(feature = "ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+    pub fn on_connect(mut self, on_connect: impl Fn(&Connection) + Send + Sync + 'static) -> Self {
+        self.on_connect = Some(Hook(std::sync::Arc::new(on_connect)));
+        self
+    }
+
+    /// Called when a websocket connection is closed. This can be used to release any resources for the connection.
+    #[cfg(feature = "ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+    pub fn on_disconnect(
+        mut self,
+        on_disconnect: impl Fn(&Connection) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_disconnect = Some(Hook(std::sync::Arc::new(on_disconnect)));
+        self
+    }
+
+    /// Derive the context of each websocket message from a context built once when the connection is opened.
+    ///
+    /// By default the context function is run again for every message. With this it's only run when the connection is upgraded, Eg. to authenticate the user, and `ws_context_fn` is called with that context for every message.
+    ///
+    /// `TCtx` must be the context type of the endpoint this is used with.
+    #[cfg(feature = "ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "ws")))]
+    pub fn ws_context_fn<TCtx: Send + Sync + 'static>(
+        mut self,
+        ws_context_fn: impl Fn(&TCtx) -> TCtx + Send + Sync + 'static,
+    ) -> Self {
+        let ws_context_fn: WsContextFn<TCtx> = std::sync::Arc::new(ws_context_fn);
+        self.ws_context_fn = Some(Hook(std::sync::Arc::new(ws_context_fn)));
+        self
+    }
+
+    // The function set with `Config::ws_context_fn` if it's for contexts of type `TCtx`.
+    #[cfg(feature = "ws")]
+    pub(crate) fn ws_context_fn_for<TCtx: 'static>(&self) -> Option<WsContextFn<TCtx>> {
+        self.ws_context_fn
+            .as_ref()
+            .and_then(|f| f.0.downcast_ref::<WsContextFn<TCtx>>())
+            .cloned()
+    }
+}
+
+#[cfg(feature = "ws")]
+pub(crate) type ConnectionHook = Hook<dyn Fn(&Connection) + Send + Sync>;
+
+#[cfg(feature = "ws")]
+pub(crate) type WsContextFn<TCtx> = std::sync::Arc<dyn Fn(&TCtx) -> TCtx + Send + Sync>;
+
+// A callback on the `Config`. This exists so `Config` can implement `Debug`.
+#[cfg(feature = "ws")]
+pub(crate) struct Hook<F: ?Sized>(pub(crate) std::sync::Arc<F>);
+
+#[cfg(feature = "ws")]
+impl<F: ?Sized> Clone for Hook<F> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+#[cfg(feature = "ws")]
+impl<F: ?Sized> std::fmt::Debug for Hook<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Hook")
+    }
+}
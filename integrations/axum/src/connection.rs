@@ -0,0 +1,77 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use axum::http::Extensions;
+
+/// A websocket connection.
+///
+/// This is inserted into the request's extensions for websocket connections so it can be extracted in your context function with [`Extension`](axum::Extension).
+///
+/// The context function is run once when the connection is upgraded and then again for every message.
+/// Values stored on the connection during the upgrade, such as the authenticated user, can be used to derive the context for each message without redoing the work.
+/// Alternatively [`Config::ws_context_fn`](crate::Config::ws_context_fn) derives each message's context from the one built during the upgrade.
+#[derive(Clone)]
+pub struct Connection(Arc<Inner>);
+
+struct Inner {
+    id: ConnectionId,
+    extensions: Mutex<Extensions>,
+}
+
+impl Connection {
+    pub(crate) fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        Self(Arc::new(Inner {
+            id: ConnectionId(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            extensions: Default::default(),
+        }))
+    }
+
+    /// A unique identifier for the connection.
+    pub fn id(&self) -> ConnectionId {
+        self.0.id
+    }
+
+    /// Store a value on the connection, replacing any existing value of the same type.
+    pub fn insert<T: Clone + Send + Sync + 'static>(&self, value: T) {
+        self.0
+            .extensions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(value);
+    }
+
+    /// Get a value previously stored on the connection.
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.0
+            .extensions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get::<T>()
+            .cloned()
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("id", &self.0.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A unique identifier for a websocket [`Connection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectionId(u64);
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
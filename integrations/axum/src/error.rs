@@ -0,0 +1,164 @@
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use rspc_procedure::ProcedureError;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    codec::Codec,
+    jsonrpc::{self, JsonRPCError, RequestId, ResponseInner},
+};
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+// The HTTP status for an error from a procedure.
+pub(crate) fn status(err: &ProcedureError) -> StatusCode {
+    StatusCode::from_u16(err.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// An error from an HTTP request.
+//
+// This is a JSON-RPC response by default or a RFC 9457 problem if `Config::problem_details` is enabled.
+pub(crate) fn response(
+    status: StatusCode,
+    error: JsonRPCError,
+    accept: Codec,
+    problem_details: bool,
+) -> Response<Body> {
+    let (content_type, body) = match problem_details {
+        true => (
+            PROBLEM_JSON,
+            serde_json::to_vec(&Problem {
+                ty: "about:blank",
+                title: status.canonical_reason().unwrap_or_default(),
+                status: status.as_u16(),
+                detail: &error.message,
+                data: error.data.as_ref(),
+            })
+            .unwrap_or_else(|_| b"{}".to_vec()),
+        ),
+        false => (
+            accept.mime(),
+            accept
+                .encode(&jsonrpc::Response {
+                    jsonrpc: "2.0",
+                    id: RequestId::Null,
+                    result: ResponseInner::Error(error),
+                })
+                .unwrap_or_else(|_| b"[]".to_vec()),
+        ),
+    };
+
+    (status, [(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+// Convert a rejection from the context function into a JSON-RPC error for batches and websockets.
+//
+// Plain text bodies become the message and JSON bodies are forwarded as the `detail` of the data.
+pub(crate) async fn rejection(response: Response<Body>) -> JsonRPCError {
+    let status = response.status();
+    let body = to_bytes(response.into_body(), 64 * 1024)
+        .await
+        .unwrap_or_default();
+
+    let (message, detail) = match serde_json::from_slice::<Value>(&body) {
+        Ok(detail) => (None, Some(detail)),
+        Err(_) => (Some(String::from_utf8_lossy(&body).into_owned()), None),
+    };
+
+    let mut data = json!({ "~rspc": true });
+    if let Some(detail) = detail {
+        data["detail"] = detail;
+    }
+
+    JsonRPCError {
+        code: status.as_u16() as i32,
+        message: message
+            .filter(|message| !message.is_empty())
+            .unwrap_or_else(|| "error creating context".into()),
+        data: Some(data),
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc9457
+#[derive(Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    ty: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<&'a Value>,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    async fn reject(status: StatusCode, body: &'static str) -> JsonRPCError {
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = status;
+        rejection(response).await
+    }
+
+    #[tokio::test]
+    async fn rejections() {
+        let err = reject(StatusCode::UNAUTHORIZED, "missing session").await;
+        assert_eq!((err.code, &*err.message), (401, "missing session"));
+        assert_eq!(err.data, Some(json!({ "~rspc": true })));
+
+        let err = reject(StatusCode::FORBIDDEN, r#"{"reason":"banned"}"#).await;
+        assert_eq!((err.code, &*err.message), (403, "error creating context"));
+        assert_eq!(
+            err.data,
+            Some(json!({ "~rspc": true, "detail": { "reason": "banned" } }))
+        );
+    }
+
+    async fn body(response: Response<Body>) -> Value {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn responses() {
+        let error = || JsonRPCError {
+            code: 404,
+            message: "not found".into(),
+            data: Some(json!({ "~rspc": true })),
+        };
+
+        let response = response(StatusCode::NOT_FOUND, error(), Codec::Json, false);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(
+            body(response).await,
+            json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "result": {
+                    "type": "error",
+                    "data": { "code": 404, "message": "not found", "data": { "~rspc": true } },
+                },
+            })
+        );
+
+        let response = super::response(StatusCode::NOT_FOUND, error(), Codec::Json, true);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        assert_eq!(
+            body(response).await,
+            json!({
+                "type": "about:blank",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "not found",
+                "data": { "~rspc": true },
+            })
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use axum::{extract::FromRequestParts, http::request::Parts};
+use axum::{
+    extract::FromRequestParts,
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
 use std::future::Future;
 
 use std::marker::PhantomData;
@@ -8,7 +12,12 @@ where
     TState: Send + Sync,
     TCtx: Send + 'static,
 {
-    fn exec(&self, parts: Parts, state: &TState) -> impl Future<Output = Result<TCtx, ()>> + Send;
+    // The error is the response for the client. This is forwarded as is over HTTP and converted into a JSON-RPC error for batches and websockets.
+    fn exec(
+        &self,
+        parts: Parts,
+        state: &TState,
+    ) -> impl Future<Output = Result<TCtx, Response>> + Send;
 }
 
 pub struct ZeroArgMarker;
@@ -19,13 +28,28 @@ where
     TState: Send + Sync,
     TCtx: Send + 'static,
 {
-    async fn exec(&self, _: Parts, _: &TState) -> Result<TCtx, ()> {
+    async fn exec(&self, _: Parts, _: &TState) -> Result<TCtx, Response> {
         Ok(self.clone()())
     }
 }
 
+pub struct AsyncZeroArgMarker;
+
+impl<TCtx, TFunc, TFut, TErr, TState> TCtxFunc<TCtx, TState, AsyncZeroArgMarker> for TFunc
+where
+    TFunc: Fn() -> TFut + Clone + Send + Sync + 'static,
+    TFut: Future<Output = Result<TCtx, TErr>> + Send,
+    TErr: IntoResponse,
+    TState: Send + Sync,
+    TCtx: Send + 'static,
+{
+    async fn exec(&self, _: Parts, _: &TState) -> Result<TCtx, Response> {
+        self.clone()().await.map_err(IntoResponse::into_response)
+    }
+}
+
 macro_rules! impl_fn {
-    ($marker:ident; $($generics:ident),*) => {
+    ($marker:ident, $async_marker:ident; $($generics:ident),*) => {
     		#[allow(unused_parens)]
         pub struct $marker<$($generics),*>(PhantomData<($($generics),*)>);
 
@@ -35,34 +59,54 @@ macro_rules! impl_fn {
             TState: Send + Sync,
             TCtx: Send + 'static
         {
-            async fn exec(&self, mut parts: Parts, state: &TState) -> Result<TCtx, ()>
+            async fn exec(&self, mut parts: Parts, state: &TState) -> Result<TCtx, Response>
             {
 		            $(
 										#[allow(non_snake_case)]
-										let Ok($generics) = $generics::from_request_parts(&mut parts, &state).await else {
-												 return Err(())
-										};
+										let $generics = $generics::from_request_parts(&mut parts, &state).await.map_err(IntoResponse::into_response)?;
 								)*
 
                 Ok(self.clone()($($generics),*))
             }
+        }
+
+    		#[allow(unused_parens)]
+        pub struct $async_marker<$($generics),*>(PhantomData<($($generics),*)>);
+
+        impl<TCtx, TFunc, TFut, TErr, TState, $($generics: FromRequestParts<TState> + Send),*> TCtxFunc<TCtx, TState, $async_marker<$($generics),*>> for TFunc
+        where
+            TFunc: Fn($($generics),*) -> TFut + Clone + Send + Sync + 'static,
+            TFut: Future<Output = Result<TCtx, TErr>> + Send,
+            TErr: IntoResponse,
+            TState: Send + Sync,
+            TCtx: Send + 'static
+        {
+            async fn exec(&self, mut parts: Parts, state: &TState) -> Result<TCtx, Response>
+            {
+		            $(
+										#[allow(non_snake_case)]
+										let $generics = $generics::from_request_parts(&mut parts, &state).await.map_err(IntoResponse::into_response)?;
+								)*
+
+                self.clone()($($generics),*).await.map_err(IntoResponse::into_response)
+            }
         }
     };
 }
 
-impl_fn!(OneArgMarker; T1);
-impl_fn!(TwoArgMarker; T1, T2);
-impl_fn!(ThreeArgMarker; T1, T2, T3);
-impl_fn!(FourArgMarker; T1, T2, T3, T4);
-impl_fn!(FiveArgMarker; T1, T2, T3, T4, T5);
-impl_fn!(SixArgMarker; T1, T2, T3, T4, T5, T6);
-impl_fn!(SevenArgMarker; T1, T2, T3, T4, T5, T6, T7);
-impl_fn!(EightArgMarker; T1, T2, T3, T4, T5, T6, T7, T8);
-impl_fn!(NineArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9);
-impl_fn!(TenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
-impl_fn!(ElevenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
-impl_fn!(TwelveArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
-impl_fn!(ThirteenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13);
-impl_fn!(FourteenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14);
-impl_fn!(FifteenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15);
-impl_fn!(SixteenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16);
+impl_fn!(OneArgMarker, AsyncOneArgMarker; T1);
+impl_fn!(TwoArgMarker, AsyncTwoArgMarker; T1, T2);
+impl_fn!(ThreeArgMarker, AsyncThreeArgMarker; T1, T2, T3);
+impl_fn!(FourArgMarker, AsyncFourArgMarker; T1, T2, T3, T4);
+impl_fn!(FiveArgMarker, AsyncFiveArgMarker; T1, T2, T3, T4, T5);
+impl_fn!(SixArgMarker, AsyncSixArgMarker; T1, T2, T3, T4, T5, T6);
+impl_fn!(SevenArgMarker, AsyncSevenArgMarker; T1, T2, T3, T4, T5, T6, T7);
+impl_fn!(EightArgMarker, AsyncEightArgMarker; T1, T2, T3, T4, T5, T6, T7, T8);
+impl_fn!(NineArgMarker, AsyncNineArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9);
+impl_fn!(TenArgMarker, AsyncTenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+impl_fn!(ElevenArgMarker, AsyncElevenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+impl_fn!(TwelveArgMarker, AsyncTwelveArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
+impl_fn!(ThirteenArgMarker, AsyncThirteenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13);
+impl_fn!(FourteenArgMarker, AsyncFourteenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14);
+impl_fn!(FifteenArgMarker, AsyncFifteenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15);
+impl_fn!(SixteenArgMarker, AsyncSixteenArgMarker; T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16);
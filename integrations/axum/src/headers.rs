@@ -0,0 +1,41 @@
+use std::sync::{Arc, Mutex};
+
+use axum::http::{header::IntoHeaderName, HeaderMap, HeaderValue};
+
+/// Set headers on the HTTP response from within a procedure.
+///
+/// This is inserted into the request's extensions so it can be extracted in your context function with [`Extension`](axum::Extension) and stored in your context.
+///
+/// The headers are sent once every procedure in the request has resolved or called `rspc::flush`, so they must be set before then.
+/// Headers set after that, or on a websocket connection, are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseHeaders(Arc<Mutex<HeaderMap>>);
+
+impl ResponseHeaders {
+    /// Insert a header, replacing any existing values.
+    pub fn insert(&self, key: impl IntoHeaderName, value: HeaderValue) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, value);
+    }
+
+    /// Append a header, keeping any existing values. This is useful for `Set-Cookie`.
+    pub fn append(&self, key: impl IntoHeaderName, value: HeaderValue) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .append(key, value);
+    }
+
+    // Move the headers onto the response.
+    pub(crate) fn apply(&self, headers: &mut HeaderMap) {
+        let map = std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()));
+        for key in map.keys() {
+            headers.remove(key);
+        }
+        for (key, value) in map.iter() {
+            headers.append(key, value.clone());
+        }
+    }
+}
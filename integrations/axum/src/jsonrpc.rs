@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 use serde_json::Value;
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
@@ -31,6 +31,9 @@ pub enum RequestInner {
     Subscription {
         path: String,
         input: (RequestId, Option<Value>),
+        // The id of the last event the client received. This resumes the subscription if it's still running.
+        #[serde(default, rename = "lastEventId")]
+        last_event_id: Option<String>,
     },
     SubscriptionStop {
         input: RequestId,
@@ -44,36 +47,69 @@ pub struct Response {
     pub result: ResponseInner,
 }
 
-#[derive(Debug, Clone, Serialize)]
-#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+#[derive(Debug, Clone)]
+// Only responses and errors are sent without websockets.
+#[cfg_attr(not(feature = "ws"), allow(dead_code))]
 pub enum ResponseInner {
-    Event(Value),
+    // A value from a subscription and the id to resume it from, if it's resumable.
+    Event(Value, Option<String>),
     Response(Value),
     Error(JsonRPCError),
+    // The subscription has ended. This isn't sent when the client stopped it.
+    Complete,
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct JsonRPCError {
-    pub code: i32,
-    pub message: String,
-    pub data: Option<Value>,
+// This is `#[serde(tag = "type", content = "data")]` with the event id alongside the data.
+impl Serialize for ResponseInner {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = match self {
+            Self::Event(_, Some(_)) => 3,
+            Self::Complete => 1,
+            _ => 2,
+        };
+        let mut s = serializer.serialize_struct("ResponseInner", len)?;
+        match self {
+            Self::Event(data, id) => {
+                s.serialize_field("type", "event")?;
+                s.serialize_field("data", data)?;
+                if let Some(id) = id {
+                    s.serialize_field("id", id)?;
+                }
+            }
+            Self::Response(data) => {
+                s.serialize_field("type", "response")?;
+                s.serialize_field("data", data)?;
+            }
+            Self::Error(err) => {
+                s.serialize_field("type", "error")?;
+                s.serialize_field("data", err)?;
+            }
+            Self::Complete => s.serialize_field("type", "complete")?,
+        }
+        s.end()
+    }
 }
 
-// TODO: BREAK
+// A `Response` which serializes it's data directly instead of going through a `Value`.
+#[derive(Debug, Serialize)]
+pub struct ResponseRef<'a, T> {
+    pub jsonrpc: &'static str,
+    pub id: &'a RequestId,
+    pub result: ResponseInnerRef<T>,
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ProcedureKind {
-    Query,
-    Mutation,
-    Subscription,
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum ResponseInnerRef<T> {
+    Response(T),
+    Error(JsonRPCError),
 }
 
-impl ProcedureKind {
-    pub fn to_str(&self) -> &'static str {
-        match self {
-            ProcedureKind::Query => "query",
-            ProcedureKind::Mutation => "mutation",
-            ProcedureKind::Subscription => "subscription",
-        }
-    }
+// Errors produced by rspc instead of a procedure have `"~rspc": true` in their data, the same as `ProcedureError`'s other variants.
+// This stops clients from decoding them as the procedure's error type.
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonRPCError {
+    pub code: i32,
+    pub message: String,
+    pub data: Option<Value>,
 }
@@ -1,170 +1,134 @@
-use std::{
-    borrow::Cow,
-    collections::HashMap,
-    future::{poll_fn, Future},
-};
+use std::future::{poll_fn, Future};
+#[cfg(feature = "ws")]
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
-use rspc_procedure::{ProcedureError, ProcedureStream, Procedures};
+#[cfg(feature = "ws")]
+use rspc_procedure::Procedures;
+use rspc_procedure::{ProcedureError, ProcedureStream};
 use serde::Serialize;
+#[cfg(feature = "ws")]
 use serde_json::Value;
-use tokio::sync::{broadcast, mpsc, oneshot, Mutex};
+#[cfg(feature = "ws")]
+use tokio::sync::oneshot;
 
-use super::jsonrpc::{self, RequestId, RequestInner, ResponseInner};
+use super::jsonrpc;
+#[cfg(feature = "ws")]
+use super::jsonrpc::{RequestId, RequestInner, ResponseInner};
+#[cfg(feature = "ws")]
+use crate::{
+    outbox::{Outbox, Outgoing},
+    replay::{Caller, Replay},
+};
 
-pub enum SubscriptionMap<'a> {
-    Ref(&'a mut HashMap<RequestId, oneshot::Sender<()>>),
-    Mutex(&'a Mutex<HashMap<RequestId, oneshot::Sender<()>>>),
-    None,
-}
+// The subscriptions running on a websocket connection.
+#[cfg(feature = "ws")]
+pub struct SubscriptionMap<'a>(pub &'a mut HashMap<RequestId, oneshot::Sender<()>>);
 
-impl<'a> SubscriptionMap<'a> {
-    pub async fn has_subscription(&self, id: &RequestId) -> bool {
-        match self {
-            SubscriptionMap::Ref(map) => map.contains_key(id),
-            SubscriptionMap::Mutex(map) => {
-                let map = map.lock().await;
-                map.contains_key(id)
-            }
-            SubscriptionMap::None => unreachable!(),
-        }
+#[cfg(feature = "ws")]
+impl SubscriptionMap<'_> {
+    // Subscriptions which have finished are still in the map so they must be ignored.
+    pub fn has_subscription(&self, id: &RequestId) -> bool {
+        self.0.get(id).is_some_and(|tx| !tx.is_closed())
     }
 
-    pub async fn insert(&mut self, id: RequestId, tx: oneshot::Sender<()>) {
-        match self {
-            SubscriptionMap::Ref(map) => {
-                map.insert(id, tx);
-            }
-            SubscriptionMap::Mutex(map) => {
-                let mut map = map.lock().await;
-                map.insert(id, tx);
-            }
-            SubscriptionMap::None => unreachable!(),
-        }
+    // The number of running subscriptions.
+    pub fn len(&mut self) -> usize {
+        self.0.retain(|_, tx| !tx.is_closed());
+        self.0.len()
     }
 
-    pub async fn remove(&mut self, id: &RequestId) {
-        match self {
-            SubscriptionMap::Ref(map) => {
-                map.remove(id);
-            }
-            SubscriptionMap::Mutex(map) => {
-                let mut map = map.lock().await;
-                map.remove(id);
-            }
-            SubscriptionMap::None => unreachable!(),
-        }
+    pub fn insert(&mut self, id: RequestId, tx: oneshot::Sender<()>) {
+        self.0.insert(id, tx);
     }
-}
-
-pub enum Sender<'a> {
-    Channel(&'a mut mpsc::Sender<jsonrpc::Response>),
-    ResponseChannel(&'a mut mpsc::UnboundedSender<jsonrpc::Response>),
-    Broadcast(&'a broadcast::Sender<jsonrpc::Response>),
-    Response(Option<jsonrpc::Response>),
-}
-
-pub enum Sender2 {
-    Channel(mpsc::Sender<jsonrpc::Response>),
-    ResponseChannel(mpsc::UnboundedSender<jsonrpc::Response>),
-    Broadcast(broadcast::Sender<jsonrpc::Response>),
-}
 
-impl Sender2 {
-    pub async fn send(
-        &mut self,
-        resp: jsonrpc::Response,
-    ) -> Result<(), mpsc::error::SendError<jsonrpc::Response>> {
-        match self {
-            Self::Channel(tx) => tx.send(resp).await?,
-            Self::ResponseChannel(tx) => tx.send(resp)?,
-            Self::Broadcast(tx) => {
-                let _ = tx.send(resp).map_err(|_err| {
-                    // #[cfg(feature = "tracing")]
-                    // tracing::error!("Failed to send response: {}", _err);
-                });
-            }
+    // Stop a subscription. Unlike dropping the map this tells it the client unsubscribed.
+    pub fn remove(&mut self, id: &RequestId) {
+        if let Some(tx) = self.0.remove(id) {
+            let _ = tx.send(());
         }
-
-        Ok(())
     }
 }
 
-impl<'a> Sender<'a> {
-    pub async fn send(
-        &mut self,
-        resp: jsonrpc::Response,
-    ) -> Result<(), mpsc::error::SendError<jsonrpc::Response>> {
-        match self {
-            Self::Channel(tx) => tx.send(resp).await?,
-            Self::ResponseChannel(tx) => tx.send(resp)?,
-            Self::Broadcast(tx) => {
-                let _ = tx.send(resp).map_err(|_err| {
-                    // #[cfg(feature = "tracing")]
-                    // tracing::error!("Failed to send response: {}", _err);
-                });
-            }
-            Self::Response(r) => {
-                *r = Some(resp);
-            }
-        }
+// Send an event or error from a subscription, which are bound by the outbox. Returns `false` if the subscription should stop.
+#[cfg(feature = "ws")]
+async fn send_event(outbox: &Outbox<Outgoing>, resp: jsonrpc::Response) -> bool {
+    let id = resp.id.clone();
+    if outbox.push_event(resp).await.is_err() {
+        // #[cfg(feature = "tracing")]
+        // tracing::debug!("Closing subscription '{:?}' as the client isn't reading fast enough", id);
 
-        Ok(())
+        outbox.push(jsonrpc::Response {
+            jsonrpc: "2.0",
+            id: id.clone(),
+            result: ResponseInner::Error(jsonrpc::JsonRPCError {
+                code: 503,
+                message: "the subscription was closed as the client isn't reading it fast enough"
+                    .into(),
+                data: None,
+            }),
+        });
+        complete(outbox, id);
+        return false;
     }
 
-    pub fn sender2(&mut self) -> Sender2 {
-        match self {
-            Self::Channel(tx) => Sender2::Channel(tx.clone()),
-            Self::ResponseChannel(tx) => Sender2::ResponseChannel(tx.clone()),
-            Self::Broadcast(tx) => Sender2::Broadcast(tx.clone()),
-            Self::Response(_) => unreachable!(),
-        }
-    }
+    true
 }
 
+// Tell the client the subscription has ended so it doesn't resubscribe to it.
+#[cfg(feature = "ws")]
+pub fn complete(outbox: &Outbox<Outgoing>, id: RequestId) {
+    outbox.push(jsonrpc::Response {
+        jsonrpc: "2.0",
+        id,
+        result: ResponseInner::Complete,
+    });
+}
+
+#[cfg(feature = "ws")]
 pub async fn handle_json_rpc<TCtx>(
     ctx: TCtx,
     req: jsonrpc::Request,
     procedures: &Procedures<TCtx>,
-    sender: &mut Sender<'_>,
+    outbox: &Arc<Outbox<Outgoing>>,
     subscriptions: &mut SubscriptionMap<'_>,
+    max_subscriptions: usize,
+    replay: Option<(&Arc<Replay>, Caller)>,
 ) where
     TCtx: 'static,
 {
     if req.jsonrpc.is_some() && req.jsonrpc.as_deref() != Some("2.0") {
-        let _ = sender
-            .send(jsonrpc::Response {
-                jsonrpc: "2.0",
-                id: req.id.clone(),
-                result: ResponseInner::Error(jsonrpc::JsonRPCError {
-                    code: 400,
-                    message: "invalid JSON-RPC version".into(),
-                    data: None,
-                }),
-            })
-            .await
-            .map_err(|_err| {
-                // #[cfg(feature = "tracing")]
-                // tracing::error!("Failed to send response: {}", _err);
-            });
+        outbox.push(jsonrpc::Response {
+            jsonrpc: "2.0",
+            id: req.id.clone(),
+            result: ResponseInner::Error(jsonrpc::JsonRPCError {
+                code: 400,
+                message: "invalid JSON-RPC version".into(),
+                data: None,
+            }),
+        });
     }
 
-    let (path, input, sub_id, is_subscription) = match req.inner {
-        RequestInner::Query { path, input } => (path, input, None, false),
-        RequestInner::Mutation { path, input } => (path, input, None, false),
-        RequestInner::Subscription { path, input } => (path, input.1, Some(input.0), true),
+    let (path, input, sub_id, last_event_id, is_subscription) = match req.inner {
+        RequestInner::Query { path, input } => (path, input, None, None, false),
+        RequestInner::Mutation { path, input } => (path, input, None, None, false),
+        RequestInner::Subscription {
+            path,
+            input,
+            last_event_id,
+        } => (path, input.1, Some(input.0), last_event_id, true),
         RequestInner::SubscriptionStop { input } => {
-            subscriptions.remove(&input).await;
+            subscriptions.remove(&input);
             return;
         }
     };
 
     let result = match procedures.get(&Cow::Borrowed(&*path)) {
         Some(procedure) => {
-            let mut stream = procedure.exec_with_deserializer(ctx, input.unwrap_or(Value::Null));
-            let first_value = next(&mut stream).await;
-
             if !is_subscription {
-                first_value
+                let mut stream =
+                    procedure.exec_with_deserializer(ctx, input.unwrap_or(Value::Null));
+                next(&mut stream)
+                    .await
                     .expect("checked at if above")
                     .map(ResponseInner::Response)
                     .unwrap_or_else(|err| {
@@ -174,87 +138,123 @@ pub async fn handle_json_rpc<TCtx>(
                         ResponseInner::Error(err)
                     })
             } else {
-                if matches!(sender, Sender::Response(_))
-                    || matches!(subscriptions, SubscriptionMap::None)
-                {
-                    let _ = sender
-                        .send(jsonrpc::Response {
+                if let Some(id) = sub_id {
+                    if matches!(id, RequestId::Null) {
+                        outbox.push(jsonrpc::Response {
                             jsonrpc: "2.0",
                             id: req.id.clone(),
                             result: ResponseInner::Error(jsonrpc::JsonRPCError {
                                 code: 400,
-                                message: "unsupported metho".into(),
+                                message: "error creating subscription with null request id".into(),
                                 data: None,
                             }),
-                        })
-                        .await
-                        .map_err(|_err| {
-                            // #[cfg(feature = "tracing")]
-                            // tracing::error!("Failed to send response: {}", _err);
                         });
-                }
-
-                if let Some(id) = sub_id {
-                    if matches!(id, RequestId::Null) {
-                        let _ = sender
-                            .send(jsonrpc::Response {
-                                jsonrpc: "2.0",
-                                id: req.id.clone(),
-                                result: ResponseInner::Error(jsonrpc::JsonRPCError {
-                                    code: 400,
-                                    message: "error creating subscription with null request id"
-                                        .into(),
-                                    data: None,
-                                }),
-                            })
-                            .await
-                            .map_err(|_err| {
-                                // #[cfg(feature = "tracing")]
-                                // tracing::error!("Failed to send response: {}", _err);
-                            });
-                    } else if subscriptions.has_subscription(&id).await {
-                        let _ = sender
-                            .send(jsonrpc::Response {
-                                jsonrpc: "2.0",
-                                id: req.id.clone(),
-                                result: ResponseInner::Error(jsonrpc::JsonRPCError {
-                                    code: 400,
-                                    message: "error creating subscription with duplicate id".into(),
-                                    data: None,
-                                }),
-                            })
-                            .await
-                            .map_err(|_err| {
-                                // #[cfg(feature = "tracing")]
-                                // tracing::error!("Failed to send response: {}", _err);
-                            });
+                        complete(outbox, req.id);
+                        return;
+                    } else if subscriptions.has_subscription(&id) {
+                        outbox.push(jsonrpc::Response {
+                            jsonrpc: "2.0",
+                            id: req.id.clone(),
+                            result: ResponseInner::Error(jsonrpc::JsonRPCError {
+                                code: 400,
+                                message: "error creating subscription with duplicate id".into(),
+                                data: None,
+                            }),
+                        });
+                        complete(outbox, req.id);
+                        return;
+                    } else if subscriptions.len() >= max_subscriptions {
+                        outbox.push(jsonrpc::Response {
+                            jsonrpc: "2.0",
+                            id: req.id.clone(),
+                            result: ResponseInner::Error(jsonrpc::JsonRPCError {
+                                code: 429,
+                                message: "error creating subscription as the connection has too many subscriptions".into(),
+                                data: None,
+                            }),
+                        });
+                        complete(outbox, req.id);
+                        return;
                     }
 
-                    let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
-                    subscriptions.insert(id.clone(), shutdown_tx).await;
-                    let mut sender2 = sender.sender2();
-                    tokio::spawn(async move {
-                        match first_value {
-                            Some(Ok(v)) => {
-                                let _ = sender2
-                                    .send(jsonrpc::Response {
-                                        jsonrpc: "2.0",
-                                        id: id.clone(),
-                                        result: ResponseInner::Event(v),
-                                    })
-                                    .await
-                                    .map_err(|_err| {
-                                        // #[cfg(feature = "tracing")]
-                                        // tracing::error!("Failed to send response: {:?}", _err);
-                                    });
+                    let input = input.unwrap_or(Value::Null);
+
+                    if let Some((replay, caller)) = replay {
+                        let limit = max_subscriptions - subscriptions.len();
+                        let subscriber = replay.subscribe(
+                            &path,
+                            input.to_string().as_bytes(),
+                            caller,
+                            last_event_id.as_deref(),
+                            limit,
+                            || procedure.exec_with_deserializer(ctx, input),
+                        );
+                        let mut subscriber = match subscriber {
+                            Ok(subscriber) => subscriber,
+                            Err(err) => {
+                                outbox.push(jsonrpc::Response {
+                                    jsonrpc: "2.0",
+                                    id: req.id.clone(),
+                                    result: ResponseInner::Error(err),
+                                });
+                                complete(outbox, req.id);
+                                return;
                             }
-                            Some(Err(_err)) => {
-                                // #[cfg(feature = "tracing")]
-                                // tracing::error!("Subscription error: {:?}", _err);
+                        };
+
+                        let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+                        subscriptions.insert(id.clone(), shutdown_tx);
+                        let outbox = outbox.clone();
+                        tokio::spawn(async move {
+                            loop {
+                                tokio::select! {
+                                    biased; // Note: Order matters
+                                    stop = &mut shutdown_rx => {
+                                        // If the client disconnected the subscription is left running so it can be resumed.
+                                        if stop.is_ok() {
+                                            subscriber.stop();
+                                        }
+                                        break;
+                                    }
+                                    v = subscriber.next() => {
+                                        match v {
+                                            Some((seq, Ok(v))) => {
+                                                if !send_event(&outbox, jsonrpc::Response {
+                                                    jsonrpc: "2.0",
+                                                    id: id.clone(),
+                                                    result: ResponseInner::Event(v, Some(subscriber.event_id(seq))),
+                                                })
+                                                .await {
+                                                    break;
+                                                }
+                                            }
+                                            Some((_, Err(err))) => {
+                                                if !send_event(&outbox, jsonrpc::Response {
+                                                    jsonrpc: "2.0",
+                                                    id: id.clone(),
+                                                    result: ResponseInner::Error(err),
+                                                })
+                                                .await {
+                                                    break;
+                                                }
+                                            }
+                                            None => {
+                                                complete(&outbox, id);
+                                                break;
+                                            }
+                                        }
+                                    }
+                                }
                             }
-                            None => return,
-                        }
+                        });
+                        return;
+                    }
 
+                    let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
+                    subscriptions.insert(id.clone(), shutdown_tx);
+                    let outbox = outbox.clone();
+                    let mut stream = procedure.exec_with_deserializer(ctx, input);
+                    tokio::spawn(async move {
                         loop {
                             tokio::select! {
                                 biased; // Note: Order matters
@@ -266,22 +266,27 @@ pub async fn handle_json_rpc<TCtx>(
                                 v = next(&mut stream) => {
                                     match v {
                                         Some(Ok(v)) => {
-                                            let _ = sender2.send(jsonrpc::Response {
+                                            if !send_event(&outbox, jsonrpc::Response {
                                                 jsonrpc: "2.0",
                                                 id: id.clone(),
-                                                result: ResponseInner::Event(v),
+                                                result: ResponseInner::Event(v, None),
                                             })
-                                            .await
-                                            .map_err(|_err| {
-                                                // #[cfg(feature = "tracing")]
-                                                // tracing::error!("Failed to send response: {:?}", _err);
-                                            });
+                                            .await {
+                                                break;
+                                            }
                                         }
-                                        Some(Err(_err)) => {
-                                           // #[cfg(feature = "tracing")]
-                                           //  tracing::error!("Subscription error: {:?}", _err);
+                                        Some(Err(err)) => {
+                                            if !send_event(&outbox, jsonrpc::Response {
+                                                jsonrpc: "2.0",
+                                                id: id.clone(),
+                                                result: ResponseInner::Error(err),
+                                            })
+                                            .await {
+                                                break;
+                                            }
                                         }
                                         None => {
+                                            complete(&outbox, id);
                                             break;
                                         }
                                     }
@@ -305,62 +310,195 @@ pub async fn handle_json_rpc<TCtx>(
         }
     };
 
-    let _ = sender
-        .send(jsonrpc::Response {
-            jsonrpc: "2.0",
-            id: req.id,
-            result,
-        })
-        .await
-        .map_err(|_err| {
-            // #[cfg(feature = "tracing")]
-            // tracing::error!("Failed to send response: {:?}", _err);
-        });
+    outbox.push(jsonrpc::Response {
+        jsonrpc: "2.0",
+        id: req.id.clone(),
+        result,
+    });
+
+    // Only a subscription for a procedure which doesn't exist gets here.
+    if is_subscription {
+        complete(outbox, req.id);
+    }
 }
 
-async fn next(
+pub async fn next(
     stream: &mut ProcedureStream,
 ) -> Option<Result<serde_json::Value, jsonrpc::JsonRPCError>> {
     let fut = stream.next();
     let mut fut = std::pin::pin!(fut);
     poll_fn(|cx| fut.as_mut().poll(cx)).await.map(|v| {
-        v.map_err(|err| match &err {
-            ProcedureError::NotFound => unimplemented!(), // Isn't created by this executor
-            ProcedureError::Deserialize(_) => jsonrpc::JsonRPCError {
-                code: 400,
-                message: "error deserializing procedure arguments".to_string(),
-                data: None,
-            },
-            ProcedureError::Downcast(_) => unimplemented!(), // Isn't supported by this executor
-            ProcedureError::Resolver(resolver_err) => {
-                let legacy_error = resolver_err
-                    .error()
-                    .and_then(|v| v.downcast_ref::<rspc_procedure::LegacyErrorInterop>())
-                    .cloned();
-
-                jsonrpc::JsonRPCError {
-                    code: match err {
-                        ProcedureError::NotFound => 404,
-                        ProcedureError::Deserialize(_) => 400,
-                        ProcedureError::Downcast(_) => 400,
-                        ProcedureError::Resolver(_) => 500, // This is a breaking change. It previously came from the user.
-                        ProcedureError::Unwind(_) => 500,
-                    },
-                    message: legacy_error
-                        .map(|v| v.0.clone())
-                        // This probally isn't a great format but we are assuming your gonna use the new router with a new executor for typesafe errors.
-                        .unwrap_or_else(|| err.to_string()),
+        v.map_err(to_error).and_then(|v| {
+            // Eg. a Binario procedure called from a JSON-RPC request.
+            let Some(v) = v.as_serialize() else {
+                return Err(jsonrpc::JsonRPCError {
+                    code: 500,
+                    message: "procedure returned a non-serializable value".into(),
                     data: None,
-                }
-            }
-            ProcedureError::Unwind(err) => panic!("{err:?}"), // Restore previous behavior lol
-                                                              // ProcedureError::Serializer(err) => panic!("{err:?}"),
-        })
-        .and_then(|v| {
-            Ok(v.as_serialize()
-                .unwrap()
-                .serialize(serde_json::value::Serializer)
-                .expect("Error serialzing value")) // This panicking is bad but this is the old exectuor
+                });
+            };
+
+            v.serialize(serde_json::value::Serializer)
+                .map_err(|err| jsonrpc::JsonRPCError {
+                    code: 500,
+                    message: format!("error serializing value: {err}"),
+                    data: None,
+                })
         })
     })
 }
+
+pub fn to_error(err: ProcedureError) -> jsonrpc::JsonRPCError {
+    let message = match &err {
+        ProcedureError::Deserialize(_) => "error deserializing procedure arguments".to_string(),
+        ProcedureError::Resolver(resolver_err) => resolver_err
+            .error()
+            .and_then(|v| v.downcast_ref::<rspc_procedure::LegacyErrorInterop>())
+            .map(|v| v.0.clone())
+            // This probally isn't a great format but we are assuming your gonna use the new router with a new executor for typesafe errors.
+            .unwrap_or_else(|| err.to_string()),
+        _ => err.message().into_owned(),
+    };
+
+    jsonrpc::JsonRPCError {
+        code: crate::error::status(&err).as_u16() as i32,
+        message,
+        // The value of the error so clients can decode it into their typed error.
+        data: serde_json::to_value(&err).ok(),
+    }
+}
+
+#[cfg(all(test, feature = "ws"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use rspc::{Procedure, ProcedureError, ResolverError, Router};
+    use serde_json::json;
+    use specta::Type;
+
+    use super::*;
+    use crate::Overflow;
+
+    #[derive(Debug, Serialize, Type)]
+    struct Error(&'static str);
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            ResolverError::new(self, None::<std::io::Error>).into()
+        }
+    }
+
+    fn procedures() -> Procedures<()> {
+        let (procedures, _) = <Router>::new()
+            .procedure(
+                "pending",
+                Procedure::builder::<Error>().subscription(|_, _: ()| async move {
+                    Ok(rspc::Stream(futures::stream::pending::<Result<(), Error>>()))
+                }),
+            )
+            .procedure(
+                "failing",
+                Procedure::builder::<Error>().subscription(|_, _: ()| async move {
+                    Ok(rspc::Stream(futures::stream::repeat_with(|| {
+                        Err::<(), _>(Error("failed"))
+                    })))
+                }),
+            )
+            .build()
+            .unwrap();
+        procedures
+    }
+
+    async fn subscribe(
+        outbox: &Arc<Outbox<Outgoing>>,
+        subscriptions: &mut HashMap<RequestId, oneshot::Sender<()>>,
+        path: &str,
+        id: Value,
+        sub_id: Value,
+    ) {
+        let req = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "subscription",
+            "params": { "path": path, "input": [sub_id, null] },
+        }))
+        .unwrap();
+        handle_json_rpc(
+            (),
+            req,
+            &procedures(),
+            outbox,
+            &mut SubscriptionMap(subscriptions),
+            10,
+            None,
+        )
+        .await;
+    }
+
+    // The id and result of the next response.
+    fn pop(outbox: &Outbox<Outgoing>) -> (Value, Value) {
+        #[allow(irrefutable_let_patterns)]
+        let Some(Outgoing::JsonRpc(resp)) = outbox.try_pop() else {
+            unreachable!("expected a JSON-RPC response");
+        };
+        let resp = serde_json::to_value(resp).unwrap();
+        (resp["id"].clone(), resp["result"].clone())
+    }
+
+    #[tokio::test]
+    async fn rejected_subscriptions_complete() {
+        let outbox = Arc::new(Outbox::<Outgoing>::new(10, Overflow::Backpressure));
+        let mut subscriptions = HashMap::new();
+
+        subscribe(
+            &outbox,
+            &mut subscriptions,
+            "pending",
+            json!(1),
+            json!(null),
+        )
+        .await;
+        let (id, result) = pop(&outbox);
+        assert_eq!((id, &result["type"]), (json!(1), &json!("error")));
+        assert_eq!(result["data"]["code"], 400);
+        assert_eq!(pop(&outbox), (json!(1), json!({ "type": "complete" })));
+
+        subscribe(&outbox, &mut subscriptions, "pending", json!(2), json!(2)).await;
+        assert!(outbox.try_pop().is_none());
+        subscribe(&outbox, &mut subscriptions, "pending", json!(2), json!(2)).await;
+        let (id, result) = pop(&outbox);
+        assert_eq!((id, &result["type"]), (json!(2), &json!("error")));
+        assert_eq!(result["data"]["code"], 400);
+        assert_eq!(pop(&outbox), (json!(2), json!({ "type": "complete" })));
+
+        subscribe(&outbox, &mut subscriptions, "pending", json!(3), json!(3)).await;
+        assert!(outbox.try_pop().is_none());
+        // Rejecting the duplicate didn't stop the original subscription.
+        assert_eq!(SubscriptionMap(&mut subscriptions).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn subscription_errors_are_bounded() {
+        let outbox = Arc::new(Outbox::<Outgoing>::new(2, Overflow::CloseSubscription));
+        let mut subscriptions = HashMap::new();
+
+        // Nothing is reading so the errors fill the buffer and the subscription is closed.
+        subscribe(&outbox, &mut subscriptions, "failing", json!(1), json!(1)).await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while SubscriptionMap(&mut subscriptions).len() != 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        for _ in 0..2 {
+            let (id, result) = pop(&outbox);
+            assert_eq!((id, &result["type"]), (json!(1), &json!("error")));
+            assert_eq!(result["data"]["data"], "failed");
+        }
+        let (id, result) = pop(&outbox);
+        assert_eq!((id, &result["data"]["code"]), (json!(1), &json!(503)));
+        assert_eq!(pop(&outbox), (json!(1), json!({ "type": "complete" })));
+        assert!(outbox.try_pop().is_none());
+    }
+}
@@ -6,14 +6,42 @@
     html_favicon_url = "https://github.com/specta-rs/rspc/raw/main/.github/logo.png"
 )]
 
+mod batch;
+#[cfg(feature = "binario")]
+mod binario;
+mod cache;
+mod codec;
+mod config;
+#[cfg(feature = "ws")]
+mod connection;
 mod endpoint;
+mod error;
 mod extractors;
+mod headers;
 mod jsonrpc;
 mod jsonrpc_exec;
+mod limits;
+mod ndjson;
+#[cfg(feature = "ws")]
+mod outbox;
+mod persisted;
 // mod legacy;
+mod replay;
 mod request;
+mod shutdown;
+mod sse;
 mod v2;
 
+pub use cache::{cache, CachePolicy};
+pub use config::Config;
+#[cfg(feature = "ws")]
+pub use connection::{Connection, ConnectionId};
 // pub use endpoint::Endpoint;
+pub use headers::ResponseHeaders;
+pub use limits::{limits, Limits};
+#[cfg(feature = "ws")]
+pub use outbox::Overflow;
+pub use persisted::PersistedQueries;
 pub use request::AxumRequest;
-pub use v2::endpoint;
+pub use shutdown::Shutdown;
+pub use v2::{endpoint, endpoint_with_config};
@@ -0,0 +1,520 @@
+use std::{cell::Cell, collections::HashMap, fmt, io};
+
+use axum::{
+    body::Body,
+    http::{Response, StatusCode},
+};
+use rspc::Extension;
+use rspc_procedure::Procedures;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::json;
+
+use crate::{
+    codec::Codec,
+    jsonrpc::{self, JsonRPCError, RequestId, RequestInner, ResponseInner},
+};
+
+/// Limits on the size and shape of the input to a procedure.
+///
+/// These are applied to HTTP requests and websocket messages. Set the default for all procedures with [`Config::limits`](crate::Config::limits) and override them for a single procedure with [`limits`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub(crate) body_size: usize,
+    pub(crate) depth: usize,
+    pub(crate) string_length: usize,
+    pub(crate) array_length: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            body_size: 2 * 1024 * 1024,
+            depth: 64,
+            string_length: 1024 * 1024,
+            array_length: 10_000,
+        }
+    }
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The maximum size in bytes of a request body, query string or websocket message. Defaults to 2MiB.
+    ///
+    /// A batch or websocket message may contain many procedures so it's checked against the default limit and the input of each procedure against it's own.
+    pub fn body_size(mut self, body_size: usize) -> Self {
+        self.body_size = body_size;
+        self
+    }
+
+    /// The maximum nesting of arrays and objects in the input. Defaults to `64`.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// The maximum length in bytes of any string or object key in the input. Defaults to 1MiB.
+    pub fn string_length(mut self, string_length: usize) -> Self {
+        self.string_length = string_length;
+        self
+    }
+
+    /// The maximum number of items in any array in the input. Defaults to `10000`.
+    pub fn array_length(mut self, array_length: usize) -> Self {
+        self.array_length = array_length;
+        self
+    }
+
+    // Get the limits for a procedure, taking into account any overrides.
+    pub(crate) fn resolve<TCtx>(self, procedures: &Procedures<TCtx>, path: &str) -> Self {
+        procedures
+            .state()
+            .get::<ProcedureLimits>()
+            .and_then(|limits| limits.0.get(path))
+            .copied()
+            .unwrap_or(self)
+    }
+
+    pub(crate) fn body_too_large(&self) -> Exceeded {
+        Exceeded::new(Limit::BodySize, self.body_size)
+    }
+
+    // Check the input of a JSON-RPC request against the limits of it's procedure.
+    // A batch or websocket message was only checked against the default body size so the size of the input is checked here too.
+    pub(crate) fn check_request<TCtx>(
+        self,
+        procedures: &Procedures<TCtx>,
+        request: &jsonrpc::Request,
+    ) -> Result<(), Exceeded> {
+        let (path, input) = match &request.inner {
+            RequestInner::Query { path, input } | RequestInner::Mutation { path, input } => {
+                (path, input)
+            }
+            RequestInner::Subscription { path, input, .. } => (path, &input.1),
+            RequestInner::SubscriptionStop { .. } => return Ok(()),
+        };
+
+        let Some(input) = input else {
+            return Ok(());
+        };
+
+        let limits = self.resolve(procedures, path);
+        let mut size = Size(0);
+        let _ = serde_json::to_writer(&mut size, input);
+        if size.0 > limits.body_size {
+            return Err(limits.body_too_large());
+        }
+        limits.check(input)
+    }
+
+    // Walk the input without deserializing it so the limits can be checked for any format.
+    // Malformed input is left for the procedure to report.
+    pub(crate) fn check<'de, D: Deserializer<'de>>(&self, input: D) -> Result<(), Exceeded> {
+        let exceeded = Cell::new(None);
+        let _ = Walk {
+            limits: self,
+            depth: 0,
+            exceeded: &exceeded,
+        }
+        .deserialize(input);
+        exceeded.into_inner().map_or(Ok(()), Err)
+    }
+}
+
+struct Walk<'a> {
+    limits: &'a Limits,
+    depth: usize,
+    exceeded: &'a Cell<Option<Exceeded>>,
+}
+
+impl Walk<'_> {
+    fn nested(&self) -> Self {
+        Walk {
+            limits: self.limits,
+            depth: self.depth + 1,
+            exceeded: self.exceeded,
+        }
+    }
+
+    fn exceeded<E: de::Error>(&self, exceeded: Exceeded) -> E {
+        self.exceeded.set(Some(exceeded));
+        E::custom("input exceeded the configured limits")
+    }
+
+    fn check_depth<E: de::Error>(&self) -> Result<(), E> {
+        match self.depth >= self.limits.depth {
+            true => Err(self.exceeded(Exceeded::new(Limit::Depth, self.limits.depth))),
+            false => Ok(()),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Walk<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Walk<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i128<E: de::Error>(self, _: i128) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u128<E: de::Error>(self, _: u128) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<(), E> {
+        self.visit_bytes(v.as_bytes())
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<(), E> {
+        match v.len() > self.limits.string_length {
+            true => Err(self.exceeded(Exceeded::new(
+                Limit::StringLength,
+                self.limits.string_length,
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        self.check_depth()?;
+
+        let mut len = 0;
+        while seq.next_element_seed(self.nested())?.is_some() {
+            len += 1;
+            if len > self.limits.array_length {
+                return Err(
+                    self.exceeded(Exceeded::new(Limit::ArrayLength, self.limits.array_length))
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        self.check_depth()?;
+
+        while map.next_key_seed(self.nested())?.is_some() {
+            map.next_value_seed(self.nested())?;
+        }
+        Ok(())
+    }
+}
+
+/// Override the [`Limits`] for a single procedure.
+///
+/// These replace the limits from the [`Config`](crate::Config) entirely.
+pub fn limits<TCtx, TInput, TResult>(limits: Limits) -> Extension<TCtx, TInput, TResult> {
+    Extension::new().setup(move |state, meta| {
+        state
+            .get_mut_or_init::<ProcedureLimits>(Default::default)
+            .0
+            .insert(meta.name().to_string(), limits);
+    })
+}
+
+// The per-procedure overrides which are stored into rspc.
+#[derive(Default)]
+struct ProcedureLimits(HashMap<String, Limits>);
+
+// Counts the bytes of the serialized input.
+struct Size(usize);
+
+impl io::Write for Size {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// One of the `Limits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Limit {
+    BodySize,
+    Depth,
+    StringLength,
+    ArrayLength,
+}
+
+impl Limit {
+    // The name of the limit in the error's data.
+    fn name(self) -> &'static str {
+        match self {
+            Self::BodySize => "bodySize",
+            Self::Depth => "depth",
+            Self::StringLength => "stringLength",
+            Self::ArrayLength => "arrayLength",
+        }
+    }
+}
+
+// A request which is over one of the `Limits`.
+#[derive(Debug, Clone)]
+pub(crate) struct Exceeded {
+    limit: Limit,
+    max: usize,
+}
+
+impl Exceeded {
+    fn new(limit: Limit, max: usize) -> Self {
+        Self { limit, max }
+    }
+
+    pub(crate) fn status(&self) -> StatusCode {
+        match self.limit {
+            Limit::BodySize => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    pub(crate) fn error(&self) -> JsonRPCError {
+        JsonRPCError {
+            code: self.status().as_u16() as i32,
+            message: match self.limit {
+                Limit::BodySize => format!("the request is larger than {} bytes", self.max),
+                Limit::Depth => format!("the input is nested deeper than {} levels", self.max),
+                Limit::StringLength => {
+                    format!("the input contains a string longer than {} bytes", self.max)
+                }
+                Limit::ArrayLength => format!(
+                    "the input contains an array with more than {} items",
+                    self.max
+                ),
+            },
+            data: Some(json!({
+                "~rspc": true,
+                "limit": self.limit.name(),
+                "max": self.max,
+            })),
+        }
+    }
+
+    pub(crate) fn response(&self, id: RequestId) -> jsonrpc::Response {
+        jsonrpc::Response {
+            jsonrpc: "2.0",
+            id,
+            result: ResponseInner::Error(self.error()),
+        }
+    }
+
+    pub(crate) fn into_http(self, accept: Codec, problem_details: bool) -> Response<Body> {
+        crate::error::response(self.status(), self.error(), accept, problem_details)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use rspc::{Procedure, ProcedureError, Router};
+    use serde_json::Value;
+    use specta::Type;
+
+    use super::*;
+
+    fn check(limits: Limits, input: &str) -> Result<(), Limit> {
+        limits
+            .check(&mut serde_json::Deserializer::from_str(input))
+            .map_err(|exceeded| exceeded.limit)
+    }
+
+    #[test]
+    fn within_limits() {
+        let limits = Limits::new();
+        assert_eq!(check(limits, "null"), Ok(()));
+        assert_eq!(
+            check(limits, r#"{"a": [1, 2.5, "b", true, null], "c": {}}"#),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn depth() {
+        let limits = Limits::new().depth(2);
+        assert_eq!(check(limits, "[[1]]"), Ok(()));
+        assert_eq!(check(limits, r#"{"a": {"b": 1}}"#), Ok(()));
+        assert_eq!(check(limits, "[[[1]]]"), Err(Limit::Depth));
+        assert_eq!(check(limits, r#"{"a": [{"b": 1}]}"#), Err(Limit::Depth));
+    }
+
+    #[test]
+    fn string_length() {
+        let limits = Limits::new().string_length(3);
+        assert_eq!(check(limits, r#"["abc"]"#), Ok(()));
+        assert_eq!(check(limits, r#"["abcd"]"#), Err(Limit::StringLength));
+        // Object keys are strings too.
+        assert_eq!(check(limits, r#"{"abcd": 1}"#), Err(Limit::StringLength));
+        // The length is in bytes.
+        assert_eq!(check(limits, r#""éé""#), Err(Limit::StringLength));
+    }
+
+    #[test]
+    fn array_length() {
+        let limits = Limits::new().array_length(2);
+        assert_eq!(check(limits, "[1, 2]"), Ok(()));
+        assert_eq!(check(limits, "[1, 2, 3]"), Err(Limit::ArrayLength));
+        assert_eq!(
+            check(limits, "[[1, 2], [[1, 2, 3]]]"),
+            Err(Limit::ArrayLength)
+        );
+    }
+
+    #[test]
+    fn malformed_input() {
+        // Left for the procedure to report.
+        assert_eq!(check(Limits::new(), r#"{"a": "#), Ok(()));
+        assert_eq!(check(Limits::new().depth(1), "[[1"), Err(Limit::Depth));
+    }
+
+    #[derive(Type)]
+    enum Error {}
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            match self {}
+        }
+    }
+
+    fn request(method: &str, path: &str, input: Value) -> jsonrpc::Request {
+        serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": { "path": path, "input": input },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn requests() {
+        let (procedures, _) = <Router>::new()
+            .procedure(
+                "default",
+                Procedure::builder::<Error>().query(|_, _: Value| async { Ok(()) }),
+            )
+            .procedure(
+                "small",
+                Procedure::builder::<Error>()
+                    .with(limits(Limits::new().body_size(8).depth(1)))
+                    .query(|_, _: Value| async { Ok(()) }),
+            )
+            .build()
+            .unwrap();
+        let limits = Limits::new();
+        let check = |req| {
+            limits
+                .check_request(&procedures, &req)
+                .map_err(|exceeded| exceeded.limit)
+        };
+
+        assert_eq!(check(request("query", "default", json!([[1]]))), Ok(()));
+        assert_eq!(
+            check(request("query", "default", json!("abcdefgh"))),
+            Ok(())
+        );
+        assert_eq!(check(request("query", "small", json!([1]))), Ok(()));
+        // A request in a batch or websocket message gets the limits of it's procedure.
+        assert_eq!(
+            check(request("query", "small", json!([[1]]))),
+            Err(Limit::Depth)
+        );
+        assert_eq!(
+            check(request("mutation", "small", json!("abcdefgh"))),
+            Err(Limit::BodySize)
+        );
+        assert_eq!(
+            check(request("subscription", "small", json!([1, [[1]]]))),
+            Err(Limit::Depth)
+        );
+        assert_eq!(check(request("query", "small", Value::Null)), Ok(()));
+    }
+
+    #[test]
+    fn errors() {
+        let exceeded = Limits::new().body_size(10).body_too_large();
+        assert_eq!(exceeded.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let error = exceeded.error();
+        assert_eq!(error.code, 413);
+        assert_eq!(
+            error.data,
+            Some(json!({ "~rspc": true, "limit": "bodySize", "max": 10 }))
+        );
+
+        let exceeded = Limits::new().depth(1).check(json!([[1]])).unwrap_err();
+        assert_eq!(exceeded.status(), StatusCode::BAD_REQUEST);
+        let error = exceeded.error();
+        assert_eq!(error.code, 400);
+        assert_eq!(error.message, "the input is nested deeper than 1 levels");
+        assert_eq!(
+            error.data,
+            Some(json!({ "~rspc": true, "limit": "depth", "max": 1 }))
+        );
+    }
+
+    #[test]
+    fn codecs() {
+        let limits = Limits::new().array_length(1);
+        assert!(Codec::Json.check(&limits, b"").is_ok());
+        assert!(Codec::Json.check(&limits, b"[1]").is_ok());
+        assert!(Codec::Json.check(&limits, b"[1, 2]").is_err());
+
+        #[cfg(feature = "msgpack")]
+        {
+            let input = rmp_serde::to_vec(&[1, 2]).unwrap();
+            assert!(Codec::MessagePack.check(&limits, &input).is_err());
+        }
+
+        #[cfg(feature = "cbor")]
+        {
+            let input = cbor4ii::serde::to_vec(Vec::new(), &[1, 2]).unwrap();
+            assert!(Codec::Cbor.check(&limits, &input).is_err());
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::{convert::Infallible, future::poll_fn, task::Poll};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, Response},
+    response::IntoResponse,
+};
+use futures::{stream, StreamExt};
+use rspc_procedure::ProcedureStream;
+
+use crate::{codec::Codec, jsonrpc::RequestId, shutdown, ResponseHeaders, Shutdown};
+
+pub(crate) const NDJSON: &str = "application/x-ndjson";
+
+// If the client wants the response as newline delimited JSON.
+pub(crate) fn accepts(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .any(|v| v.to_str().is_ok_and(|v| v.contains(NDJSON)))
+}
+
+// Run every procedure until it has resolved or called `rspc::flush` so they all have a chance to set headers.
+// The streams must be using `require_manual_stream` and won't yield a value until `stream` is called.
+//
+// Returns which streams finished without yielding a value.
+pub(crate) async fn wait_for_headers(streams: &mut [&mut ProcedureStream]) -> Vec<bool> {
+    let mut ended = vec![false; streams.len()];
+    let mut ready = vec![false; streams.len()];
+    poll_fn(|cx| {
+        for ((stream, ended), ready) in streams.iter_mut().zip(&mut ended).zip(&mut ready) {
+            if !*ready {
+                *ended = stream.poll_next(cx).is_ready();
+                *ready = *ended || stream.resolved() || stream.flushable();
+            }
+        }
+
+        match ready.iter().all(|ready| *ready) {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    })
+    .await;
+    ended
+}
+
+// Stream every value from a procedure as a line of JSON.
+//
+// This allows a procedure returning a `rspc::Stream` to be consumed incrementally over HTTP.
+// The headers are sent once the procedure resolves or calls `rspc::flush`.
+pub(crate) async fn handle_ndjson(
+    stream: ProcedureStream,
+    headers: ResponseHeaders,
+    shutdown: Option<&Shutdown>,
+) -> Response<Body> {
+    let mut stream = stream.require_manual_stream();
+    let ended = wait_for_headers(&mut [&mut stream]).await[0];
+    stream.stream();
+
+    let body = stream::unfold((stream, ended), |(mut stream, ended)| async move {
+        if ended {
+            return None;
+        }
+
+        let result = stream.next().await?;
+        Some((
+            Codec::Json.encode_result(&RequestId::Null, Some(result)),
+            (stream, false),
+        ))
+    });
+    // If the server is shutting down the last line is an error telling the client to reconnect elsewhere.
+    let body =
+        shutdown::until_expired(body, shutdown, shutdown::cut_off(Codec::Json)).map(|mut line| {
+            line.push(b'\n');
+            Ok::<_, Infallible>(Bytes::from(line))
+        });
+
+    let mut response = ([(header::CONTENT_TYPE, NDJSON)], Body::from_stream(body)).into_response();
+    headers.apply(response.headers_mut());
+    response
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use axum::{
+        body::to_bytes,
+        http::{HeaderValue, Request},
+        Extension,
+    };
+    use rspc::{Procedure, ProcedureError, Router};
+    use serde_json::{json, Value};
+    use specta::Type;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Type)]
+    enum Error {}
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            match self {}
+        }
+    }
+
+    fn app() -> axum::Router {
+        let (procedures, _) = Router::<ResponseHeaders>::new()
+            .procedure(
+                "count",
+                Procedure::builder::<Error>().query(|_, to: u32| async move {
+                    Ok(rspc::Stream(stream::iter((1..=to).map(Ok))))
+                }),
+            )
+            .procedure(
+                "export",
+                Procedure::builder::<Error>().query(|headers: ResponseHeaders, _: ()| async move {
+                    headers.insert("x-export", HeaderValue::from_static("1"));
+                    rspc::flush().await;
+
+                    // This never finishes so anything the client receives was sent incrementally.
+                    Ok(rspc::Stream(
+                        stream::iter([Ok("a")]).chain(stream::pending()),
+                    ))
+                }),
+            )
+            .build()
+            .unwrap();
+        axum::Router::new().nest(
+            "/rspc",
+            crate::endpoint(
+                procedures,
+                |Extension(headers): Extension<ResponseHeaders>| headers,
+            ),
+        )
+    }
+
+    fn request(uri: &str) -> Request<Body> {
+        Request::get(uri)
+            .header(header::ACCEPT, NDJSON)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn lines() {
+        let response = app().oneshot(request("/rspc/count?input=3")).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], NDJSON);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let lines = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["result"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            (1..=3)
+                .map(|i| json!({ "type": "response", "data": i }))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn incremental() {
+        let response = app().oneshot(request("/rspc/export")).await.unwrap();
+        // The headers set before `rspc::flush` are sent.
+        assert_eq!(response.headers()["x-export"], "1");
+
+        let mut body = response.into_body().into_data_stream();
+        let line = body.next().await.unwrap().unwrap();
+        let line: Value = serde_json::from_slice(&line).unwrap();
+        assert_eq!(line["result"]["data"], "a");
+    }
+
+    #[tokio::test]
+    async fn single_value() {
+        // Without `Accept: application/x-ndjson` only the first value is sent.
+        let response = app()
+            .oneshot(
+                Request::get("/rspc/count?input=3")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"]["data"], 1);
+    }
+}
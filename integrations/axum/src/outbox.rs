@@ -0,0 +1,332 @@
+use std::{
+    collections::VecDeque,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+use crate::jsonrpc::{self, ResponseInner};
+
+/// What to do when a subscription produces events faster than the client is reading them.
+///
+/// Set this with [`Config::ws_overflow`](crate::Config::ws_overflow).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Overflow {
+    /// Wait for space in the buffer, slowing down the subscription. This is the default.
+    #[default]
+    Backpressure,
+    /// Discard the subscription's oldest event in the buffer to make space for the new one.
+    ///
+    /// Events from other subscriptions are never dropped, so a subscription with nothing buffered waits for space like with [`Overflow::Backpressure`].
+    DropOldest,
+    /// Stop the subscription and send the client an error.
+    CloseSubscription,
+}
+
+// A message which can be queued in an `Outbox`.
+pub(crate) trait Queued {
+    // Is this an event or error from the same subscription as `event`? These are what `Overflow::DropOldest` discards.
+    fn is_event_of(&self, event: &Self) -> bool;
+}
+
+impl Queued for jsonrpc::Response {
+    fn is_event_of(&self, event: &Self) -> bool {
+        self.id == event.id
+            && matches!(
+                self.result,
+                ResponseInner::Event(..) | ResponseInner::Error(..)
+            )
+    }
+}
+
+// A message on a websocket. Binario frames share the outbox so they are bound by the same limits.
+pub(crate) enum Outgoing {
+    JsonRpc(jsonrpc::Response),
+    #[cfg(feature = "binario")]
+    Binario(crate::binario::Frame),
+}
+
+impl From<jsonrpc::Response> for Outgoing {
+    fn from(response: jsonrpc::Response) -> Self {
+        Self::JsonRpc(response)
+    }
+}
+
+#[cfg(feature = "binario")]
+impl From<crate::binario::Frame> for Outgoing {
+    fn from(frame: crate::binario::Frame) -> Self {
+        Self::Binario(frame)
+    }
+}
+
+impl Queued for Outgoing {
+    fn is_event_of(&self, event: &Self) -> bool {
+        // JSON-RPC and Binario subscriptions are tracked separately so their ids can't be compared.
+        match (self, event) {
+            (Self::JsonRpc(a), Self::JsonRpc(b)) => a.is_event_of(b),
+            #[cfg(feature = "binario")]
+            (Self::Binario(a), Self::Binario(b)) => a.is_event_of(b),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+}
+
+// The subscription was stopped because the buffer was full or the connection has closed.
+#[derive(Debug)]
+pub(crate) struct Overflowed;
+
+// The messages waiting to be sent on a websocket.
+//
+// Responses to requests are always queued as they are bounded by the messages the client sends.
+// Subscription events are limited to `capacity` with the `Overflow` deciding what happens when it's full.
+pub(crate) struct Outbox<T = jsonrpc::Response> {
+    queue: Mutex<VecDeque<T>>,
+    capacity: usize,
+    overflow: Overflow,
+    closed: AtomicBool,
+    readable: Notify,
+    writable: Notify,
+}
+
+impl<T: Queued> Outbox<T> {
+    pub(crate) fn new(capacity: usize, overflow: Overflow) -> Self {
+        Self {
+            queue: Default::default(),
+            capacity,
+            overflow,
+            closed: AtomicBool::new(false),
+            readable: Notify::new(),
+            writable: Notify::new(),
+        }
+    }
+
+    fn queue(&self) -> std::sync::MutexGuard<'_, VecDeque<T>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn push(&self, response: impl Into<T>) {
+        self.queue().push_back(response.into());
+        self.readable.notify_one();
+    }
+
+    pub(crate) async fn push_event(&self, response: impl Into<T>) -> Result<(), Overflowed> {
+        let response = response.into();
+        loop {
+            let mut writable = pin!(self.writable.notified());
+            writable.as_mut().enable();
+
+            if self.closed.load(Ordering::Relaxed) {
+                return Err(Overflowed);
+            }
+
+            {
+                let mut queue = self.queue();
+                if queue.len() < self.capacity {
+                    queue.push_back(response);
+                    drop(queue);
+                    self.readable.notify_one();
+                    return Ok(());
+                }
+
+                match self.overflow {
+                    Overflow::Backpressure => {}
+                    Overflow::DropOldest => {
+                        // Responses to requests and events from other subscriptions are never dropped.
+                        if let Some(i) = queue.iter().position(|r| r.is_event_of(&response)) {
+                            queue.remove(i);
+                            queue.push_back(response);
+                            drop(queue);
+                            self.readable.notify_one();
+                            return Ok(());
+                        }
+                    }
+                    Overflow::CloseSubscription => return Err(Overflowed),
+                }
+            }
+
+            writable.await;
+        }
+    }
+
+    // Wait for the next message to send. This is cancel safe.
+    pub(crate) async fn pop(&self) -> T {
+        loop {
+            if let Some(response) = self.try_pop() {
+                return response;
+            }
+
+            self.readable.notified().await;
+        }
+    }
+
+    // The next message to send, if there is one.
+    pub(crate) fn try_pop(&self) -> Option<T> {
+        let response = self.queue().pop_front();
+        if response.is_some() {
+            self.writable.notify_waiters();
+        }
+        response
+    }
+
+    // Stop any subscriptions waiting for space.
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        self.writable.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::jsonrpc::RequestId;
+
+    fn event(id: u32, value: u32) -> jsonrpc::Response {
+        jsonrpc::Response {
+            jsonrpc: "2.0",
+            id: RequestId::Number(id),
+            result: ResponseInner::Event(json!(value), None),
+        }
+    }
+
+    fn response(id: u32) -> jsonrpc::Response {
+        jsonrpc::Response {
+            jsonrpc: "2.0",
+            id: RequestId::Number(id),
+            result: ResponseInner::Response(json!(null)),
+        }
+    }
+
+    fn error(id: u32) -> jsonrpc::Response {
+        jsonrpc::Response {
+            jsonrpc: "2.0",
+            id: RequestId::Number(id),
+            result: ResponseInner::Error(jsonrpc::JsonRPCError {
+                code: 500,
+                message: "error".into(),
+                data: None,
+            }),
+        }
+    }
+
+    // The subscription id and value of everything queued, with `None` for responses.
+    fn drain(outbox: &Outbox) -> Vec<(RequestId, Option<u32>)> {
+        std::iter::from_fn(|| outbox.try_pop())
+            .map(|r| match r.result {
+                ResponseInner::Event(v, _) => (r.id, Some(v.as_u64().unwrap() as u32)),
+                _ => (r.id, None),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn responses_ignore_capacity() {
+        let outbox = Outbox::<jsonrpc::Response>::new(1, Overflow::CloseSubscription);
+        outbox.push(response(1));
+        outbox.push(response(2));
+        outbox.push(response(3));
+
+        assert_eq!(outbox.try_pop().unwrap().id, RequestId::Number(1));
+        assert_eq!(drain(&outbox).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn backpressure() {
+        let outbox = Arc::new(Outbox::<jsonrpc::Response>::new(1, Overflow::Backpressure));
+        outbox.push_event(event(1, 0)).await.unwrap();
+
+        let task = tokio::spawn({
+            let outbox = outbox.clone();
+            async move { outbox.push_event(event(1, 1)).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!task.is_finished());
+
+        assert_eq!(outbox.pop().await.id, RequestId::Number(1));
+        task.await.unwrap().unwrap();
+        assert_eq!(drain(&outbox), vec![(RequestId::Number(1), Some(1))]);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_is_per_subscription() {
+        let outbox = Outbox::<jsonrpc::Response>::new(3, Overflow::DropOldest);
+        outbox.push_event(event(1, 0)).await.unwrap();
+        outbox.push_event(event(2, 0)).await.unwrap();
+        outbox.push(response(3));
+        outbox.push_event(event(1, 1)).await.unwrap();
+        outbox.push_event(event(2, 1)).await.unwrap();
+
+        assert_eq!(
+            drain(&outbox),
+            vec![
+                (RequestId::Number(3), None),
+                (RequestId::Number(1), Some(1)),
+                (RequestId::Number(2), Some(1)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_at_the_limit() {
+        let outbox = Arc::new(Outbox::<jsonrpc::Response>::new(2, Overflow::DropOldest));
+        outbox.push_event(event(1, 0)).await.unwrap();
+        outbox.push_event(event(2, 0)).await.unwrap();
+        outbox.push_event(error(1)).await.unwrap();
+        // Errors are dropped like events.
+        outbox.push_event(event(1, 1)).await.unwrap();
+        assert_eq!(outbox.queue().len(), 2);
+
+        // A subscription with nothing to drop waits for space.
+        let task = tokio::spawn({
+            let outbox = outbox.clone();
+            async move { outbox.push_event(event(3, 0)).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!task.is_finished());
+        assert_eq!(outbox.queue().len(), 2);
+
+        assert_eq!(outbox.pop().await.id, RequestId::Number(2));
+        task.await.unwrap().unwrap();
+        assert_eq!(
+            drain(&outbox),
+            vec![
+                (RequestId::Number(1), Some(1)),
+                (RequestId::Number(3), Some(0))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn close_subscription() {
+        let outbox = Outbox::<jsonrpc::Response>::new(1, Overflow::CloseSubscription);
+        outbox.push_event(event(1, 0)).await.unwrap();
+        assert!(outbox.push_event(event(1, 1)).await.is_err());
+        assert_eq!(drain(&outbox), vec![(RequestId::Number(1), Some(0))]);
+    }
+
+    #[tokio::test]
+    async fn close_stops_waiting_subscriptions() {
+        let outbox = Arc::new(Outbox::<jsonrpc::Response>::new(1, Overflow::Backpressure));
+        outbox.push_event(event(1, 0)).await.unwrap();
+
+        let task = tokio::spawn({
+            let outbox = outbox.clone();
+            async move { outbox.push_event(event(1, 1)).await }
+        });
+        tokio::task::yield_now().await;
+
+        outbox.close();
+        assert!(task.await.unwrap().is_err());
+        assert!(outbox.push_event(event(2, 0)).await.is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use axum::{
+    body::Body,
+    http::{Response, StatusCode},
+};
+
+use crate::{
+    codec::Codec,
+    jsonrpc::{self, JsonRPCError, RequestId, RequestInner, ResponseInner},
+};
+
+/// Allow clients to call procedures using their hash from [`Types::persisted_queries`](rspc::Types::persisted_queries) instead of their name.
+///
+/// A query can then be made with `GET /rspc/{hash}?input=...`. Hashes can also be used in place of the path in batches and websocket messages.
+/// Use the Typescript exporter's `export_persisted_queries_to` to get the hashes on the frontend.
+///
+/// As [`Types::persisted_queries`](rspc::Types::persisted_queries) contains every procedure, an [allow-list](PersistedQueries::allow_list) of the hashes your clients actually use can be provided to reject everything else.
+///
+/// ```rust,ignore
+/// let (procedures, types) = router.build().unwrap();
+///
+/// // Eg. the hashes collected from your frontend's build.
+/// let used: Vec<String> = serde_json::from_str(include_str!("../persisted-queries.json")).unwrap();
+///
+/// let config = rspc_axum::Config::new()
+///     .persisted_queries(PersistedQueries::new(types.persisted_queries()).allow_list(used));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PersistedQueries {
+    // Hash to procedure name.
+    hashes: Arc<HashMap<String, String>>,
+    allow_list: Option<Arc<HashSet<String>>>,
+}
+
+impl PersistedQueries {
+    /// Create from a map of procedure name to hash, as returned by [`Types::persisted_queries`](rspc::Types::persisted_queries).
+    pub fn new(manifest: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self {
+            hashes: Arc::new(
+                manifest
+                    .into_iter()
+                    .map(|(key, hash)| (hash, key))
+                    .collect(),
+            ),
+            allow_list: None,
+        }
+    }
+
+    /// Only accept requests using one of these hashes. By default any procedure can be called by it's hash or name.
+    ///
+    /// This locks the endpoint down to the operations known by your clients. It applies to HTTP requests, batches and websockets.
+    pub fn allow_list(mut self, hashes: impl IntoIterator<Item = String>) -> Self {
+        self.allow_list = Some(Arc::new(hashes.into_iter().collect()));
+        self
+    }
+
+    // Get the name of the procedure a request is for.
+    pub(crate) fn resolve<'a>(&self, path: &'a str) -> Result<Cow<'a, str>, NotAllowed> {
+        if let Some(allow_list) = &self.allow_list {
+            if !allow_list.contains(path) {
+                return Err(NotAllowed);
+            }
+        }
+
+        match self.hashes.get(path) {
+            Some(key) => Ok(Cow::Owned(key.clone())),
+            // A hash in the allow-list for a procedure which doesn't exist will be a 404.
+            None => Ok(Cow::Borrowed(path)),
+        }
+    }
+
+    // Replace the hash in a JSON-RPC request with the name of it's procedure.
+    pub(crate) fn resolve_request(&self, request: &mut jsonrpc::Request) -> Result<(), NotAllowed> {
+        let path = match &mut request.inner {
+            RequestInner::Query { path, .. }
+            | RequestInner::Mutation { path, .. }
+            | RequestInner::Subscription { path, .. } => path,
+            RequestInner::SubscriptionStop { .. } => return Ok(()),
+        };
+
+        if let Cow::Owned(key) = self.resolve(path)? {
+            *path = key;
+        }
+        Ok(())
+    }
+}
+
+// A request for an operation which isn't in the allow-list.
+#[derive(Debug, Clone)]
+pub(crate) struct NotAllowed;
+
+impl NotAllowed {
+    pub(crate) fn error(&self) -> JsonRPCError {
+        JsonRPCError {
+            code: 403,
+            message: "the requested operation is not a persisted query".into(),
+            data: None,
+        }
+    }
+
+    pub(crate) fn response(&self, id: RequestId) -> jsonrpc::Response {
+        jsonrpc::Response {
+            jsonrpc: "2.0",
+            id,
+            result: ResponseInner::Error(self.error()),
+        }
+    }
+
+    pub(crate) fn into_http(self, accept: Codec, problem_details: bool) -> Response<Body> {
+        crate::error::response(StatusCode::FORBIDDEN, self.error(), accept, problem_details)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> PersistedQueries {
+        PersistedQueries::new([
+            ("version".to_string(), "aaaa".to_string()),
+            ("users.get".to_string(), "bbbb".to_string()),
+        ])
+    }
+
+    #[test]
+    fn resolve() {
+        let persisted = manifest();
+        assert_eq!(persisted.resolve("aaaa").unwrap(), "version");
+        assert_eq!(persisted.resolve("bbbb").unwrap(), "users.get");
+        // Names can still be used without an allow-list.
+        assert_eq!(persisted.resolve("version").unwrap(), "version");
+        assert_eq!(persisted.resolve("unknown").unwrap(), "unknown");
+    }
+
+    #[test]
+    fn allow_list() {
+        let persisted = manifest().allow_list(["aaaa".to_string()]);
+        assert_eq!(persisted.resolve("aaaa").unwrap(), "version");
+        // In the manifest but not used by the client.
+        assert!(persisted.resolve("bbbb").is_err());
+        assert!(persisted.resolve("version").is_err());
+        assert!(persisted.resolve("unknown").is_err());
+
+        let persisted = manifest().allow_list([]);
+        assert!(persisted.resolve("aaaa").is_err());
+    }
+
+    #[test]
+    fn resolve_request() {
+        let persisted = manifest().allow_list(["bbbb".to_string()]);
+
+        let mut request: jsonrpc::Request = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "method": "query",
+            "params": { "path": "bbbb", "input": null }
+        }))
+        .unwrap();
+        persisted.resolve_request(&mut request).unwrap();
+        assert!(matches!(&request.inner, RequestInner::Query { path, .. } if path == "users.get"));
+
+        let mut request: jsonrpc::Request = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "method": "mutation",
+            "params": { "path": "users.get", "input": null }
+        }))
+        .unwrap();
+        assert!(persisted.resolve_request(&mut request).is_err());
+
+        // Stopping a subscription doesn't have a path.
+        let mut request: jsonrpc::Request = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "method": "subscriptionStop",
+            "params": { "input": 1 }
+        }))
+        .unwrap();
+        persisted.resolve_request(&mut request).unwrap();
+    }
+}
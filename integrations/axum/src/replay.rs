@@ -0,0 +1,624 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, VecDeque},
+    hash::BuildHasher,
+    pin::pin,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use axum::http::{header, HeaderMap};
+use rspc_procedure::ProcedureStream;
+use serde_json::{json, Value};
+use tokio::{sync::Notify, time::Instant};
+
+use crate::{jsonrpc::JsonRPCError, jsonrpc_exec::next};
+
+type Event = (u64, Result<Value, JsonRPCError>);
+
+// Subscriptions which keep running when their client disconnects so they can be resumed.
+//
+// Every event is given an id of the form `{key}-{seq}` where `key` is a random token identifying the subscription and `seq` increases with every event.
+// The last `capacity` events are kept so a client resuming with the id of the last event it received is sent the ones it missed.
+// A client which missed more than that, or whose subscription has stopped, is sent a gap error first.
+// A subscription can only be resumed with the same path and input by the same caller and is stopped after `retention` if nobody is listening to it.
+// Each caller can leave at most `max_retained` subscriptions running, after which the one idle the longest is stopped.
+pub(crate) struct Replay {
+    subscriptions: Mutex<HashMap<String, Arc<Subscription>>>,
+    capacity: usize,
+    retention: Duration,
+    max_retained: usize,
+    // Randomly seeded so fingerprints can't be computed outside of the server.
+    hasher: RandomState,
+}
+
+// Who started a subscription. This is a hash of the request's `Authorization` and `Cookie` headers, or `None` if it had neither.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Caller(Option<u64>);
+
+struct Subscription {
+    // A hash of the path, input and caller which must match to resume the subscription.
+    fingerprint: u64,
+    caller: Caller,
+    state: Mutex<State>,
+    changed: Notify,
+    stop: Notify,
+}
+
+struct State {
+    events: VecDeque<Event>,
+    seq: u64,
+    done: bool,
+    listeners: usize,
+    idle_since: Option<Instant>,
+}
+
+impl Subscription {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Replay {
+    pub(crate) fn new(capacity: usize, retention: Duration, max_retained: usize) -> Self {
+        Self {
+            subscriptions: Default::default(),
+            capacity: capacity.max(1),
+            retention,
+            max_retained,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub(crate) fn caller(&self, headers: &HeaderMap) -> Caller {
+        let credentials = |name| {
+            headers
+                .get_all(name)
+                .iter()
+                .map(|v| v.as_bytes())
+                .collect::<Vec<_>>()
+        };
+        let (authorization, cookie) = (
+            credentials(header::AUTHORIZATION),
+            credentials(header::COOKIE),
+        );
+        if authorization.is_empty() && cookie.is_empty() {
+            return Caller(None);
+        }
+
+        Caller(Some(self.hasher.hash_one((authorization, cookie))))
+    }
+
+    fn subscriptions(&self) -> MutexGuard<'_, HashMap<String, Arc<Subscription>>> {
+        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Resume the subscription the event id belongs to or start a new one using `exec`.
+    //
+    // The procedure is only executed if there is nothing to resume.
+    // `limit` is how many more subscriptions the caller can start, which includes the ones they have left running to resume later.
+    pub(crate) fn subscribe(
+        self: &Arc<Self>,
+        path: &str,
+        input: &[u8],
+        caller: Caller,
+        last_event_id: Option<&str>,
+        limit: usize,
+        exec: impl FnOnce() -> ProcedureStream,
+    ) -> Result<Subscriber, JsonRPCError> {
+        let fingerprint = self.hasher.hash_one((path, input, caller));
+        let last_event_id = last_event_id.and_then(|id| {
+            let (key, seq) = id.trim().rsplit_once('-')?;
+            Some((key, seq.parse::<u64>().ok()?))
+        });
+
+        let mut subscriptions = self.subscriptions();
+        if let Some((key, cursor)) = last_event_id {
+            if let Some(subscription) = subscriptions.get(key) {
+                if subscription.fingerprint != fingerprint {
+                    return Err(JsonRPCError {
+                        code: 403,
+                        message: "the subscription can only be resumed by the client which started it with the same input".into(),
+                        data: None,
+                    });
+                }
+
+                let mut state = subscription.state();
+                state.listeners += 1;
+                state.idle_since = None;
+                drop(state);
+
+                return Ok(Subscriber {
+                    replay: self.clone(),
+                    key: key.to_string(),
+                    subscription: subscription.clone(),
+                    cursor,
+                    restarted: false,
+                });
+            }
+        }
+
+        // Anonymous callers can't be told apart so only the subscriptions on their connection are counted.
+        // The ones they leave running are still bounded by `max_retained` when they detach.
+        let retained = match caller {
+            Caller(Some(_)) => subscriptions
+                .values()
+                .filter(|s| {
+                    let state = s.state();
+                    s.caller == caller && state.listeners == 0 && !state.done
+                })
+                .count(),
+            Caller(None) => 0,
+        };
+        if retained >= limit {
+            return Err(JsonRPCError {
+                code: 429,
+                message: "error creating subscription as the connection has too many subscriptions"
+                    .into(),
+                data: None,
+            });
+        }
+
+        let mut random = [0u8; 16];
+        if let Err(_err) = getrandom::fill(&mut random) {
+            // #[cfg(feature = "tracing")]
+            // tracing::error!("Error generating subscription id: {}", _err);
+
+            return Err(JsonRPCError {
+                code: 500,
+                message: "error generating subscription id".into(),
+                data: None,
+            });
+        }
+        let key = random
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        let subscription = Arc::new(Subscription {
+            fingerprint,
+            caller,
+            state: Mutex::new(State {
+                events: VecDeque::new(),
+                seq: 0,
+                done: false,
+                listeners: 1,
+                idle_since: None,
+            }),
+            changed: Notify::new(),
+            stop: Notify::new(),
+        });
+        subscriptions.insert(key.clone(), subscription.clone());
+        drop(subscriptions);
+
+        tokio::spawn(produce(exec(), subscription.clone(), self.capacity));
+
+        Ok(Subscriber {
+            replay: self.clone(),
+            key,
+            subscription,
+            cursor: 0,
+            // The client expected to resume a subscription which has since stopped.
+            restarted: last_event_id.is_some(),
+        })
+    }
+
+    // Stop the subscriptions the caller has left running the longest until they are within `max_retained`.
+    fn evict(&self, caller: Caller) {
+        let mut subscriptions = self.subscriptions();
+        let mut idle = subscriptions
+            .iter()
+            .filter_map(|(key, s)| {
+                let state = s.state();
+                (s.caller == caller && state.listeners == 0)
+                    .then(|| (state.idle_since, key.clone()))
+            })
+            .collect::<Vec<_>>();
+        if idle.len() <= self.max_retained {
+            return;
+        }
+
+        idle.sort();
+        for (_, key) in &idle[..idle.len() - self.max_retained] {
+            if let Some(subscription) = subscriptions.remove(key) {
+                subscription.stop.notify_one();
+            }
+        }
+    }
+
+    fn remove(&self, key: &str, subscription: &Arc<Subscription>) {
+        let mut subscriptions = self.subscriptions();
+        if subscriptions
+            .get(key)
+            .is_some_and(|s| Arc::ptr_eq(s, subscription))
+        {
+            subscriptions.remove(key);
+        }
+    }
+}
+
+// Run the procedure, buffering it's events until it finishes or is stopped.
+async fn produce(mut stream: ProcedureStream, subscription: Arc<Subscription>, capacity: usize) {
+    loop {
+        let result = tokio::select! {
+            biased;
+            _ = subscription.stop.notified() => break,
+            result = next(&mut stream) => result,
+        };
+        let Some(result) = result else {
+            break;
+        };
+
+        let mut state = subscription.state();
+        state.seq += 1;
+        let seq = state.seq;
+        state.events.push_back((seq, result));
+        if state.events.len() > capacity {
+            state.events.pop_front();
+        }
+        drop(state);
+        subscription.changed.notify_waiters();
+    }
+
+    subscription.state().done = true;
+    subscription.changed.notify_waiters();
+}
+
+// A client listening to a resumable subscription.
+//
+// Dropping this detaches the client, leaving the subscription running so it can be resumed.
+pub(crate) struct Subscriber {
+    replay: Arc<Replay>,
+    key: String,
+    subscription: Arc<Subscription>,
+    cursor: u64,
+    restarted: bool,
+}
+
+impl Subscriber {
+    // The id the client can resume from after receiving this event.
+    pub(crate) fn event_id(&self, seq: u64) -> String {
+        format!("{}-{seq}", self.key)
+    }
+
+    // The next event the client hasn't received.
+    //
+    // If the client falls further behind than the buffer the events it missed are skipped and it's sent a gap error instead.
+    // The gap has the id of the last missed event so resuming from it continues with the next available one.
+    pub(crate) async fn next(&mut self) -> Option<Event> {
+        if std::mem::take(&mut self.restarted) {
+            return Some((
+                self.cursor,
+                Err(gap(
+                    "the subscription could not be resumed so it was restarted",
+                    None,
+                )),
+            ));
+        }
+
+        loop {
+            let mut changed = pin!(self.subscription.changed.notified());
+            changed.as_mut().enable();
+
+            {
+                let state = self.subscription.state();
+                if let Some((seq, result)) = state.events.iter().find(|(seq, _)| *seq > self.cursor)
+                {
+                    if *seq > self.cursor + 1 {
+                        let missed = *seq - self.cursor - 1;
+                        self.cursor = *seq - 1;
+                        return Some((
+                            self.cursor,
+                            Err(gap(
+                                "events were missed as the client fell too far behind",
+                                Some(missed),
+                            )),
+                        ));
+                    }
+
+                    self.cursor = *seq;
+                    return Some((*seq, result.clone()));
+                }
+
+                if state.done {
+                    return None;
+                }
+            }
+
+            changed.await;
+        }
+    }
+
+    // Stop the subscription so it can't be resumed. This is used when the client unsubscribes.
+    #[cfg_attr(not(feature = "ws"), allow(dead_code))]
+    pub(crate) fn stop(self) {
+        self.subscription.stop.notify_one();
+        self.replay.remove(&self.key, &self.subscription);
+    }
+}
+
+// Tells the client it didn't receive every event. `missed` is how many if that's known.
+fn gap(message: &str, missed: Option<u64>) -> JsonRPCError {
+    JsonRPCError {
+        code: 410,
+        message: message.into(),
+        data: Some(json!({ "~rspc": true, "missed": missed })),
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        let mut state = self.subscription.state();
+        state.listeners -= 1;
+        if state.listeners != 0 {
+            return;
+        }
+        state.idle_since = Some(Instant::now());
+        drop(state);
+        self.replay.evict(self.subscription.caller);
+
+        let (replay, key, subscription) = (
+            self.replay.clone(),
+            self.key.clone(),
+            self.subscription.clone(),
+        );
+        tokio::spawn(async move {
+            tokio::time::sleep(replay.retention).await;
+
+            // The subscription could have been resumed and detached again in the meantime.
+            let state = subscription.state();
+            if state.listeners == 0
+                && state
+                    .idle_since
+                    .is_some_and(|since| since.elapsed() >= replay.retention)
+            {
+                drop(state);
+                subscription.stop.notify_one();
+                replay.remove(&key, &subscription);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use axum::http::HeaderValue;
+    use futures::stream;
+    use rspc_procedure::ProcedureError;
+    use serde_json::json;
+
+    use super::*;
+
+    fn values(values: Vec<u32>) -> impl FnOnce() -> ProcedureStream {
+        move || {
+            ProcedureStream::from_stream(stream::iter(
+                values.into_iter().map(Ok::<_, ProcedureError>),
+            ))
+        }
+    }
+
+    fn pending() -> ProcedureStream {
+        ProcedureStream::from_stream(stream::pending::<Result<u32, ProcedureError>>())
+    }
+
+    fn caller(replay: &Replay, cookie: &'static str) -> Caller {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_static(cookie));
+        replay.caller(&headers)
+    }
+
+    #[test]
+    fn callers() {
+        let replay = Replay::new(10, Duration::from_secs(30), 10);
+        assert_eq!(replay.caller(&HeaderMap::new()), Caller(None));
+        assert_eq!(caller(&replay, "session=a"), caller(&replay, "session=a"));
+        assert_ne!(caller(&replay, "session=a"), caller(&replay, "session=b"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("session=a"));
+        assert_ne!(replay.caller(&headers), caller(&replay, "session=a"));
+    }
+
+    #[tokio::test]
+    async fn resume() {
+        let replay = Arc::new(Replay::new(10, Duration::from_secs(30), 10));
+        let caller = caller(&replay, "session=a");
+
+        let mut subscriber = replay
+            .subscribe("numbers", b"null", caller, None, 1, values(vec![1, 2, 3]))
+            .unwrap();
+        let (seq, value) = subscriber.next().await.unwrap();
+        assert_eq!(value.unwrap(), json!(1));
+        let event_id = subscriber.event_id(seq);
+        drop(subscriber);
+
+        // The procedure isn't run again when resuming.
+        let mut subscriber = replay
+            .subscribe(
+                "numbers",
+                b"null",
+                caller,
+                Some(&event_id),
+                1,
+                || unreachable!(),
+            )
+            .unwrap();
+        assert_eq!(subscriber.next().await.unwrap().1.unwrap(), json!(2));
+        assert_eq!(subscriber.next().await.unwrap().1.unwrap(), json!(3));
+        assert!(subscriber.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn keys_are_random() {
+        let replay = Arc::new(Replay::new(10, Duration::from_secs(30), 10));
+        let a = replay
+            .subscribe("numbers", b"null", Caller(None), None, 1, pending)
+            .unwrap();
+        let b = replay
+            .subscribe("numbers", b"null", Caller(None), None, 1, pending)
+            .unwrap();
+
+        assert_eq!(a.key.len(), 32);
+        assert!(a.key.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a.key, b.key);
+    }
+
+    #[tokio::test]
+    async fn resume_must_match() {
+        let replay = Arc::new(Replay::new(10, Duration::from_secs(30), 10));
+        let caller_a = caller(&replay, "session=a");
+        let subscriber = replay
+            .subscribe("numbers", b"1", caller_a, None, 1, pending)
+            .unwrap();
+        let event_id = subscriber.event_id(0);
+        drop(subscriber);
+
+        let resume = |path, input: &[u8], caller| {
+            replay
+                .subscribe(path, input, caller, Some(&event_id), 1, pending)
+                .map(|s| s.key.clone())
+        };
+        assert_eq!(resume("other", b"1", caller_a).unwrap_err().code, 403);
+        assert_eq!(resume("numbers", b"2", caller_a).unwrap_err().code, 403);
+        let caller_b = caller(&replay, "session=b");
+        assert_eq!(resume("numbers", b"1", caller_b).unwrap_err().code, 403);
+        assert_eq!(resume("numbers", b"1", Caller(None)).unwrap_err().code, 403);
+        assert_eq!(
+            resume("numbers", b"1", caller_a).unwrap(),
+            event_id.rsplit_once('-').unwrap().0
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_event_id_starts_a_new_subscription() {
+        let replay = Arc::new(Replay::new(10, Duration::from_secs(30), 10));
+        let mut subscriber = replay
+            .subscribe(
+                "numbers",
+                b"null",
+                Caller(None),
+                Some("abc-1"),
+                1,
+                values(vec![1]),
+            )
+            .unwrap();
+
+        assert_ne!(subscriber.key, "abc");
+        // The client is told it's subscription was restarted.
+        let (seq, value) = subscriber.next().await.unwrap();
+        let err = value.unwrap_err();
+        assert_eq!((seq, err.code), (0, 410));
+        assert_eq!(err.data, Some(json!({ "~rspc": true, "missed": null })));
+        let (seq, value) = subscriber.next().await.unwrap();
+        assert_eq!((seq, value.unwrap()), (1, json!(1)));
+    }
+
+    #[tokio::test]
+    async fn gaps() {
+        let replay = Arc::new(Replay::new(2, Duration::from_secs(30), 10));
+        let mut subscriber = replay
+            .subscribe(
+                "numbers",
+                b"null",
+                Caller(None),
+                None,
+                1,
+                values(vec![1, 2, 3, 4, 5]),
+            )
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !subscriber.subscription.state().done {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // Only the last two events were kept so the client is told it missed the first three.
+        let (seq, value) = subscriber.next().await.unwrap();
+        let err = value.unwrap_err();
+        assert_eq!((seq, err.code), (3, 410));
+        assert_eq!(err.data, Some(json!({ "~rspc": true, "missed": 3 })));
+        let event_id = subscriber.event_id(seq);
+        drop(subscriber);
+
+        // Resuming from the gap continues with the next event.
+        let mut subscriber = replay
+            .subscribe(
+                "numbers",
+                b"null",
+                Caller(None),
+                Some(&event_id),
+                1,
+                || unreachable!(),
+            )
+            .unwrap();
+        let (seq, value) = subscriber.next().await.unwrap();
+        assert_eq!((seq, value.unwrap()), (4, json!(4)));
+        let (seq, value) = subscriber.next().await.unwrap();
+        assert_eq!((seq, value.unwrap()), (5, json!(5)));
+        assert!(subscriber.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn retained_subscriptions_are_evicted() {
+        let replay = Arc::new(Replay::new(10, Duration::from_secs(30), 2));
+
+        // Anonymous callers share the limit.
+        let mut event_ids = Vec::new();
+        for _ in 0..3 {
+            let subscriber = replay
+                .subscribe("numbers", b"null", Caller(None), None, 1, pending)
+                .unwrap();
+            event_ids.push(subscriber.event_id(0));
+            drop(subscriber);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(replay.subscriptions().len(), 2);
+
+        // The one left running the longest was stopped.
+        let resume = |event_id: &str| {
+            replay
+                .subscribe("numbers", b"null", Caller(None), Some(event_id), 1, pending)
+                .unwrap()
+        };
+        assert!(resume(&event_ids[0]).restarted);
+        assert!(!resume(&event_ids[2]).restarted);
+    }
+
+    #[tokio::test]
+    async fn retained_subscriptions_are_limited() {
+        let replay = Arc::new(Replay::new(10, Duration::from_secs(30), 10));
+        let caller = caller(&replay, "session=a");
+
+        let running = replay
+            .subscribe("numbers", b"null", caller, None, 2, pending)
+            .unwrap();
+        // Only subscriptions nobody is listening to are counted.
+        let retained = replay
+            .subscribe("numbers", b"null", caller, None, 2, pending)
+            .unwrap();
+        let event_id = retained.event_id(0);
+        drop(retained);
+
+        assert_eq!(
+            replay
+                .subscribe("numbers", b"null", caller, None, 1, pending)
+                .map(|_| ())
+                .unwrap_err()
+                .code,
+            429
+        );
+        // Other callers and resuming aren't affected.
+        let other = self::caller(&replay, "session=b");
+        assert!(replay
+            .subscribe("numbers", b"null", other, None, 1, pending)
+            .is_ok());
+        assert!(replay
+            .subscribe("numbers", b"null", Caller(None), None, 1, pending)
+            .is_ok());
+        assert!(replay
+            .subscribe("numbers", b"null", caller, Some(&event_id), 1, pending)
+            .is_ok());
+        drop(running);
+    }
+}
@@ -0,0 +1,207 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{header, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::{stream, Stream, StreamExt};
+use serde_json::json;
+use tokio::sync::watch;
+
+use crate::{
+    codec::Codec,
+    jsonrpc::{self, JsonRPCError, RequestId, ResponseInner},
+};
+
+/// Gracefully shut down an rspc endpoint.
+///
+/// Once shutdown has started new requests are rejected with a `503 Service Unavailable` and in-flight queries and mutations have the grace period to finish before they are cut off.
+/// Newline delimited JSON and batch responses which are still streaming at that point end with a final `503` error.
+/// Subscriptions are sent a final error telling the client to reconnect elsewhere and then their websocket or Server-Sent Events stream is closed.
+///
+/// Register it with [`Config::shutdown`](crate::Config::shutdown) and pass [`Shutdown::signal`] to Axum's `with_graceful_shutdown`:
+///
+/// ```rust,ignore
+/// let shutdown = rspc_axum::Shutdown::new(Duration::from_secs(30));
+/// let app = axum::Router::new().nest(
+///     "/rspc",
+///     rspc_axum::endpoint_with_config(procedures, || (), Config::new().shutdown(shutdown.clone())),
+/// );
+///
+/// axum::serve(listener, app)
+///     .with_graceful_shutdown(shutdown.signal(async {
+///         tokio::signal::ctrl_c().await.ok();
+///     }))
+///     .await
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Shutdown(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    grace_period: Duration,
+    started: watch::Sender<bool>,
+    websockets: watch::Sender<usize>,
+}
+
+impl Shutdown {
+    /// Create a handle where in-flight requests have `grace_period` to finish.
+    pub fn new(grace_period: Duration) -> Self {
+        Self(Arc::new(Inner {
+            grace_period,
+            started: watch::Sender::new(false),
+            websockets: watch::Sender::new(0),
+        }))
+    }
+
+    /// Start shutting down.
+    pub fn shutdown(&self) {
+        self.0.started.send_replace(true);
+    }
+
+    /// Returns `true` once shutdown has started.
+    pub fn is_shutting_down(&self) -> bool {
+        *self.0.started.borrow()
+    }
+
+    /// Start shutting down once `signal` completes.
+    ///
+    /// The returned future resolves once every websocket has been closed, or the grace period has elapsed, so it can be given to Axum's `with_graceful_shutdown`.
+    pub fn signal<F>(&self, signal: F) -> impl Future<Output = ()> + Send + 'static
+    where
+        F: Future + Send + 'static,
+    {
+        let this = self.clone();
+        async move {
+            signal.await;
+            this.shutdown();
+
+            // Axum doesn't wait for upgraded connections so we give them a chance to send their final messages.
+            let mut websockets = this.0.websockets.subscribe();
+            let _ = tokio::time::timeout(
+                this.0.grace_period,
+                websockets.wait_for(|websockets| *websockets == 0),
+            )
+            .await;
+        }
+    }
+
+    // Resolves once shutdown has started.
+    pub(crate) fn started(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut started = self.0.started.subscribe();
+        async move {
+            let _ = started.wait_for(|started| *started).await;
+        }
+    }
+
+    // Resolves once the grace period for in-flight requests is over.
+    pub(crate) fn expired(&self) -> impl Future<Output = ()> + Send + 'static {
+        let started = self.started();
+        let grace_period = self.0.grace_period;
+        async move {
+            started.await;
+            tokio::time::sleep(grace_period).await;
+        }
+    }
+
+    // Track an open websocket until the guard is dropped.
+    #[cfg(feature = "ws")]
+    pub(crate) fn websocket(&self) -> WebsocketGuard {
+        self.0.websockets.send_modify(|websockets| *websockets += 1);
+        WebsocketGuard(self.clone())
+    }
+}
+
+#[cfg(feature = "ws")]
+pub(crate) struct WebsocketGuard(Shutdown);
+
+#[cfg(feature = "ws")]
+impl Drop for WebsocketGuard {
+    fn drop(&mut self) {
+        (self.0)
+            .0
+            .websockets
+            .send_modify(|websockets| *websockets -= 1);
+    }
+}
+
+// The error for requests made after shutdown has started and the final message sent to subscriptions.
+//
+// `reconnect` tells the client it should retry against another server instead of treating it as a failure.
+pub(crate) fn error() -> JsonRPCError {
+    JsonRPCError {
+        code: 503,
+        message: "server shutting down".into(),
+        data: Some(json!({ "~rspc": true, "reconnect": true })),
+    }
+}
+
+// The response to an HTTP request made after shutdown has started.
+pub(crate) fn response(batch: bool, accept: Codec, problem_details: bool) -> Response<Body> {
+    if !batch {
+        return crate::error::response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            error(),
+            accept,
+            problem_details,
+        );
+    }
+
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::CONTENT_TYPE, accept.mime())],
+        accept
+            .encode(&[jsonrpc::Response {
+                jsonrpc: "2.0",
+                id: RequestId::Null,
+                result: ResponseInner::Error(error()),
+            }])
+            .unwrap_or_else(|_| b"[]".to_vec()),
+    )
+        .into_response()
+}
+
+// The item ending a streamed body which was cut off by the grace period.
+pub(crate) fn cut_off(accept: Codec) -> Vec<u8> {
+    accept
+        .encode(&jsonrpc::Response {
+            jsonrpc: "2.0",
+            id: RequestId::Null,
+            result: ResponseInner::Error(error()),
+        })
+        .expect("error responses are always serializable")
+}
+
+// Stop a streamed body once the grace period is over, ending it with `last` so the client knows it's incomplete.
+pub(crate) fn until_expired<S>(
+    body: S,
+    shutdown: Option<&Shutdown>,
+    last: S::Item,
+) -> impl Stream<Item = S::Item> + Send + 'static
+where
+    S: Stream + Send + 'static,
+    S::Item: Send + 'static,
+{
+    let expired = shutdown.map(|shutdown| Box::pin(shutdown.expired()));
+    stream::unfold(
+        (Box::pin(body), expired, Some(last)),
+        |(mut body, mut expired, last)| async move {
+            // `None` once the body has been cut off.
+            let last = last?;
+            let Some(deadline) = expired.as_mut() else {
+                return body
+                    .next()
+                    .await
+                    .map(|item| (item, (body, None, Some(last))));
+            };
+
+            tokio::select! {
+                biased;
+                item = body.next() => item.map(|item| (item, (body, expired, Some(last)))),
+                _ = deadline => Some((last, (body, None, None))),
+            }
+        },
+    )
+}
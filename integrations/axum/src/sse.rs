@@ -0,0 +1,254 @@
+use std::{convert::Infallible, future, time::Duration};
+
+use axum::{
+    http::{header, HeaderMap},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{stream, Stream, StreamExt};
+use rspc_procedure::ProcedureStream;
+use serde::Serialize;
+
+use crate::{
+    jsonrpc::JsonRPCError,
+    jsonrpc_exec::to_error,
+    replay::Subscriber,
+    shutdown::{self, Shutdown},
+};
+
+const EVENT_STREAM: &str = "text/event-stream";
+const LAST_EVENT_ID: &str = "last-event-id";
+
+// If the client wants the response as Server-Sent Events. This is what `EventSource` sends.
+pub(crate) fn accepts(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .any(|v| v.to_str().is_ok_and(|v| v.contains(EVENT_STREAM)))
+}
+
+// The id of the last event the client received before it reconnected.
+pub(crate) fn last_event_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(LAST_EVENT_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+}
+
+// Stream the output of a procedure as Server-Sent Events.
+//
+// Each value is sent as a `next` event and each error as an `error` event containing the JSON-RPC error.
+// A `complete` event is sent once the procedure finishes so the browser knows not to reconnect.
+// Event ids continue on from the `Last-Event-ID` so they keep increasing across reconnects.
+pub(crate) fn handle_sse(
+    stream: ProcedureStream,
+    last_event_id: Option<String>,
+    keep_alive: Duration,
+    shutdown: Option<&Shutdown>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let last_event_id = last_event_id
+        .and_then(|id| id.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    let events = stream::unfold(Some((stream, last_event_id)), |state| async move {
+        let (mut stream, id) = state?;
+        let id = id + 1;
+
+        let (event, done) = match stream.next().await {
+            Some(Ok(output)) => (
+                match output.as_serialize() {
+                    Some(output) => event(Some(Ok(output))),
+                    None => event::<()>(Some(Err(JsonRPCError {
+                        code: 500,
+                        message: "the procedure returned a value which can't be serialized".into(),
+                        data: None,
+                    }))),
+                },
+                false,
+            ),
+            Some(Err(err)) => (event::<()>(Some(Err(to_error(err)))), false),
+            None => (event::<()>(None), true),
+        };
+
+        Some((
+            Ok(event.id(id.to_string())),
+            (!done).then_some((stream, id)),
+        ))
+    });
+
+    sse(events, keep_alive, shutdown)
+}
+
+// Stream a resumable subscription as Server-Sent Events.
+//
+// This is the same as `handle_sse` except the event ids come from the subscription so the browser resumes it when reconnecting.
+pub(crate) fn handle_replay_sse(
+    subscriber: Subscriber,
+    keep_alive: Duration,
+    shutdown: Option<&Shutdown>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = stream::unfold(Some(subscriber), |subscriber| async move {
+        let mut subscriber = subscriber?;
+
+        Some(match subscriber.next().await {
+            Some((seq, result)) => {
+                let id = subscriber.event_id(seq);
+                (Ok(event(Some(result)).id(id)), Some(subscriber))
+            }
+            None => (Ok(event::<()>(None)), None),
+        })
+    });
+
+    sse(events, keep_alive, shutdown)
+}
+
+// If the server shuts down the stream is ended with an `error` event telling the client to reconnect elsewhere.
+// Unlike `complete` the browser will reconnect by itself.
+fn sse(
+    events: impl Stream<Item = Result<Event, Infallible>> + Send + 'static,
+    keep_alive: Duration,
+    shutdown: Option<&Shutdown>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let started = shutdown.map(Shutdown::started);
+    let handle = shutdown.cloned();
+    let events = events
+        .take_until(async move {
+            match started {
+                Some(started) => started.await,
+                None => future::pending().await,
+            }
+        })
+        .chain(
+            stream::once(async move {
+                handle
+                    .filter(Shutdown::is_shutting_down)
+                    .map(|_| Ok(event::<()>(Some(Err(shutdown::error())))))
+            })
+            .filter_map(future::ready),
+        );
+
+    Sse::new(events).keep_alive(KeepAlive::new().interval(keep_alive))
+}
+
+fn event<T: Serialize>(result: Option<Result<T, JsonRPCError>>) -> Event {
+    let event = match result {
+        Some(Ok(output)) => Event::default().event("next").json_data(output),
+        Some(Err(err)) => Event::default().event("error").json_data(err),
+        None => Ok(Event::default().event("complete").data("null")),
+    };
+
+    event.unwrap_or_else(|_err| {
+        // #[cfg(feature = "tracing")]
+        // tracing::error!("Error serializing event: {}", _err);
+
+        Event::default()
+            .event("error")
+            .data(r#"{"code":500,"message":"error serializing response","data":null}"#)
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        http::Request,
+    };
+    use rspc::{Procedure, ProcedureError, ResolverError, Router};
+    use specta::Type;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::Config;
+
+    #[derive(Debug, Serialize, Type)]
+    struct Error(&'static str);
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            ResolverError::new(self, None::<std::io::Error>).into()
+        }
+    }
+
+    fn app(config: Config) -> axum::Router {
+        let (procedures, _) = <Router>::new()
+            .procedure(
+                "count",
+                Procedure::builder::<Error>().subscription(|_, to: u32| async move {
+                    Ok(rspc::Stream(stream::iter((1..=to).map(|i| match i {
+                        2 => Err(Error("two")),
+                        i => Ok(i),
+                    }))))
+                }),
+            )
+            .procedure(
+                "pending",
+                Procedure::builder::<Error>().subscription(|_, _: ()| async move {
+                    Ok(rspc::Stream(stream::pending::<Result<(), Error>>()))
+                }),
+            )
+            .build()
+            .unwrap();
+        axum::Router::new().nest(
+            "/rspc",
+            crate::endpoint_with_config(procedures, || (), config),
+        )
+    }
+
+    fn request(uri: &str, last_event_id: Option<&str>) -> Request<Body> {
+        let mut req = Request::get(uri).header(header::ACCEPT, EVENT_STREAM);
+        if let Some(id) = last_event_id {
+            req = req.header(LAST_EVENT_ID, id);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn events() {
+        let response = app(Config::new())
+            .oneshot(request("/rspc/count?input=3", None))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], EVENT_STREAM);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let events = body
+            .split_terminator("\n\n")
+            .map(|event| event.lines().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], ["event: next", "data: 1", "id: 1"]);
+        // Errors are sent as their JSON-RPC error.
+        assert_eq!((events[1][0], events[1][2]), ("event: error", "id: 2"));
+        let err: serde_json::Value =
+            serde_json::from_str(events[1][1].strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!((&err["code"], &err["data"]), (&500.into(), &"two".into()));
+        assert_eq!(events[2], ["event: next", "data: 3", "id: 3"]);
+        assert_eq!(events[3], ["event: complete", "data: null", "id: 4"]);
+    }
+
+    #[tokio::test]
+    async fn last_event_id() {
+        let response = app(Config::new())
+            .oneshot(request("/rspc/count?input=1", Some("41")))
+            .await
+            .unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        // The ids carry on from the last one the client received.
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            "event: next\ndata: 1\nid: 42\n\nevent: complete\ndata: null\nid: 43\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn keep_alive() {
+        let response = app(Config::new().sse_keep_alive(Duration::from_millis(10)))
+            .oneshot(request("/rspc/pending", None))
+            .await
+            .unwrap();
+        let mut body = response.into_body().into_data_stream();
+        let chunk = body.next().await.unwrap().unwrap();
+        assert!(chunk.starts_with(b":"));
+    }
+}
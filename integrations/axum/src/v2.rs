@@ -1,22 +1,54 @@
-use std::{borrow::Borrow, collections::HashMap};
+use std::{
+    borrow::{Borrow, Cow},
+    sync::Arc,
+};
 
+#[cfg(feature = "ws")]
+use axum::RequestExt;
 use axum::{
-    body::{to_bytes, Body},
+    body::{to_bytes, Body, Bytes},
     extract::{Request, State},
-    http::{request::Parts, Method, Response, StatusCode},
+    http::{header, Method, StatusCode},
     response::IntoResponse,
     routing::{on, MethodFilter},
-    RequestExt, Router,
+    Router,
 };
-use rspc_procedure::{Procedure, Procedures};
-use serde_json::Value;
+use rspc_procedure::Procedures;
 
 use crate::{
+    batch::handle_batch,
+    cache::CachePolicy,
+    codec::Codec,
+    error,
     extractors::TCtxFunc,
-    jsonrpc::{self, ProcedureKind, RequestId},
-    jsonrpc_exec::{handle_json_rpc, Sender, SubscriptionMap},
+    jsonrpc::{JsonRPCError, RequestId},
+    jsonrpc_exec::to_error,
+    ndjson::{self, handle_ndjson},
+    replay::Replay,
+    shutdown,
+    sse::{self, handle_replay_sse, handle_sse},
+    Config, ResponseHeaders, Shutdown,
 };
 
+/// Mount rspc onto an Axum router.
+///
+/// The context function can take any Axum extractors. It can also be async and return a `Result` whose error implements [`IntoResponse`].
+/// The error is sent as is over HTTP and as a JSON-RPC error in batches and websockets.
+/// For websockets it's run when the connection is opened and again for every message, unless [`Config::ws_context_fn`] is used.
+///
+/// A request with `Accept: application/x-ndjson` streams every value of a procedure returning a `rspc::Stream` as a line of JSON instead of only responding with the first.
+///
+/// A `GET` request with `Accept: text/event-stream` streams the procedure as Server-Sent Events, which allows subscriptions to be used without a websocket.
+/// Values are sent as `next` events, errors as `error` events and a `complete` event is sent when the subscription ends.
+///
+/// With [`Config::replay_buffer`] subscriptions can be resumed after a reconnect without missing any events.
+///
+/// `GET` requests for queries with a [`CachePolicy`](crate::CachePolicy) can be cached by browsers and CDNs and revalidated using `If-None-Match`.
+///
+/// With [`Config::persisted_queries`] procedures can also be called using their hash, Eg. `GET /rspc/{hash}?input=...`.
+///
+/// Requests and responses are JSON by default. With the `msgpack` or `cbor` features enabled clients can pick a format using the `Content-Type` and `Accept` headers or the `rspc.msgpack` and `rspc.cbor` websocket subprotocols.
+/// With the `binario` feature enabled websockets opened with the `rspc.binario` subprotocol handle binary frames as Binario requests.
 pub fn endpoint<TCtx, TCtxFnMarker, TCtxFn, S>(
     procedures: impl Borrow<Procedures<TCtx>>,
     ctx_fn: TCtxFn,
@@ -27,54 +59,145 @@ where
     TCtxFnMarker: Send + Sync + 'static,
     TCtxFn: TCtxFunc<TCtx, S, TCtxFnMarker>,
 {
+    endpoint_with_config(procedures, ctx_fn, Config::default())
+}
+
+/// Same as [`endpoint`] but with a custom [`Config`].
+pub fn endpoint_with_config<TCtx, TCtxFnMarker, TCtxFn, S>(
+    procedures: impl Borrow<Procedures<TCtx>>,
+    ctx_fn: TCtxFn,
+    config: Config,
+) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    TCtx: Send + Sync + 'static,
+    TCtxFnMarker: Send + Sync + 'static,
+    TCtxFn: TCtxFunc<TCtx, S, TCtxFnMarker>,
+{
+    #[cfg(feature = "ws")]
+    debug_assert!(
+        config.ws_context_fn.is_none() || config.ws_context_fn_for::<TCtx>().is_some(),
+        "`Config::ws_context_fn` is for a different context type than the endpoint"
+    );
+
     let procedures = procedures.borrow().clone();
+    let replay = config.replay_buffer.map(|capacity| {
+        Arc::new(Replay::new(
+            capacity,
+            config.replay_retention,
+            config.replay_max_retained,
+        ))
+    });
 
     Router::<S>::new().route(
         "/{id}",
         on(
             MethodFilter::GET.or(MethodFilter::POST),
-            move |state: State<S>, req: axum::extract::Request<Body>| {
+            move |state: State<S>, mut req: axum::extract::Request<Body>| {
                 let procedures = procedures.clone();
+                let config = config.clone();
+                let replay = replay.clone();
+
+                // Allow the context function to extract it.
+                let headers = ResponseHeaders::default();
+                req.extensions_mut().insert(headers.clone());
 
                 async move {
-                    match (req.method(), &req.uri().path()[1..]) {
-                        (&Method::GET, "ws") => {
+                    // Requests made after shutdown has started are rejected and in-flight ones are cut off once the grace period is over.
+                    let shutdown = config.shutdown.clone();
+                    let shutting_down = {
+                        let batch = req.uri().path() == "/_batch";
+                        let accept = Codec::from_accept(req.headers());
+                        let problem_details = config.problem_details;
+                        move || shutdown::response(batch, accept, problem_details)
+                    };
+                    if shutdown.as_ref().is_some_and(Shutdown::is_shutting_down) {
+                        return shutting_down();
+                    }
+
+                    let handle = async move {
+                        match (req.method(), &req.uri().path()[1..]) {
                             #[cfg(feature = "ws")]
-                            {
+                            (&Method::GET, "ws") => {
                                 let mut req = req;
-                                return req
+                                let upgrade = match req
                                     .extract_parts::<axum::extract::ws::WebSocketUpgrade>()
                                     .await
-                                    .unwrap() // TODO: error handling
-                                    .on_upgrade(|socket| {
+                                {
+                                    Ok(upgrade) => upgrade,
+                                    Err(rejection) => return rejection.into_response(),
+                                };
+                                let mut parts = req.into_parts().0;
+                                let connection = crate::Connection::new();
+                                parts.extensions.insert(connection.clone());
+                                let replay = replay.map(|replay| {
+                                    let caller = replay.caller(&parts.headers);
+                                    (replay, caller)
+                                });
+
+                                // Browsers can't read the response to a failed upgrade so the rejection is sent as the first message instead.
+                                let ctx = match ctx_fn.exec(parts.clone(), &state.0).await {
+                                    Ok(ctx) => Ok(match config.ws_context_fn_for::<TCtx>() {
+                                        Some(derive) => WsContext::Connection(ctx, derive),
+                                        None => {
+                                            WsContext::PerMessage(ctx_fn, Box::new(parts), state.0)
+                                        }
+                                    }),
+                                    Err(rejection) => Err(error::rejection(rejection).await),
+                                };
+
+                                #[cfg(feature = "binario")]
+                                let binario = Some(crate::binario::SUBPROTOCOL);
+                                #[cfg(not(feature = "binario"))]
+                                let binario = None;
+
+                                upgrade
+                                    .protocols(
+                                        Codec::ALL.iter().map(Codec::subprotocol).chain(binario),
+                                    )
+                                    .on_upgrade(move |socket| {
                                         handle_websocket(
-                                            ctx_fn,
-                                            socket,
-                                            req.into_parts().0,
-                                            procedures,
-                                            state.0,
+                                            ctx, socket, procedures, config, replay, connection,
                                         )
                                     })
-                                    .into_response();
+                                    .into_response()
                             }
-
-                            #[cfg(not(feature = "ws"))]
-                            Response::builder()
-                                .status(StatusCode::NOT_FOUND)
-                                .body(Body::from("[]")) // TODO: Better error message which frontend is actually setup to handle.
-                                .unwrap()
-                        }
-                        (&Method::GET, _) => {
-                            handle_http(ctx_fn, ProcedureKind::Query, req, &procedures, state.0)
-                                .await
-                                .into_response()
-                        }
-                        (&Method::POST, _) => {
-                            handle_http(ctx_fn, ProcedureKind::Mutation, req, &procedures, state.0)
-                                .await
-                                .into_response()
+                            (&Method::POST, "_batch") => {
+                                handle_batch(ctx_fn, req, &procedures, state.0, headers, &config)
+                                    .await
+                            }
+                            (&Method::GET, _) => handle_http(
+                                ctx_fn,
+                                req,
+                                &procedures,
+                                state.0,
+                                headers,
+                                &config,
+                                replay.as_ref(),
+                            )
+                            .await
+                            .into_response(),
+                            (&Method::POST, _) => handle_http(
+                                ctx_fn,
+                                req,
+                                &procedures,
+                                state.0,
+                                headers,
+                                &config,
+                                replay.as_ref(),
+                            )
+                            .await
+                            .into_response(),
+                            _ => unreachable!(),
                         }
-                        _ => unreachable!(),
+                    };
+
+                    match shutdown {
+                        Some(shutdown) => tokio::select! {
+                            resp = handle => resp,
+                            _ = shutdown.expired() => shutting_down(),
+                        },
+                        None => handle.await,
                     }
                 }
             },
@@ -84,10 +207,12 @@ where
 
 async fn handle_http<TCtx, TCtxFn, TCtxFnMarker, TState>(
     ctx_fn: TCtxFn,
-    kind: ProcedureKind,
     req: Request,
     procedures: &Procedures<TCtx>,
     state: TState,
+    headers: ResponseHeaders,
+    config: &Config,
+    replay: Option<&Arc<Replay>>,
 ) -> impl IntoResponse
 where
     TCtx: Send + Sync + 'static,
@@ -96,146 +221,336 @@ where
 {
     let procedure_name = req.uri().path()[1..].to_string(); // Has to be allocated because `TCtxFn` takes ownership of `req`
     let (parts, body) = req.into_parts();
-    let input = match parts.method {
-        Method::GET => parts
-            .uri
-            .query()
-            .map(|query| form_urlencoded::parse(query.as_bytes()))
-            .and_then(|mut params| params.find(|e| e.0 == "input").map(|e| e.1))
-            .map(|v| serde_json::from_str(&v))
-            .unwrap_or(Ok(None as Option<Value>)),
-        Method::POST => {
-            // TODO: Limit body size?
-            let body = to_bytes(body, usize::MAX).await.unwrap(); // TODO: error handling
-            (!body.is_empty())
-                .then(|| serde_json::from_slice(body.to_vec().as_slice()))
-                .unwrap_or(Ok(None))
-        }
-        _ => unreachable!(),
+    let procedure_name = match &config.persisted_queries {
+        Some(persisted) => match persisted.resolve(&procedure_name) {
+            Ok(name) => name.into_owned(),
+            Err(not_allowed) => {
+                return not_allowed
+                    .into_http(Codec::from_accept(&parts.headers), config.problem_details)
+            }
+        },
+        None => procedure_name,
     };
+    let limits = config.limits.resolve(procedures, &procedure_name);
+    // Subscriptions can be streamed as Server-Sent Events and `rspc::Stream`'s as newline delimited JSON.
+    let sse = (parts.method == Method::GET && sse::accepts(&parts.headers))
+        .then(|| sse::last_event_id(&parts.headers));
+    let streamed = ndjson::accepts(&parts.headers);
+    let accept = Codec::from_accept(&parts.headers);
+    // Only plain `GET` requests can be cached.
+    let cache = (parts.method == Method::GET && sse.is_none() && !streamed)
+        .then(|| CachePolicy::resolve(procedures, &procedure_name))
+        .flatten()
+        .map(|policy| (policy, parts.headers.clone()));
+    let error = |status: StatusCode, message: &str| {
+        error::response(
+            status,
+            JsonRPCError {
+                code: status.as_u16() as i32,
+                message: message.into(),
+                data: None,
+            },
+            accept,
+            config.problem_details,
+        )
+    };
+    let (codec, input) = match parts.method {
+        Method::GET => {
+            if parts
+                .uri
+                .query()
+                .is_some_and(|query| query.len() > limits.body_size)
+            {
+                return limits
+                    .body_too_large()
+                    .into_http(accept, config.problem_details);
+            }
 
-    let input = match input {
-        Ok(input) => input,
-        Err(_err) => {
-            // #[cfg(feature = "tracing")]
-            // tracing::error!("Error passing parameters to operation '{procedure_name}': {_err}");
+            // Query strings are always JSON.
+            let input = parts
+                .uri
+                .query()
+                .map(|query| form_urlencoded::parse(query.as_bytes()))
+                .and_then(|mut params| params.find(|e| e.0 == "input").map(|e| e.1))
+                .map(|v| Bytes::from(v.into_owned()))
+                .unwrap_or_default();
+            (Codec::Json, input)
+        }
+        Method::POST => {
+            let Some(codec) = Codec::from_content_type(&parts.headers) else {
+                // #[cfg(feature = "tracing")]
+                // tracing::error!("Unsupported content type for operation '{procedure_name}'");
+
+                return error(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "the request body is in an unsupported format",
+                );
+            };
 
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .header("Content-Type", "application/json")
-                .body(Body::from(b"[]".as_slice()))
-                .unwrap();
+            let Ok(body) = to_bytes(body, limits.body_size).await else {
+                return limits
+                    .body_too_large()
+                    .into_http(accept, config.problem_details);
+            };
+            (codec, body)
         }
+        _ => unreachable!(),
     };
 
+    if let Err(exceeded) = codec.check(&limits, &input) {
+        return exceeded.into_http(accept, config.problem_details);
+    }
+
     // #[cfg(feature = "tracing")]
-    // tracing::debug!("Executing operation '{procedure_name}' with params {input:?}");
+    // tracing::debug!("Executing operation '{procedure_name}'");
+
+    let Some(procedure) = procedures.get(&Cow::Borrowed(&*procedure_name)) else {
+        // #[cfg(feature = "tracing")]
+        // tracing::error!("Error executing operation: the requested operation '{procedure_name}' is not supported by this server");
+
+        return error(
+            StatusCode::NOT_FOUND,
+            "the requested operation is not supported by this server",
+        );
+    };
 
-    let mut resp = Sender::Response(None);
+    // Resumable subscriptions are tied to the caller which started them.
+    let replay = replay
+        .filter(|_| sse.is_some())
+        .map(|replay| (replay, replay.caller(&parts.headers)));
 
     let ctx = match ctx_fn.exec(parts, &state).await {
         Ok(ctx) => ctx,
-        Err(_err) => {
+        Err(mut rejection) => {
             // #[cfg(feature = "tracing")]
-            // tracing::error!("Error executing context function: {}", _err);
+            // tracing::error!("Error executing context function: {}", rejection.status());
 
-            return Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .header("Content-Type", "application/json")
-                .body(Body::from(b"[]".as_slice()))
-                .unwrap();
+            headers.apply(rejection.headers_mut());
+            return rejection;
         }
     };
 
-    handle_json_rpc(
-        ctx,
-        jsonrpc::Request {
-            jsonrpc: None,
-            id: RequestId::Null,
-            inner: match kind {
-                ProcedureKind::Query => jsonrpc::RequestInner::Query {
-                    path: procedure_name.to_string(), // TODO: Lifetime instead of allocate?
-                    input,
-                },
-                ProcedureKind::Mutation => jsonrpc::RequestInner::Mutation {
-                    path: procedure_name.to_string(), // TODO: Lifetime instead of allocate?
-                    input,
-                },
-                ProcedureKind::Subscription => {
+    if let Some(last_event_id) = sse {
+        let mut resp = match replay {
+            // Each Server-Sent Events request is a single subscription so only the ones the caller has left running are limited.
+            Some((replay, caller)) => match replay.subscribe(
+                &procedure_name,
+                &input,
+                caller,
+                last_event_id.as_deref(),
+                config.replay_max_retained,
+                || codec.exec(procedure, ctx, &input),
+            ) {
+                Ok(subscriber) => {
+                    handle_replay_sse(subscriber, config.sse_keep_alive, config.shutdown.as_ref())
+                        .into_response()
+                }
+                Err(err) => error::response(
+                    StatusCode::from_u16(err.code as u16)
+                        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                    err,
+                    accept,
+                    config.problem_details,
+                ),
+            },
+            None => handle_sse(
+                codec.exec(procedure, ctx, &input),
+                last_event_id,
+                config.sse_keep_alive,
+                config.shutdown.as_ref(),
+            )
+            .into_response(),
+        };
+        headers.apply(resp.headers_mut());
+        return resp;
+    }
+
+    let mut stream = codec.exec(procedure, ctx, &input);
+
+    if streamed {
+        return handle_ndjson(stream, headers, config.shutdown.as_ref()).await;
+    }
+    let result = stream.next().await;
+
+    let mut resp = match (result, cache) {
+        (Some(Err(err)), _) if config.problem_details => {
+            error::response(error::status(&err), to_error(err), accept, true)
+        }
+        (Some(Ok(output)), Some((policy, request))) => {
+            match accept.try_encode_result(&RequestId::Null, Some(Ok(output))) {
+                Ok(body) => policy.response(&request, accept.mime(), body),
+                // Errors must never be cached.
+                Err(_err) => {
                     // #[cfg(feature = "tracing")]
-                    // tracing::error!("Attempted to execute a subscription operation with HTTP");
+                    // tracing::error!("Error serializing response: {}", _err);
 
-                    return Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .header("Content-Type", "application/json")
-                        .body(Body::from(b"[]".as_slice()))
-                        .unwrap();
+                    error(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "error serializing response",
+                    )
                 }
+            }
+        }
+        (result, _) => (
+            match &result {
+                Some(Err(err)) => error::status(err),
+                _ => StatusCode::OK,
             },
-        },
-        procedures,
-        &mut resp,
-        &mut SubscriptionMap::None,
-    )
-    .await;
-
-    match resp {
-        Sender::Response(Some(resp)) => match serde_json::to_vec(&resp) {
-            Ok(v) => Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", "application/json")
-                .body(Body::from(v))
-                .unwrap(),
-            Err(_err) => {
-                // #[cfg(feature = "tracing")]
-                // tracing::error!("Error serializing response: {}", _err);
+            [(header::CONTENT_TYPE, accept.mime())],
+            accept.encode_result(&RequestId::Null, result),
+        )
+            .into_response(),
+    };
+    headers.apply(resp.headers_mut());
+    resp
+}
 
-                Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .header("Content-Type", "application/json")
-                    .body(Body::from(b"[]".as_slice()))
-                    .unwrap()
+// How the context of each websocket message is created.
+#[cfg(feature = "ws")]
+enum WsContext<TCtx, TCtxFn, TState> {
+    // Run the context function again.
+    PerMessage(TCtxFn, Box<axum::http::request::Parts>, TState),
+    // Derive it from the context built when the connection was opened using `Config::ws_context_fn`.
+    Connection(TCtx, crate::config::WsContextFn<TCtx>),
+}
+
+#[cfg(feature = "ws")]
+impl<TCtx, TCtxFn, TState> WsContext<TCtx, TCtxFn, TState>
+where
+    TState: Send + Sync,
+    TCtx: Send + 'static,
+{
+    async fn exec<TCtxFnMarker>(&self) -> Result<TCtx, JsonRPCError>
+    where
+        TCtxFn: TCtxFunc<TCtx, TState, TCtxFnMarker>,
+    {
+        match self {
+            Self::PerMessage(ctx_fn, parts, state) => {
+                match ctx_fn.exec((**parts).clone(), state).await {
+                    Ok(ctx) => Ok(ctx),
+                    Err(rejection) => Err(error::rejection(rejection).await),
+                }
             }
-        },
-        _ => unreachable!(),
+            Self::Connection(ctx, derive) => Ok(derive(ctx)),
+        }
     }
 }
 
 #[cfg(feature = "ws")]
 async fn handle_websocket<TCtx, TCtxFn, TCtxFnMarker, TState>(
-    ctx_fn: TCtxFn,
+    ctx: Result<WsContext<TCtx, TCtxFn, TState>, JsonRPCError>,
     mut socket: axum::extract::ws::WebSocket,
-    parts: Parts,
     procedures: Procedures<TCtx>,
-    state: TState,
+    config: Config,
+    replay: Option<(Arc<Replay>, crate::replay::Caller)>,
+    connection: crate::Connection,
 ) where
     TCtx: Send + Sync + 'static,
     TCtxFn: TCtxFunc<TCtx, TState, TCtxFnMarker>,
     TState: Send + Sync,
 {
-    use axum::extract::ws::Message;
+    use std::collections::HashMap;
+
+    use axum::extract::ws::{close_code, CloseFrame, Message};
     use futures::StreamExt;
-    use tokio::sync::mpsc;
+    use serde_json::Value;
+    use tokio::{
+        sync::oneshot,
+        time::{interval_at, sleep_until, Instant, MissedTickBehavior},
+    };
+
+    use crate::{
+        jsonrpc,
+        jsonrpc_exec::{complete, handle_json_rpc, SubscriptionMap},
+        outbox::{Outbox, Outgoing},
+    };
 
     // #[cfg(feature = "tracing")]
     // tracing::debug!("Accepting websocket connection");
 
-    let mut subscriptions = HashMap::new();
-    let (mut tx, mut rx) = mpsc::channel::<jsonrpc::Response>(100);
+    let codec = Codec::from_subprotocol(socket.protocol());
+    // Binary frames are Binario requests instead of encoded JSON-RPC ones if the client asked for it.
+    #[cfg(feature = "binario")]
+    let binario = socket
+        .protocol()
+        .is_some_and(|v| v == crate::binario::SUBPROTOCOL);
+    let encode_json = |msg: &jsonrpc::Response| {
+        if codec == Codec::Json {
+            serde_json::to_string(msg)
+                .map(|v| Message::Text(v.into()))
+                .map_err(|err| err.to_string())
+        } else {
+            codec.encode(msg).map(|v| Message::Binary(v.into()))
+        }
+    };
+    let encode = |msg: Outgoing| match msg {
+        Outgoing::JsonRpc(msg) => encode_json(&msg),
+        #[cfg(feature = "binario")]
+        Outgoing::Binario(frame) => Ok(Message::Binary(frame.into_bytes().into())),
+    };
+
+    let ctx = match ctx {
+        Ok(ctx) => ctx,
+        Err(err) => {
+            // #[cfg(feature = "tracing")]
+            // tracing::debug!("Rejecting websocket connection: {}", err.message);
+
+            if let Ok(frame) = encode_json(&jsonrpc::Response {
+                jsonrpc: "2.0",
+                id: RequestId::Null,
+                result: jsonrpc::ResponseInner::Error(err),
+            }) {
+                let _ = socket.send(frame).await;
+            }
+            let _ = socket
+                .send(Message::Close(Some(CloseFrame {
+                    code: close_code::POLICY,
+                    reason: "error creating context".into(),
+                })))
+                .await;
+            return;
+        }
+    };
+
+    if let Some(on_connect) = &config.on_connect {
+        (on_connect.0)(&connection);
+    }
+
+    let _guard = config.shutdown.as_ref().map(Shutdown::websocket);
+    let mut shutdown = config
+        .shutdown
+        .as_ref()
+        .map(|shutdown| Box::pin(shutdown.started()));
+
+    let limits = config.limits;
+    let mut last_seen = Instant::now();
+    let mut ping = config.ws_ping_interval.map(|period| {
+        let mut ping = interval_at(Instant::now() + period, period);
+        ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ping
+    });
+
+    let mut subscriptions = HashMap::<RequestId, oneshot::Sender<()>>::new();
+    #[cfg(feature = "binario")]
+    let mut bin_subscriptions = crate::binario::Subscriptions::default();
+    let outbox = Arc::new(Outbox::<Outgoing>::new(
+        config.ws_send_buffer,
+        config.ws_overflow,
+    ));
 
     loop {
         tokio::select! {
             biased; // Note: Order is important here
-            msg = rx.recv() => {
-                match socket.send(Message::Text(match serde_json::to_string(&msg) {
-                    Ok(v) => v.into(),
+            msg = outbox.pop() => {
+                match socket.send(match encode(msg) {
+                    Ok(frame) => frame,
                     Err(_err) => {
                         // #[cfg(feature = "tracing")]
                         // tracing::error!("Error serializing websocket message: {}", _err);
 
                         continue;
                     }
-                })).await {
+                }).await {
                     Ok(_) => {}
                     Err(_err) => {
                         // #[cfg(feature = "tracing")]
@@ -245,12 +560,95 @@ async fn handle_websocket<TCtx, TCtxFn, TCtxFnMarker, TState>(
                     }
                 }
             }
+            _ = async { shutdown.as_mut().expect("checked by precondition").await }, if shutdown.is_some() => {
+                // #[cfg(feature = "tracing")]
+                // tracing::debug!("Closing websocket connection as the server is shutting down");
+
+                // Tell every subscription to reconnect elsewhere and send anything still queued before closing.
+                for (id, tx) in subscriptions.drain() {
+                    if !tx.is_closed() {
+                        outbox.push(jsonrpc::Response {
+                            jsonrpc: "2.0",
+                            id,
+                            result: jsonrpc::ResponseInner::Error(shutdown::error()),
+                        });
+                    }
+                }
+                #[cfg(feature = "binario")]
+                for id in bin_subscriptions.drain() {
+                    outbox.push(crate::binario::error_frame(id, shutdown::error()));
+                }
+                while let Some(msg) = outbox.try_pop() {
+                    if let Ok(frame) = encode(msg) {
+                        let _ = socket.send(frame).await;
+                    }
+                }
+
+                let _ = socket
+                    .send(Message::Close(Some(CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "server shutting down".into(),
+                    })))
+                    .await;
+                break;
+            }
+            _ = async { ping.as_mut().expect("checked by precondition").tick().await }, if ping.is_some() => {
+                if let Err(_err) = socket.send(Message::Ping(Default::default())).await {
+                    // #[cfg(feature = "tracing")]
+                    // tracing::error!("Error sending websocket ping: {}", _err);
+                }
+            }
+            _ = sleep_until(last_seen + config.ws_idle_timeout.unwrap_or_default()), if config.ws_idle_timeout.is_some() => {
+                // #[cfg(feature = "tracing")]
+                // tracing::debug!("Closing idle websocket connection");
+
+                let _ = socket
+                    .send(Message::Close(Some(CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "idle timeout".into(),
+                    })))
+                    .await;
+                break;
+            }
             msg = socket.next() => {
                 match msg {
                     Some(Ok(msg)) => {
+                        last_seen = Instant::now();
+
+                        let len = match &msg {
+                            Message::Text(text) => text.len(),
+                            Message::Binary(binary) => binary.len(),
+                            _ => 0,
+                        };
+                        if len > limits.body_size {
+                            outbox.push(limits.body_too_large().response(RequestId::Null));
+                            continue;
+                        }
+
                        let res = match msg {
                             Message::Text(text) => serde_json::from_str::<Value>(&text),
-                            Message::Binary(binary) => serde_json::from_slice(&binary),
+                            #[cfg(feature = "binario")]
+                            Message::Binary(binary) if binario => {
+                                let ctx = match ctx.exec().await {
+                                    Ok(ctx) => ctx,
+                                    Err(err) => {
+                                        // #[cfg(feature = "tracing")]
+                                        // tracing::error!("Error executing context function: {}", err.message);
+
+                                        outbox.push(crate::binario::error_frame(crate::binario::request_id(&binary).unwrap_or_default(), err));
+                                        continue;
+                                    }
+                                };
+
+                                // The limit is shared with the JSON-RPC subscriptions on this connection.
+                                let running = SubscriptionMap(&mut subscriptions).len();
+                                let max_subscriptions = config.ws_max_subscriptions.saturating_sub(running);
+                                crate::binario::handle_binario(ctx, &binary, &procedures, &config, &outbox, &mut bin_subscriptions, max_subscriptions).await;
+                                continue;
+                            }
+                            Message::Binary(binary) => {
+                                codec.decode::<Value>(&binary).map_err(serde::de::Error::custom)
+                            }
                             Message::Ping(_) | Message::Pong(_) | Message::Close(_) => {
                                 continue;
                             }
@@ -261,52 +659,414 @@ async fn handle_websocket<TCtx, TCtxFn, TCtxFnMarker, TState>(
                             false => serde_json::from_value::<jsonrpc::Request>(v).map(|v| vec![v]),
                         }) {
                             Ok(reqs) => {
-                                for request in reqs {
-                                    let ctx = match ctx_fn.exec(parts.clone(), &state).await {
-                                        Ok(ctx) => {
-                                            ctx
-                                        },
-                                        Err(_err) => {
+                                for mut request in reqs {
+                                    if let Err(not_allowed) = config.persisted_queries.as_ref().map_or(Ok(()), |p| p.resolve_request(&mut request)) {
+                                        outbox.push(not_allowed.response(request.id));
+                                        continue;
+                                    }
 
+                                    if let Err(exceeded) = limits.check_request(&procedures, &request) {
+                                        outbox.push(exceeded.response(request.id));
+                                        continue;
+                                    }
+
+                                    let ctx = match ctx.exec().await {
+                                        Ok(ctx) => ctx,
+                                        Err(err) => {
                                             // #[cfg(feature = "tracing")]
-                                            // tracing::error!("Error executing context function: {}", _err);
+                                            // tracing::error!("Error executing context function: {}", err.message);
 
+                                            let subscription = matches!(request.inner, jsonrpc::RequestInner::Subscription { .. });
+                                            outbox.push(jsonrpc::Response {
+                                                jsonrpc: "2.0",
+                                                id: request.id.clone(),
+                                                result: jsonrpc::ResponseInner::Error(err),
+                                            });
+                                            if subscription {
+                                                complete(&outbox, request.id);
+                                            }
                                             continue;
                                         }
                                     };
 
-                                    handle_json_rpc(ctx, request, &procedures, &mut Sender::Channel(&mut tx),
-                                    &mut SubscriptionMap::Ref(&mut subscriptions)).await;
+                                    // The limit is shared with the Binario subscriptions on this connection.
+                                    #[cfg(feature = "binario")]
+                                    let max_subscriptions = config.ws_max_subscriptions.saturating_sub(bin_subscriptions.len());
+                                    #[cfg(not(feature = "binario"))]
+                                    let max_subscriptions = config.ws_max_subscriptions;
+
+                                    handle_json_rpc(ctx, request, &procedures, &outbox,
+                                    &mut SubscriptionMap(&mut subscriptions), max_subscriptions, replay.as_ref().map(|(replay, caller)| (replay, *caller))).await;
                                 }
                             },
-                            Err(_err) => {
+                            Err(err) => {
                                 // #[cfg(feature = "tracing")]
-                                // tracing::error!("Error parsing websocket message: {}", _err);
-
-                                // TODO: Send report of error to frontend
+                                // tracing::error!("Error parsing websocket message: {}", err);
 
+                                outbox.push(jsonrpc::Response {
+                                    jsonrpc: "2.0",
+                                    id: RequestId::Null,
+                                    result: jsonrpc::ResponseInner::Error(JsonRPCError {
+                                        code: 400,
+                                        message: "error parsing websocket message".into(),
+                                        data: Some(serde_json::json!({ "~rspc": true, "detail": err.to_string() })),
+                                    }),
+                                });
                                 continue;
                             }
                         };
                     }
-                    Some(Err(_err)) => {
+                    Some(Err(err)) => {
                         // #[cfg(feature = "tracing")]
-                        // tracing::error!("Error in websocket: {}", _err);
-
-                        // TODO: Send report of error to frontend
+                        // tracing::error!("Error in websocket: {}", err);
 
+                        outbox.push(jsonrpc::Response {
+                            jsonrpc: "2.0",
+                            id: RequestId::Null,
+                            result: jsonrpc::ResponseInner::Error(JsonRPCError {
+                                code: 500,
+                                message: "error reading websocket message".into(),
+                                data: Some(serde_json::json!({ "~rspc": true, "detail": err.to_string() })),
+                            }),
+                        });
                         continue;
                     },
                     None => {
                         // #[cfg(feature = "tracing")]
                         // tracing::debug!("Shutting down websocket connection");
 
-                        // TODO: Send report of error to frontend
-
-                        return;
+                        break;
                     },
                 }
             }
         }
     }
+
+    outbox.close();
+
+    if let Some(on_disconnect) = &config.on_disconnect {
+        (on_disconnect.0)(&connection);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use axum::http::HeaderMap;
+    use rspc::{Procedure, ProcedureError, Router};
+    use serde_json::{json, Value};
+    use specta::Type;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Type)]
+    enum Error {}
+
+    impl rspc::Error for Error {
+        fn into_procedure_error(self) -> ProcedureError {
+            match self {}
+        }
+    }
+
+    // The context is the user from the `Authorization` header.
+    async fn ctx_fn(headers: HeaderMap) -> Result<String, (StatusCode, &'static str)> {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string)
+            .ok_or((StatusCode::UNAUTHORIZED, "missing session"))
+    }
+
+    fn app() -> axum::Router {
+        let (procedures, _) = Router::<String>::new()
+            .procedure(
+                "whoami",
+                Procedure::builder::<Error>().query(|user: String, _: ()| async move { Ok(user) }),
+            )
+            .build()
+            .unwrap();
+        axum::Router::new().nest("/rspc", endpoint(procedures, ctx_fn))
+    }
+
+    async fn send(req: axum::http::Request<Body>) -> (StatusCode, Bytes) {
+        let response = app().oneshot(req).await.unwrap();
+        let status = response.status();
+        (
+            status,
+            to_bytes(response.into_body(), usize::MAX).await.unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn context_rejections() {
+        let (status, body) = send(
+            axum::http::Request::get("/rspc/whoami")
+                .header(header::AUTHORIZATION, "oscar")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"]["data"], "oscar");
+
+        // The rejection is forwarded as is over HTTP.
+        let (status, body) = send(
+            axum::http::Request::get("/rspc/whoami")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, "missing session");
+
+        // and becomes a JSON-RPC error for each request in a batch.
+        let (status, body) = send(
+            axum::http::Request::post("/rspc/_batch")
+                .body(Body::from(
+                    json!([{ "id": 1, "method": "query", "params": { "path": "whoami" } }])
+                        .to_string(),
+                ))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body[0]["result"]["data"],
+            json!({ "code": 401, "message": "missing session", "data": { "~rspc": true } })
+        );
+    }
+
+    #[tokio::test]
+    async fn extractor_rejections() {
+        let (procedures, _) = Router::<String>::new()
+            .procedure(
+                "whoami",
+                Procedure::builder::<Error>().query(|user: String, _: ()| async move { Ok(user) }),
+            )
+            .build()
+            .unwrap();
+        let app = axum::Router::new().nest(
+            "/rspc",
+            endpoint(
+                procedures,
+                |axum::Extension(user): axum::Extension<String>| user,
+            ),
+        );
+
+        let response = app
+            .oneshot(
+                axum::http::Request::get("/rspc/whoami")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).starts_with("Missing request extension"));
+    }
+
+    #[cfg(feature = "ws")]
+    #[tokio::test]
+    async fn websocket_context_rejections() {
+        use futures::StreamExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app()).await });
+
+        // Browsers can't read the response to a failed upgrade so the rejection is the first message.
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/rspc/ws"))
+            .await
+            .unwrap();
+        let Some(Ok(Message::Text(msg))) = socket.next().await else {
+            unreachable!("expected a text message");
+        };
+        let msg: Value = serde_json::from_str(&msg).unwrap();
+        assert_eq!(msg["id"], Value::Null);
+        assert_eq!(msg["result"]["data"]["code"], 401);
+        assert_eq!(msg["result"]["data"]["message"], "missing session");
+        assert!(matches!(socket.next().await, Some(Ok(Message::Close(_)))));
+    }
+
+    #[cfg(feature = "ws")]
+    type Socket = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    // Serve `app` and open a websocket to it, optionally asking for a subprotocol.
+    #[cfg(feature = "ws")]
+    async fn connect(app: axum::Router, protocol: Option<&str>) -> Socket {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut req = format!("ws://{addr}/rspc/ws")
+            .into_client_request()
+            .unwrap();
+        if let Some(protocol) = protocol {
+            req.headers_mut()
+                .insert(header::SEC_WEBSOCKET_PROTOCOL, protocol.parse().unwrap());
+        }
+        tokio_tungstenite::connect_async(req).await.unwrap().0
+    }
+
+    // The next JSON-RPC response on the socket.
+    #[cfg(feature = "ws")]
+    async fn recv(socket: &mut Socket) -> Value {
+        use futures::StreamExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let Some(Ok(Message::Text(msg))) = socket.next().await else {
+            unreachable!("expected a text message");
+        };
+        serde_json::from_str(&msg).unwrap()
+    }
+
+    #[cfg(feature = "ws")]
+    fn whoami(id: u32) -> tokio_tungstenite::tungstenite::Message {
+        tokio_tungstenite::tungstenite::Message::text(
+            json!({ "id": id, "method": "query", "params": { "path": "whoami" } }).to_string(),
+        )
+    }
+
+    // An endpoint whose context is the number of times the context function has run, rejecting after `limit` runs.
+    #[cfg(feature = "ws")]
+    fn counting_app(
+        limit: usize,
+        config: Config,
+    ) -> (axum::Router, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let (procedures, _) = Router::<String>::new()
+            .procedure(
+                "whoami",
+                Procedure::builder::<Error>().query(|user: String, _: ()| async move { Ok(user) }),
+            )
+            .procedure(
+                "events",
+                Procedure::builder::<Error>().subscription(|user: String, _: ()| async move {
+                    Ok(rspc::Stream(futures::stream::iter([Ok::<_, Error>(user)])))
+                }),
+            )
+            .build()
+            .unwrap();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let ctx_fn = {
+            let runs = runs.clone();
+            move || {
+                let run = runs.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match run < limit {
+                        true => Ok(run.to_string()),
+                        false => Err((StatusCode::UNAUTHORIZED, "session expired")),
+                    }
+                }
+            }
+        };
+        let app =
+            axum::Router::new().nest("/rspc", endpoint_with_config(procedures, ctx_fn, config));
+        (app, runs)
+    }
+
+    #[cfg(feature = "ws")]
+    #[tokio::test]
+    async fn websocket_contexts() {
+        use futures::SinkExt;
+        use std::sync::atomic::Ordering;
+
+        // By default the context function runs when the connection is opened and again for every message.
+        let (app, runs) = counting_app(3, Config::new());
+        let mut socket = connect(app, None).await;
+        socket.send(whoami(1)).await.unwrap();
+        assert_eq!(recv(&mut socket).await["result"]["data"], "1");
+        socket.send(whoami(2)).await.unwrap();
+        assert_eq!(recv(&mut socket).await["result"]["data"], "2");
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+        // A rejection only fails the message it was for and doesn't close the connection.
+        socket.send(whoami(3)).await.unwrap();
+        let msg = recv(&mut socket).await;
+        assert_eq!(msg["id"], 3);
+        assert_eq!(
+            msg["result"]["data"],
+            json!({ "code": 401, "message": "session expired", "data": { "~rspc": true } })
+        );
+        socket
+            .send(tokio_tungstenite::tungstenite::Message::text(
+                json!({ "id": 4, "method": "subscription", "params": { "path": "events", "input": [4, null] } })
+                    .to_string(),
+            ))
+            .await
+            .unwrap();
+        let msg = recv(&mut socket).await;
+        assert_eq!(
+            (&msg["id"], &msg["result"]["type"]),
+            (&json!(4), &json!("error"))
+        );
+        let msg = recv(&mut socket).await;
+        assert_eq!(
+            (&msg["id"], &msg["result"]),
+            (&json!(4), &json!({ "type": "complete" }))
+        );
+
+        // With `ws_context_fn` it only runs when the connection is opened and each message's context is derived from that one.
+        let (app, runs) = counting_app(
+            1,
+            Config::new().ws_context_fn(|ctx: &String| format!("{ctx}!")),
+        );
+        let mut socket = connect(app, None).await;
+        for id in 1..=2 {
+            socket.send(whoami(id)).await.unwrap();
+            assert_eq!(recv(&mut socket).await["result"]["data"], "0!");
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "ws")]
+    #[tokio::test]
+    async fn websocket_binary_frames() {
+        use futures::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        // JSON-RPC can be sent in binary frames unless the client asked for Binario.
+        let (app, _) = counting_app(usize::MAX, Config::new());
+        let mut socket = connect(app, None).await;
+        socket
+            .send(Message::binary(
+                json!({ "id": 1, "method": "query", "params": { "path": "whoami" } }).to_string(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(recv(&mut socket).await["result"]["data"], "1");
+
+        #[cfg(feature = "binario")]
+        {
+            use futures::StreamExt;
+
+            let (app, _) = counting_app(usize::MAX, Config::new());
+            let mut socket = connect(app, Some(crate::binario::SUBPROTOCOL)).await;
+            // A query for an unknown procedure.
+            let mut frame = vec![0];
+            frame.extend(7u32.to_le_bytes());
+            frame.extend(7u16.to_le_bytes());
+            frame.extend(b"unknown");
+            socket.send(Message::binary(frame)).await.unwrap();
+            let Some(Ok(Message::Binary(frame))) = socket.next().await else {
+                unreachable!("expected a binary message");
+            };
+            // An error frame for the request.
+            assert_eq!(frame[..5], [2, 7, 0, 0, 0]);
+
+            // Text frames are still JSON-RPC.
+            socket.send(whoami(2)).await.unwrap();
+            assert_eq!(recv(&mut socket).await["result"]["data"], "2");
+        }
+    }
 }
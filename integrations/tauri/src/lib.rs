@@ -24,12 +24,18 @@
 use std::{
     borrow::Cow,
     collections::HashMap,
-    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::Duration,
 };
 
+use rspc::ProcedureKind;
 use rspc_procedure::{ProcedureError, Procedures};
 use serde::{de::Error, Deserialize, Serialize};
-use serde_json::value::RawValue;
+use serde_json::{json, value::RawValue};
 use tauri::{
     async_runtime::{spawn, JoinHandle},
     generate_handler,
@@ -37,9 +43,10 @@ use tauri::{
     plugin::{Builder, TauriPlugin},
     Manager,
 };
+use tokio::sync::{oneshot, Notify};
 
 struct RpcHandler<R, TCtxFn, TCtx> {
-    subscriptions: Mutex<HashMap<u32, JoinHandle<()>>>,
+    shutdown: Shutdown,
     ctx_fn: TCtxFn,
     procedures: Procedures<TCtx>,
     phantom: std::marker::PhantomData<fn() -> R>,
@@ -51,12 +58,6 @@ where
     TCtxFn: Fn(tauri::Window<R>) -> TCtx + Send + Sync + 'static,
     TCtx: Send + 'static,
 {
-    fn subscriptions(&self) -> MutexGuard<HashMap<u32, JoinHandle<()>>> {
-        self.subscriptions
-            .lock()
-            .unwrap_or_else(PoisonError::into_inner)
-    }
-
     fn handle_rpc_impl(
         self: Arc<Self>,
         window: tauri::Window<R>,
@@ -66,6 +67,18 @@ where
         match req {
             Request::Request { path, input } => {
                 let id = channel.id();
+                if self.shutdown.is_shutting_down() {
+                    send(
+                        &channel,
+                        Response::Value {
+                            code: 503,
+                            value: &shutting_down(),
+                        },
+                    );
+                    send::<()>(&channel, Response::Done);
+                    return;
+                }
+
                 let ctx = (self.ctx_fn)(window);
 
                 let Some(procedure) = self.procedures.get(&Cow::Borrowed(&*path)) else {
@@ -73,13 +86,7 @@ where
                     send(
                         &channel,
                         Response::Value {
-                            code: match err {
-                                ProcedureError::NotFound => 404,
-                                ProcedureError::Deserialize(_) => 400,
-                                ProcedureError::Downcast(_) => 400,
-                                ProcedureError::Resolver(_) => 500, // This is a breaking change. It previously came from the user.
-                                ProcedureError::Unwind(_) => 500,
-                            },
+                            code: err.status(),
                             value: &err,
                         },
                     );
@@ -87,50 +94,77 @@ where
                     return;
                 };
 
+                let subscription =
+                    ProcedureKind::of(&self.procedures, &path) == Some(ProcedureKind::Subscription);
                 let mut stream = match input {
                     Some(i) => procedure.exec_with_deserializer(ctx, i.as_ref()),
                     None => procedure.exec_with_deserializer(ctx, serde_json::Value::Null),
                 };
 
                 let this = self.clone();
-                let handle = spawn(async move {
-                    while let Some(value) = stream.next().await {
-                        match value {
-                            Ok(v) => send(
-                                &channel,
-                                Response::Value {
-                                    code: 200,
-                                    value: &v.as_serialize().unwrap(),
+                let (inserted_tx, inserted_rx) = oneshot::channel::<()>();
+                let handle = spawn({
+                    let channel = channel.clone();
+                    async move {
+                        // Waiting until the task has been inserted stops it from finishing before then.
+                        let _ = inserted_rx.await;
+
+                        while let Some(value) = stream.next().await {
+                            match value {
+                                Ok(v) => match v.as_serialize() {
+                                    Some(v) => send(
+                                        &channel,
+                                        Response::Value {
+                                            code: 200,
+                                            value: &v,
+                                        },
+                                    ),
+                                    None => send(
+                                        &channel,
+                                        Response::Value {
+                                            code: 500,
+                                            value: &json!({
+                                                "code": 500,
+                                                "message": "procedure output can't be serialized",
+                                                "data": { "~rspc": true },
+                                            }),
+                                        },
+                                    ),
                                 },
-                            ),
-                            Err(err) => send(
-                                &channel,
-                                Response::Value {
-                                    code: match err {
-                                        ProcedureError::NotFound => 404,
-                                        ProcedureError::Deserialize(_) => 400,
-                                        ProcedureError::Downcast(_) => 400,
-                                        ProcedureError::Resolver(_) => 500, // This is a breaking change. It previously came from the user.
-                                        ProcedureError::Unwind(_) => 500,
+                                Err(err) => send(
+                                    &channel,
+                                    Response::Value {
+                                        code: err.status(),
+                                        value: &err,
                                     },
-                                    value: &err,
-                                },
-                            ),
+                                ),
+                            }
                         }
-                    }
 
-                    this.subscriptions().remove(&id);
-                    send::<()>(&channel, Response::Done);
+                        // If shutdown removed the task it has already told the frontend.
+                        if this.shutdown.remove(id).is_some() {
+                            send::<()>(&channel, Response::Done);
+                        }
+                    }
                 });
 
                 // if the client uses an existing ID, we will assume the previous subscription is no longer required
-                if let Some(old) = self.subscriptions().insert(id, handle) {
-                    old.abort();
+                let old = self.shutdown.tasks().insert(
+                    id,
+                    Task {
+                        handle,
+                        channel,
+                        subscription,
+                    },
+                );
+                let _ = inserted_tx.send(());
+                if let Some(old) = old {
+                    old.handle.abort();
                 }
             }
             Request::Abort(id) => {
-                if let Some(h) = self.subscriptions().remove(&id) {
-                    h.abort();
+                if let Some(task) = self.shutdown.remove(id) {
+                    task.handle.abort();
                 }
             }
         }
@@ -192,8 +226,11 @@ where
     Builder::new("rspc")
         .invoke_handler(generate_handler![handle_rpc])
         .setup(move |app_handle, _| {
+            let shutdown = Shutdown::default();
+            app_handle.manage(shutdown.clone());
+
             if !app_handle.manage(State(Arc::new(RpcHandler {
-                subscriptions: Default::default(),
+                shutdown,
                 ctx_fn,
                 procedures,
                 phantom: Default::default(),
@@ -206,6 +243,127 @@ where
         .build()
 }
 
+/// Gracefully shut down the plugin.
+///
+/// This is managed by Tauri so it can be retrieved with `app.state::<tauri_plugin_rspc::Shutdown>()`.
+/// Once shutdown has started new requests are rejected with a `503` and in-flight queries and mutations have the grace period to finish before they are cut off.
+/// Subscriptions are sent a final `503` telling the frontend to reconnect.
+///
+/// ```rust,ignore
+/// app.run(|app, event| {
+///     if let tauri::RunEvent::ExitRequested { api, .. } = event {
+///         let shutdown = app.state::<tauri_plugin_rspc::Shutdown>().inner().clone();
+///         if !shutdown.is_shutting_down() {
+///             api.prevent_exit();
+///             let app = app.clone();
+///             tauri::async_runtime::spawn(async move {
+///                 shutdown.shutdown(Duration::from_secs(5)).await;
+///                 app.exit(0);
+///             });
+///         }
+///     }
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct Shutdown(Arc<Tasks>);
+
+#[derive(Default)]
+struct Tasks {
+    shutting_down: AtomicBool,
+    running: Mutex<HashMap<u32, Task>>,
+    drained: Notify,
+}
+
+// A running procedure.
+struct Task {
+    handle: JoinHandle<()>,
+    channel: Channel<IpcResultResponse>,
+    subscription: bool,
+}
+
+impl Shutdown {
+    /// Returns `true` once shutdown has started.
+    pub fn is_shutting_down(&self) -> bool {
+        self.0.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// Start shutting down and wait for in-flight requests to finish, up to `grace_period`.
+    pub async fn shutdown(&self, grace_period: Duration) {
+        self.0.shutting_down.store(true, Ordering::Relaxed);
+
+        // Subscriptions would never finish by themselves so they are stopped straight away.
+        let subscriptions = self
+            .tasks()
+            .iter()
+            .filter(|(_, task)| task.subscription)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in subscriptions {
+            self.stop(id);
+        }
+
+        let drained = async {
+            loop {
+                let mut drained = pin!(self.0.drained.notified());
+                drained.as_mut().enable();
+                if self.tasks().is_empty() {
+                    return;
+                }
+                drained.await;
+            }
+        };
+        if tokio::time::timeout(grace_period, drained).await.is_err() {
+            let ids = self.tasks().keys().copied().collect::<Vec<_>>();
+            for id in ids {
+                self.stop(id);
+            }
+        }
+    }
+
+    fn tasks(&self) -> MutexGuard<HashMap<u32, Task>> {
+        self.0
+            .running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn remove(&self, id: u32) -> Option<Task> {
+        let mut tasks = self.tasks();
+        let task = tasks.remove(&id);
+        if tasks.is_empty() {
+            self.0.drained.notify_waiters();
+        }
+        task
+    }
+
+    // Abort a task and tell the frontend to reconnect.
+    fn stop(&self, id: u32) {
+        if let Some(task) = self.remove(id) {
+            task.handle.abort();
+            send(
+                &task.channel,
+                Response::Value {
+                    code: 503,
+                    value: &shutting_down(),
+                },
+            );
+            send::<()>(&task.channel, Response::Done);
+        }
+    }
+}
+
+// The error for requests made after shutdown has started and the final message sent to subscriptions.
+// This is the same error `rspc-axum` sends.
+//
+// `reconnect` tells the frontend it should retry instead of treating it as a failure.
+fn shutting_down() -> serde_json::Value {
+    json!({
+        "code": 503,
+        "message": "server shutting down",
+        "data": { "~rspc": true, "reconnect": true },
+    })
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(tag = "method", content = "params", rename_all = "camelCase")]
 enum Request {
@@ -245,3 +403,27 @@ impl IpcResponse for IpcResultResponse {
         self.0.map_err(|err| serde_json::Error::custom(err).into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shutting_down_matches_axum() {
+        assert_eq!(
+            serde_json::to_value(Response::Value {
+                code: 503,
+                value: &shutting_down(),
+            })
+            .ok(),
+            Some(json!({
+                "code": 503,
+                "value": {
+                    "code": 503,
+                    "message": "server shutting down",
+                    "data": { "~rspc": true, "reconnect": true },
+                },
+            }))
+        );
+    }
+}
@@ -42,16 +42,22 @@ export function observable<T>(
 export type Observable<T> = ReturnType<typeof observable<T>>;
 
 export const fetchExecute = (
-	config: { url: string },
+	config: {
+		url: string;
+		// The `persistedQueries` exported by rspc. Procedures are called using their hash instead of their name.
+		persistedQueries?: Record<string, string>;
+	},
 	args: ExecuteArgs,
 ): ReturnType<ExecuteFn> => {
 	if (args.type === "subscription")
 		throw new Error("Subscriptions are not possible with the `fetch` executor");
 
+	const path = config.persistedQueries?.[args.path] ?? args.path;
+
 	let promise;
 	if (args.type === "query") {
 		promise = fetch(
-			`${config.url}/${args.path}?${new URLSearchParams({
+			`${config.url}/${path}?${new URLSearchParams({
 				input: JSON.stringify(args.input),
 			})}`,
 			{
@@ -62,7 +68,7 @@ export const fetchExecute = (
 			},
 		);
 	} else {
-		promise = fetch(`${config.url}/${args.path}`, {
+		promise = fetch(`${config.url}/${path}`, {
 			method: "POST",
 			headers: {
 				"Content-Type": "application/json",
@@ -74,15 +80,8 @@ export const fetchExecute = (
 
 	return observable((subscriber) => {
 		promise
-			.then(async (r) => {
-				let json;
-
-				if (r.status === 200) {
-					subscriber.next(await r.json());
-				} else json = (await r.json()) as [];
-
-				json;
-			})
+			// Errors are sent with a non-200 status but the body is still a valid response.
+			.then(async (r) => subscriber.next(await r.json()))
 			.finally(() => subscriber.complete());
 	});
 };
@@ -14,10 +14,17 @@ export class FetchTransport implements Transport {
   private url: string;
   clientSubscriptionCallback?: (id: string, key: string, value: any) => void;
   private fetch: typeof globalThis.fetch;
-
-  constructor(url: string, fetch?: typeof globalThis.fetch) {
+  // The `persistedQueries` exported by rspc. Procedures are called using their hash instead of their name.
+  private persistedQueries?: Record<string, string>;
+
+  constructor(
+    url: string,
+    fetch?: typeof globalThis.fetch,
+    persistedQueries?: Record<string, string>
+  ) {
     this.url = url;
     this.fetch = fetch || globalThis.fetch.bind(globalThis);
+    this.persistedQueries = persistedQueries;
   }
 
   async doRequest(
@@ -46,8 +53,9 @@ export class FetchTransport implements Transport {
       headers.set("Content-Type", "application/json");
     }
     const paramsStr = params.toString();
+    const path = this.persistedQueries?.[key] ?? key;
     const resp = await this.fetch(
-      `${this.url}/${key}${paramsStr.length > 0 ? `?${paramsStr}` : ""}`,
+      `${this.url}/${path}${paramsStr.length > 0 ? `?${paramsStr}` : ""}`,
       {
         method,
         body,
@@ -79,10 +87,15 @@ export class WebsocketTransport implements Transport {
       cb: (data: any) => void;
     }
   >();
+  // The id of the last event received by each subscription so it can be resumed after reconnecting.
+  private lastEventIds = new Map<string, string>();
+  // The `persistedQueries` exported by rspc. Procedures are called using their hash instead of their name.
+  private persistedQueries?: Record<string, string>;
   clientSubscriptionCallback?: (id: string, value: any) => void;
 
-  constructor(url: string) {
+  constructor(url: string, persistedQueries?: Record<string, string>) {
     this.url = url;
+    this.persistedQueries = persistedQueries;
     this.ws = new WebSocket(url);
     this.attachEventListeners();
   }
@@ -90,12 +103,24 @@ export class WebsocketTransport implements Transport {
   attachEventListeners() {
     // Resume all in-progress tasks
     for (const [_, item] of this.requestMap) {
-      this.ws.send(JSON.stringify(item.op));
+      const op = item.op as any;
+      const lastEventId =
+        op.method === "subscription"
+          ? this.lastEventIds.get(op.params.input[0])
+          : undefined;
+      this.ws.send(
+        JSON.stringify(
+          lastEventId === undefined
+            ? op
+            : { ...op, params: { ...op.params, lastEventId } }
+        )
+      );
     }
 
     this.ws.addEventListener("message", (event) => {
       const { id, result } = JSON.parse(event.data);
       if (result.type === "event") {
+        if (result.id !== undefined) this.lastEventIds.set(id, result.id);
         if (this.clientSubscriptionCallback)
           this.clientSubscriptionCallback(id, result.data);
       } else if (result.type === "response") {
@@ -111,6 +136,14 @@ export class WebsocketTransport implements Transport {
           this.requestMap.get(id)?.cb({ type: "error", message, code });
           this.requestMap.delete(id);
         }
+      } else if (result.type === "complete") {
+        // The subscription has ended so it mustn't be resumed when reconnecting.
+        this.lastEventIds.delete(id);
+        for (const [key, item] of this.requestMap) {
+          const op = item.op as any;
+          if (op.method === "subscription" && op.params.input[0] === id)
+            this.requestMap.delete(key);
+        }
       } else {
         console.error(`Received event of unknown type '${result.type}'`);
       }
@@ -158,35 +191,29 @@ export class WebsocketTransport implements Transport {
       await promise;
     }
 
+    if (operation === "subscriptionStop") this.lastEventIds.delete(input);
+
     const id = randomId();
     let resolve: (data: any) => void;
     const promise = new Promise((res) => {
       resolve = res;
     });
 
-    this.requestMap.set(id, {
-      op: {
-        id,
-        method: operation,
-        params: {
-          path: key,
-          input,
-        },
+    const op = {
+      id,
+      method: operation,
+      params: {
+        path: this.persistedQueries?.[key] ?? key,
+        input,
       },
+    };
+    this.requestMap.set(id, {
+      op,
       // @ts-ignore
       cb: resolve,
     });
 
-    this.ws.send(
-      JSON.stringify({
-        id,
-        method: operation,
-        params: {
-          path: key,
-          input,
-        },
-      })
-    );
+    this.ws.send(JSON.stringify(op));
 
     const body = (await promise) as any;
     if (body.type === "error") {
@@ -5,9 +5,9 @@ mod rust;
 #[cfg_attr(docsrs, doc(cfg(feature = "typescript")))]
 mod typescript;
 
-// #[cfg(feature = "rust")]
-// #[cfg_attr(docsrs, doc(cfg(feature = "rust")))]
-// pub use rust::Rust; // TODO
+#[cfg(feature = "rust")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rust")))]
+pub use rust::Rust;
 #[cfg(feature = "typescript")]
 #[cfg_attr(docsrs, doc(cfg(feature = "typescript")))]
 pub use typescript::Typescript;
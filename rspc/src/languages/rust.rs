@@ -1,85 +1,452 @@
-//! TODO: Bring this back when published.
-// use std::{borrow::Cow, collections::HashMap, path::Path};
-
-// use specta::datatype::DataType;
-// use specta_typescript::ExportError;
-
-// use crate::{procedure::ProcedureType, types::TypesOrType, ProcedureKind, Types};
-
-// pub struct Rust(()); // TODO: specta_rust::Rust
-
-// // TODO: Traits - `Debug`, `Clone`, etc
-
-// impl Default for Rust {
-//     fn default() -> Self {
-//         Self(()) // TODO: specta_typescript::Typescript::default().framework_header("// This file was generated by [rspc](https://github.com/specta-rs/rspc). Do not edit this file manually.")
-//     }
-// }
-
-// impl Rust {
-//     // TODO: Clone all methods from `specta_rust::Rust`
-
-//     pub fn export_to(&self, path: impl AsRef<Path>, types: &Types) -> Result<(), ExportError> {
-//         std::fs::write(path, self.export(types)?)?;
-//         // TODO: Format file
-//         Ok(())
-//     }
-
-//     pub fn export(&self, types: &Types) -> Result<String, ExportError> {
-//         println!("WARNING: `rspc::Rust` is an unstable feature! Use at your own discretion!");
-
-//         let mut s = "//! This file was generated by [rspc](https://github.com/specta-rs/rspc). Do not edit this file manually.\n\npub struct Procedures;\n\n".to_string();
-
-//         // TODO: Move to `specta_rust::Rust` which should handle this like we do with Typescript.
-//         for (_, ty) in types.types.into_iter() {
-//             s.push_str(&specta_rust::export_named_datatype(ty).unwrap())
-//         }
-
-//         // TODO: disabling warning on the output???
-
-//         for (key, item) in types.procedures.clone().into_iter() {
-//             export(&mut s, key.to_string(), key, item);
-//         }
-
-//         Ok(s)
-//     }
-// }
-
-// fn export(s: &mut String, full_key: String, ident: Cow<'static, str>, item: TypesOrType) {
-//     match item {
-//         TypesOrType::Type(ty) => {
-//             let kind = match ty.kind {
-//                 ProcedureKind::Query => "Query",
-//                 ProcedureKind::Mutation => "Mutation",
-//                 ProcedureKind::Subscription => "Subscription",
-//             };
-
-//             let input = specta_rust::datatype(&ty.input).unwrap();
-//             let output = specta_rust::datatype(&ty.output).unwrap();
-//             let error = "()"; // TODO: specta_rust::datatype(&ty.error).unwrap();
-
-//             s.push_str(&format!(
-//                 r#"pub struct {ident};
-
-// impl rspc_client::Procedure for {ident} {{
-//     type Input = {input};
-//     type Output = {output};
-//     type Error = {error};
-//     type Procedures = Procedures;
-//     const KIND: rspc_client::ProcedureKind = rspc_client::ProcedureKind::{kind};
-//     const KEY: &'static str = "{ident}";
-// }}
-
-// "#
-//             ));
-//         }
-//         TypesOrType::Types(inner) => {
-//             for (key, item) in inner {
-//                 s.push_str(&format!("\npub mod {key} {{\n"));
-//                 s.push_str(&"\tpub use super::Procedures;\n");
-//                 export(s, format!("{full_key}.{key}"), key, item); // TODO: Inset all items by the correct nuber of tabs
-//                 s.push_str("}\n");
-//             }
-//         }
-//     }
-// }
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    io,
+    path::Path,
+    process::Command,
+};
+
+use specta::datatype::{
+    DataType, EnumRepr, EnumVariants, Field, GenericType, LiteralType, StructFields,
+};
+
+use crate::{types::TypesOrType, ProcedureKind, Types};
+
+/// Export the types of your procedures for use with [`rspc-client`](https://docs.rs/rspc-client).
+///
+/// The output contains all of the named types and a struct implementing `rspc_client::Procedure` for each procedure.
+/// Procedures are nested into modules which match the nesting of the router.
+pub struct Rust {
+    header: Cow<'static, str>,
+}
+
+// TODO: Traits - `Debug`, `Clone`, etc
+
+impl Default for Rust {
+    fn default() -> Self {
+        Self {
+            header: Cow::Borrowed(""),
+        }
+    }
+}
+
+impl Rust {
+    pub fn header(self, header: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            header: header.into(),
+        }
+    }
+
+    /// Export the bindings to a file and format it with `rustfmt`.
+    pub fn export_to(&self, path: impl AsRef<Path>, types: &Types) -> Result<(), io::Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, self.export(types))?;
+
+        let status = Command::new("rustfmt")
+            .args(["--edition", "2021"])
+            .arg(path)
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "rustfmt failed to format '{}' ({status})",
+                path.display()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Export the bindings to a string. Unlike [`Rust::export_to`] this is not formatted.
+    pub fn export(&self, types: &Types) -> String {
+        let mut out = self.header.to_string();
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out += "//! This file was generated by [rspc](https://github.com/specta-rs/rspc). Do not edit this file manually.\n";
+        out += "#![allow(dead_code, non_camel_case_types, non_snake_case, clippy::all)]\n\n";
+        out += "pub struct Procedures;\n\n";
+
+        let mut inlined = Inlined::new();
+        let mut procedures_out = String::new();
+        procedures(&mut procedures_out, &types.procedures, "", 0, &mut inlined);
+
+        // `TypeCollection` is unordered so we sort them to keep the output stable.
+        let mut named = types
+            .types
+            .into_iter()
+            .map(|(_, ndt)| ndt)
+            .collect::<Vec<_>>();
+        named.sort_by(|a, b| a.name().cmp(b.name()));
+
+        let mut defined = HashSet::new();
+        for ndt in named {
+            defined.insert(ndt.name().to_string());
+            named_type(&mut out, ndt.name(), ndt.docs(), &ndt.inner, &mut inlined);
+        }
+
+        // Defining an inlined type can inline more types so we keep going until there are none left.
+        while let Some((name, dt)) = inlined.pop_first() {
+            if defined.insert(name.clone()) {
+                named_type(&mut out, &name, "", &dt, &mut inlined);
+            }
+        }
+
+        out + &procedures_out
+    }
+}
+
+// Structs and enums which were inlined into another type. They have a name so we define them alongside the named types.
+type Inlined = BTreeMap<String, DataType>;
+
+fn named_type(out: &mut String, name: &str, docs_str: &str, dt: &DataType, inlined: &mut Inlined) {
+    docs(out, docs_str);
+    let name = ident(name);
+    let generics = dt.generics().map(|g| generics_decl(g)).unwrap_or_default();
+
+    match dt {
+        DataType::Struct(s) => {
+            *out += "#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]\n";
+            if let Some(tag) = s.tag() {
+                writeln!(out, "#[serde(tag = {tag:?})]").expect("infallible");
+            }
+
+            match s.fields() {
+                StructFields::Unit => writeln!(out, "pub struct {name}{generics};\n"),
+                StructFields::Unnamed(fields) => writeln!(
+                    out,
+                    "pub struct {name}{generics}({});\n",
+                    unnamed_fields(fields.fields(), "pub ", inlined)
+                ),
+                StructFields::Named(fields) => {
+                    writeln!(out, "pub struct {name}{generics} {{").expect("infallible");
+                    named_fields(out, fields.fields(), "pub ", inlined);
+                    writeln!(out, "}}\n")
+                }
+            }
+            .expect("infallible");
+        }
+        DataType::Enum(e) => {
+            *out += "#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]\n";
+            match e.repr() {
+                EnumRepr::External => {}
+                EnumRepr::Untagged => *out += "#[serde(untagged)]\n",
+                EnumRepr::Internal { tag } => {
+                    writeln!(out, "#[serde(tag = {tag:?})]").expect("infallible")
+                }
+                EnumRepr::Adjacent { tag, content } => {
+                    writeln!(out, "#[serde(tag = {tag:?}, content = {content:?})]")
+                        .expect("infallible")
+                }
+            }
+
+            writeln!(out, "pub enum {name}{generics} {{").expect("infallible");
+            for (variant_name, variant) in e.variants().iter().filter(|(_, v)| !v.skip()) {
+                docs(out, variant.docs());
+                let variant_ident = ident(&pascal_case(variant_name));
+                if variant_ident != *variant_name {
+                    writeln!(out, "#[serde(rename = {variant_name:?})]").expect("infallible");
+                }
+
+                match variant.inner() {
+                    EnumVariants::Unit => writeln!(out, "{variant_ident},"),
+                    EnumVariants::Unnamed(fields) => writeln!(
+                        out,
+                        "{variant_ident}({}),",
+                        unnamed_fields(fields.fields(), "", inlined)
+                    ),
+                    EnumVariants::Named(fields) => {
+                        writeln!(out, "{variant_ident} {{").expect("infallible");
+                        named_fields(out, fields.fields(), "", inlined);
+                        writeln!(out, "}},")
+                    }
+                }
+                .expect("infallible");
+            }
+            writeln!(out, "}}\n").expect("infallible");
+        }
+        dt => writeln!(
+            out,
+            "pub type {name}{generics} = {};\n",
+            datatype(dt, "", inlined)
+        )
+        .expect("infallible"),
+    }
+}
+
+fn named_fields(
+    out: &mut String,
+    fields: &[(Cow<'static, str>, Field)],
+    vis: &str,
+    inlined: &mut Inlined,
+) {
+    for (name, field) in fields {
+        // `None` means the field was skipped
+        let Some(ty) = field.ty() else {
+            continue;
+        };
+
+        docs(out, field.docs());
+        let field_ident = ident(&snake_case(name));
+        let mut attrs = Vec::new();
+        if field_ident != *name {
+            attrs.push(format!("rename = {name:?}"));
+        }
+        if field.flatten() {
+            attrs.push("flatten".into());
+        }
+
+        let mut ty = datatype(ty, "", inlined);
+        if field.optional() {
+            attrs.push("default".into());
+            if !ty.starts_with("Option<") {
+                ty = format!("Option<{ty}>");
+                attrs.push(r#"skip_serializing_if = "Option::is_none""#.into());
+            }
+        }
+
+        if !attrs.is_empty() {
+            writeln!(out, "#[serde({})]", attrs.join(", ")).expect("infallible");
+        }
+        writeln!(out, "{vis}{field_ident}: {ty},").expect("infallible");
+    }
+}
+
+fn unnamed_fields(fields: &[Field], vis: &str, inlined: &mut Inlined) -> String {
+    fields
+        .iter()
+        .filter_map(|f| f.ty())
+        .map(|ty| format!("{vis}{}", datatype(ty, "", inlined)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn procedures(
+    out: &mut String,
+    map: &BTreeMap<Cow<'static, str>, TypesOrType>,
+    prefix: &str,
+    depth: usize,
+    inlined: &mut Inlined,
+) {
+    // Named types and `Procedures` are defined in the root module.
+    let root = "super::".repeat(depth);
+
+    for (key, item) in map {
+        let full_key = match prefix {
+            "" => key.to_string(),
+            prefix => format!("{prefix}.{key}"),
+        };
+
+        match item {
+            TypesOrType::Type(ty) => {
+                let name = ident(key);
+                let kind = match ty.kind {
+                    ProcedureKind::Query => "Query",
+                    ProcedureKind::Mutation => "Mutation",
+                    ProcedureKind::Subscription => "Subscription",
+                };
+
+                writeln!(
+                    out,
+                    r#"pub struct {name};
+
+impl rspc_client::Procedure for {name} {{
+    type Input = {};
+    type Output = {};
+    type Error = {};
+    type Procedures = {root}Procedures;
+    const KEY: &'static str = {full_key:?};
+    const KIND: rspc_client::ProcedureKind = rspc_client::ProcedureKind::{kind};
+}}
+"#,
+                    datatype(&ty.input, &root, inlined),
+                    datatype(&ty.output, &root, inlined),
+                    datatype(&ty.error, &root, inlined),
+                )
+                .expect("infallible");
+            }
+            TypesOrType::Types(inner) => {
+                writeln!(out, "pub mod {} {{", ident(key)).expect("infallible");
+                procedures(out, inner, &full_key, depth + 1, inlined);
+                writeln!(out, "}}\n").expect("infallible");
+            }
+        }
+    }
+}
+
+// Convert a type into it's Rust syntax. `root` is the path to the module containing the named types.
+fn datatype(dt: &DataType, root: &str, inlined: &mut Inlined) -> String {
+    match dt {
+        DataType::Primitive(p) => p.to_rust_str().into(),
+        DataType::Literal(l) => match l {
+            LiteralType::i8(_) => "i8".into(),
+            LiteralType::i16(_) => "i16".into(),
+            LiteralType::i32(_) => "i32".into(),
+            LiteralType::u8(_) => "u8".into(),
+            LiteralType::u16(_) => "u16".into(),
+            LiteralType::u32(_) => "u32".into(),
+            LiteralType::f32(_) => "f32".into(),
+            LiteralType::f64(_) => "f64".into(),
+            LiteralType::bool(_) => "bool".into(),
+            LiteralType::String(_) => "String".into(),
+            LiteralType::char(_) => "char".into(),
+            LiteralType::None => "()".into(),
+            // `LiteralType` is `#[non_exhaustive]`
+            _ => "serde_json::Value".into(),
+        },
+        DataType::List(l) => match l.length() {
+            Some(length) => format!("[{}; {length}]", datatype(l.ty(), root, inlined)),
+            None => format!("Vec<{}>", datatype(l.ty(), root, inlined)),
+        },
+        DataType::Map(m) => format!(
+            "std::collections::HashMap<{}, {}>",
+            datatype(m.key_ty(), root, inlined),
+            datatype(m.value_ty(), root, inlined)
+        ),
+        DataType::Nullable(t) => format!("Option<{}>", datatype(t, root, inlined)),
+        DataType::Tuple(t) => match t.elements().as_slice() {
+            [] => "()".into(),
+            [ty] => format!("({},)", datatype(ty, root, inlined)),
+            elements => format!(
+                "({})",
+                elements
+                    .iter()
+                    .map(|ty| datatype(ty, root, inlined))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        },
+        DataType::Reference(r) => {
+            let mut s = format!("{root}{}", ident(r.name()));
+            if !r.generics().is_empty() {
+                s.push('<');
+                s += &r
+                    .generics()
+                    .iter()
+                    .map(|(_, ty)| datatype(ty, root, inlined))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                s.push('>');
+            }
+            s
+        }
+        DataType::Generic(g) => g.to_string(),
+        DataType::Struct(s) if !s.name().is_empty() => {
+            inlined_reference(s.name(), s.generics(), dt, root, inlined)
+        }
+        DataType::Enum(e) if !e.name().is_empty() => {
+            inlined_reference(e.name(), e.generics(), dt, root, inlined)
+        }
+        // Anonymous structs and enums can't be expressed in Rust.
+        DataType::Any | DataType::Unknown | DataType::Struct(_) | DataType::Enum(_) => {
+            "serde_json::Value".into()
+        }
+    }
+}
+
+// Reference a struct or enum which was inlined, defining it if it hasn't been already.
+fn inlined_reference(
+    name: &str,
+    generics: &[GenericType],
+    dt: &DataType,
+    root: &str,
+    inlined: &mut Inlined,
+) -> String {
+    inlined
+        .entry(name.to_string())
+        .or_insert_with(|| dt.clone());
+
+    // Specta doesn't keep the generic arguments of an inlined type so we can't know them.
+    let mut s = format!("{root}{}", ident(name));
+    if !generics.is_empty() {
+        s += &format!("<{}>", vec!["serde_json::Value"; generics.len()].join(", "));
+    }
+    s
+}
+
+fn generics_decl(generics: &[GenericType]) -> String {
+    if generics.is_empty() {
+        return String::new();
+    }
+
+    format!(
+        "<{}>",
+        generics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+fn docs(out: &mut String, docs: &str) {
+    for line in docs.trim().lines() {
+        writeln!(out, "/// {}", line.trim()).expect("infallible");
+    }
+}
+
+// Convert a name into a valid Rust identifier.
+fn ident(name: &str) -> String {
+    let mut s = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if s.is_empty() || s.starts_with(|c: char| c.is_numeric()) {
+        s.insert(0, '_');
+    }
+
+    match s.as_str() {
+        // These can't be raw identifiers
+        "self" | "Self" | "super" | "crate" | "_" => s.push('_'),
+        "as" | "async" | "await" | "break" | "const" | "continue" | "dyn" | "else" | "enum"
+        | "extern" | "false" | "fn" | "for" | "gen" | "if" | "impl" | "in" | "let" | "loop"
+        | "match" | "mod" | "move" | "mut" | "pub" | "ref" | "return" | "static" | "struct"
+        | "trait" | "true" | "try" | "type" | "unsafe" | "use" | "where" | "while" | "yield"
+        | "abstract" | "become" | "box" | "do" | "final" | "macro" | "override" | "priv"
+        | "typeof" | "unsized" | "virtual" => s.insert_str(0, "r#"),
+        _ => {}
+    }
+
+    s
+}
+
+fn snake_case(name: &str) -> String {
+    let mut s = String::with_capacity(name.len());
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if prev_lower {
+                s.push('_');
+            }
+            s.extend(c.to_lowercase());
+            prev_lower = false;
+        } else {
+            s.push(if c == '-' { '_' } else { c });
+            prev_lower = c.is_lowercase() || c.is_numeric();
+        }
+    }
+    s
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split(['_', '-', ' '])
+        .filter(|s| !s.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
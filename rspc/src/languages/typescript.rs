@@ -164,6 +164,26 @@ impl Typescript {
         self.inner.export(&typess)
     }
 
+    /// Export a file containing a `persistedQueries` object mapping the name of every procedure to it's hash from [`Types::persisted_queries`].
+    ///
+    /// This is kept separate from the bindings so it's only bundled by clients which use persisted queries.
+    pub fn export_persisted_queries_to(
+        &self,
+        path: impl AsRef<Path>,
+        types: &Types,
+    ) -> Result<(), ExportError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut out = construct_file(self);
+        generate_persisted_queries(&mut out, types);
+        std::fs::write(path, out)?;
+        self.inner.format(path)?;
+        Ok(())
+    }
+
     // pub fn export_ // TODO: Source map (can we make it be inline?)
 }
 
@@ -256,6 +276,14 @@ fn generate_bindings(
     );
 }
 
+fn generate_persisted_queries(out: &mut String, types: &Types) {
+    *out += "export const persistedQueries = {\n";
+    for (key, hash) in types.persisted_queries() {
+        *out += &format!("\t\"{key}\": \"{hash}\",\n");
+    }
+    *out += "} as const;\n";
+}
+
 fn construct_file(this: &Typescript) -> String {
     let mut out = this.inner.header.to_string();
     if !out.is_empty() {
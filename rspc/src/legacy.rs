@@ -42,7 +42,7 @@ impl<TCtx> From<rspc_legacy::Router<TCtx>> for crate::Router<TCtx> {
                         kind,
                         location: Location::caller().clone(), // TODO: This needs to actually be correct
                         setup: Default::default(),
-                        inner: Box::new(move |_, types| {
+                        inner: Box::new(move |_, _, types| {
                             (
                                 layer_to_procedure(key.to_string(), kind, p.exec),
                                 ProcedureType {
@@ -101,6 +101,7 @@ pub(crate) fn layer_to_procedure<TCtx: 'static>(
                         (), /* typesafe errors aren't supported in legacy router */
                         Some(rspc_procedure::LegacyErrorInterop(err.message().into())),
                     )
+                    .with_status(err.code().to_status_code())
                     .into()
                 })
         });
@@ -119,12 +120,15 @@ pub(crate) fn layer_to_procedure<TCtx: 'static>(
                                     (), /* typesafe errors aren't supported in legacy router */
                                     Some(rspc_procedure::LegacyErrorInterop(err.message().into())),
                                 )
+                                .with_status(err.code().to_status_code())
                                 .into()
                             })
                             .boxed(),
                         Err(err) => {
                             let err: rspc_legacy::Error = err.into();
-                            let err = ResolverError::new(err.message().to_string(), err.cause());
+                            let status = err.code().to_status_code();
+                            let err = ResolverError::new(err.message().to_string(), err.cause())
+                                .with_status(status);
                             stream::once(async { Err(err.into()) }).boxed()
                         }
                     }
@@ -240,7 +244,7 @@ fn flatten_procedures_for_legacy(
 ) {
     match item {
         TypesOrType::Type(ty) => {
-            p.insert(key, ty);
+            p.insert(key, *ty);
         }
         TypesOrType::Types(types) => {
             for (k, v) in types {
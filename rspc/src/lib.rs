@@ -36,7 +36,8 @@ pub use extension::Extension;
 #[allow(unused)]
 pub use languages::*;
 pub use procedure::{
-    ErasedProcedure, Procedure, ProcedureBuilder, ProcedureMeta, ResolverInput, ResolverOutput,
+    ErasedProcedure, Procedure, ProcedureBuilder, ProcedureMeta, ProcedureType, ResolverInput,
+    ResolverOutput,
 };
 pub use procedure_kind::ProcedureKind;
 pub use router::Router;
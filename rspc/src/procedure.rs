@@ -34,8 +34,11 @@ use specta::{datatype::DataType, Generics, Type};
 
 use crate::{Error, Extension, ProcedureKind, State};
 
-#[derive(Clone)]
-pub(crate) struct ProcedureType {
+/// The type information of a single procedure.
+///
+/// This can be used along with [`Types`](crate::Types) for custom exporting logic.
+#[derive(Debug, Clone)]
+pub struct ProcedureType {
     pub(crate) kind: ProcedureKind,
     pub(crate) input: DataType,
     pub(crate) output: DataType,
@@ -43,6 +46,29 @@ pub(crate) struct ProcedureType {
     pub(crate) location: Location<'static>,
 }
 
+impl ProcedureType {
+    pub fn kind(&self) -> ProcedureKind {
+        self.kind
+    }
+
+    pub fn input(&self) -> &DataType {
+        &self.input
+    }
+
+    pub fn output(&self) -> &DataType {
+        &self.output
+    }
+
+    pub fn error(&self) -> &DataType {
+        &self.error
+    }
+
+    /// The location the procedure was defined.
+    pub fn location(&self) -> Location<'static> {
+        self.location
+    }
+}
+
 /// Represents a single operations on the server that can be executed.
 ///
 /// A [`Procedure`] is built from a [`ProcedureBuilder`] and holds the type information along with the logic to execute the operation.
@@ -75,11 +101,10 @@ impl<TCtx, TInput, TOutput> Procedure<TCtx, TInput, TOutput> {
                     setup: setup
                         .into_iter()
                         .map(|setup| {
-                            let v: Box<dyn FnOnce(&mut State)> =
-                                Box::new(move |state: &mut State| {
-                                    let key: Cow<'static, str> = "todo".to_string().into(); // TODO: Work this out properly
+                            let v: erased::Setup =
+                                Box::new(move |state: &mut State, key: Cow<'static, str>| {
                                     let meta = ProcedureMeta::new(
-                                        key.into(),
+                                        key,
                                         kind,
                                         Arc::new(State::default()), // TODO: Can we configure a panic instead of this!
                                     );
@@ -89,23 +114,23 @@ impl<TCtx, TInput, TOutput> Procedure<TCtx, TInput, TOutput> {
                         })
                         .collect::<Vec<_>>(),
                     location,
-                    inner: Box::new(move |state, types| {
-                        let key: Cow<'static, str> = "todo".to_string().into(); // TODO: Work this out properly
-                        let meta = ProcedureMeta::new(key.clone(), kind, state);
+                    inner: Box::new(move |key, state, types| {
+                        let meta = ProcedureMeta::new(key, kind, state);
 
                         (
                             rspc_procedure::Procedure::new(move |ctx, input| {
+                                let input = match TInput::from_input(input) {
+                                    Ok(input) => input,
+                                    Err(err) => return err.into(),
+                                };
+
                                 TOutput::into_procedure_stream(
-                                    handler(
-                                        ctx,
-                                        TInput::from_input(input).unwrap(), // TODO: Error handling
-                                        meta.clone(),
-                                    )
-                                    .into_stream()
-                                    .map_ok(|v| v.into_stream())
-                                    .map_err(|err| err.into_procedure_error())
-                                    .try_flatten()
-                                    .into_stream(),
+                                    handler(ctx, input, meta.clone())
+                                        .into_stream()
+                                        .map_ok(|v| v.into_stream())
+                                        .map_err(|err| err.into_procedure_error())
+                                        .try_flatten()
+                                        .into_stream(),
                                 )
                             }),
                             ProcedureType {
@@ -1,15 +1,19 @@
-use std::{panic::Location, sync::Arc};
+use std::{borrow::Cow, panic::Location, sync::Arc};
 
 use specta::TypeCollection;
 
 use crate::{procedure::ProcedureType, ProcedureKind, State};
 
+// The procedure's key isn't known until the router is built so it's provided as an argument.
+pub(crate) type Setup = Box<dyn FnOnce(&mut State, Cow<'static, str>) + 'static>;
+
 pub struct ErasedProcedure<TCtx> {
-    pub(crate) setup: Vec<Box<dyn FnOnce(&mut State) + 'static>>,
+    pub(crate) setup: Vec<Setup>,
     pub(crate) location: Location<'static>,
     pub(crate) kind: ProcedureKind,
     pub(crate) inner: Box<
         dyn FnOnce(
+            Cow<'static, str>,
             Arc<State>,
             &mut TypeCollection,
         ) -> (rspc_procedure::Procedure<TCtx>, ProcedureType),
@@ -1,5 +1,6 @@
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
+use rspc_procedure::Procedures;
 use specta::Type;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Type)]
@@ -10,6 +11,22 @@ pub enum ProcedureKind {
     Subscription,
 }
 
+impl ProcedureKind {
+    /// The kind of the procedure with `key`. This is `None` if there is no such procedure or `procedures` wasn't built by a [`Router`](crate::Router).
+    ///
+    /// This allows integrations which aren't told the kind by the client to tell subscriptions apart.
+    pub fn of<TCtx>(procedures: &Procedures<TCtx>, key: &str) -> Option<Self> {
+        procedures
+            .state()
+            .get::<ProcedureKinds>()
+            .and_then(|kinds| kinds.0.get(key))
+            .copied()
+    }
+}
+
+// The kind of every procedure, which is stored into the state when the router is built.
+pub(crate) struct ProcedureKinds(pub(crate) HashMap<String, ProcedureKind>);
+
 impl fmt::Display for ProcedureKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
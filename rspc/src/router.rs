@@ -10,7 +10,10 @@ use specta::TypeCollection;
 
 use rspc_procedure::Procedures;
 
-use crate::{procedure::ErasedProcedure, types::TypesOrType, ProcedureKind, State, Types};
+use crate::{
+    procedure::ErasedProcedure, procedure_kind::ProcedureKinds, types::TypesOrType, ProcedureKind,
+    State, Types,
+};
 
 /// TODO: Examples exporting types and with `rspc_axum`
 pub struct Router<TCtx = ()> {
@@ -52,15 +55,15 @@ impl<TCtx> Router<TCtx> {
                 duplicate: Location::caller().clone(),
             });
         } else {
-            let mut procedure = procedure.into();
-            self.setup.extend(procedure.setup.drain(..));
-            self.procedures.insert(vec![key], procedure);
+            self.procedures.insert(vec![key], procedure.into());
         }
 
         self
     }
 
-    // TODO: Document the order this is run in for `build`
+    /// Register a function to initialise the [`State`] when the router is built.
+    ///
+    /// All router setup functions are run before the setup functions of the procedures.
     pub fn setup(mut self, func: impl FnOnce(&mut State) + 'static) -> Self {
         self.setup.push(Box::new(func));
         self
@@ -137,6 +140,18 @@ impl<TCtx> Router<TCtx> {
         for setup in self.setup {
             setup(&mut state);
         }
+        for (key, p) in self.procedures.iter_mut() {
+            let key = get_flattened_name(key);
+            for setup in p.setup.drain(..) {
+                setup(&mut state, key.clone());
+            }
+        }
+        state.insert(ProcedureKinds(
+            self.procedures
+                .iter()
+                .map(|(key, p)| (get_flattened_name(key).into_owned(), p.kind))
+                .collect(),
+        ));
         let state = Arc::new(state);
 
         let mut procedure_types = BTreeMap::new();
@@ -144,7 +159,8 @@ impl<TCtx> Router<TCtx> {
             .procedures
             .into_iter()
             .map(|(key, p)| {
-                let (procedure, ty) = (p.inner)(state.clone(), &mut self.types);
+                let name = get_flattened_name(&key);
+                let (procedure, ty) = (p.inner)(name.clone(), state.clone(), &mut self.types);
 
                 let mut current = &mut procedure_types;
                 // TODO: if `key.len()` is `0` we might run into issues here. It shouldn't but probs worth protecting.
@@ -157,9 +173,9 @@ impl<TCtx> Router<TCtx> {
                         TypesOrType::Types(map) => current = map,
                     }
                 }
-                current.insert(key[key.len() - 1].clone(), TypesOrType::Type(ty));
+                current.insert(key[key.len() - 1].clone(), TypesOrType::Type(Box::new(ty)));
 
-                (get_flattened_name(&key), procedure)
+                (name, procedure)
             })
             .collect::<HashMap<_, _>>();
 
@@ -1,12 +1,19 @@
-use std::{borrow::Cow, collections::BTreeMap, fmt};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt::{self, Write},
+};
 
-use specta::TypeCollection;
+use specta::{
+    datatype::{DataType, EnumVariants, Field, NamedFields, StructFields, UnnamedFields},
+    SpectaID, TypeCollection,
+};
 
 use crate::procedure::ProcedureType;
 
 #[derive(Clone)]
 pub(crate) enum TypesOrType {
-    Type(ProcedureType),
+    Type(Box<ProcedureType>),
     Types(BTreeMap<Cow<'static, str>, TypesOrType>),
 }
 
@@ -18,7 +25,7 @@ pub struct Types {
 impl fmt::Debug for Types {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Types")
-            // TODO: Finish this
+            .field("procedures", &self.procedures().keys().collect::<Vec<_>>())
             .finish()
     }
 }
@@ -26,5 +33,186 @@ impl fmt::Debug for Types {
 // TODO: Traits
 
 impl Types {
-    // TODO: Expose inners for manual exporting logic
+    /// The collection of all named types used by the procedures.
+    pub fn types(&self) -> &TypeCollection {
+        &self.types
+    }
+
+    /// Get the type of every procedure keyed by it's name.
+    ///
+    /// The names are flattened (Eg. `users.get`) so they match the keys of [`Procedures`](crate::Procedures).
+    pub fn procedures(&self) -> BTreeMap<String, &ProcedureType> {
+        fn inner<'a>(
+            prefix: &str,
+            map: &'a BTreeMap<Cow<'static, str>, TypesOrType>,
+            out: &mut BTreeMap<String, &'a ProcedureType>,
+        ) {
+            for (key, item) in map {
+                let key = match prefix {
+                    "" => key.to_string(),
+                    prefix => format!("{prefix}.{key}"),
+                };
+
+                match item {
+                    TypesOrType::Type(ty) => {
+                        out.insert(key, ty);
+                    }
+                    TypesOrType::Types(map) => inner(&key, map, out),
+                }
+            }
+        }
+
+        let mut out = BTreeMap::new();
+        inner("", &self.procedures, &mut out);
+        out
+    }
+
+    /// Get a hash for every procedure keyed by it's name.
+    ///
+    /// Clients can call a procedure using it's hash instead of it's name, which allows an integration to only accept operations known by the client.
+    /// The hash covers the procedure's name, kind and the definition of it's input, output and error types (including every named type they reference),
+    /// so it changes whenever the operation does but stays the same between builds if it doesn't.
+    pub fn persisted_queries(&self) -> BTreeMap<String, String> {
+        self.procedures()
+            .into_iter()
+            .map(|(key, ty)| {
+                let hash = persisted_query_hash(&self.types, &key, ty);
+                (key, hash)
+            })
+            .collect()
+    }
+}
+
+// 64-bit FNV-1a of the operation. The standard library's hasher isn't guaranteed to be stable between Rust versions so it can't be used for hashes which are shipped to clients.
+fn persisted_query_hash(types: &TypeCollection, key: &str, ty: &ProcedureType) -> String {
+    let mut refs = Vec::new();
+    let mut out = format!("{}:{key}", ty.kind);
+    for dt in [&ty.input, &ty.output, &ty.error] {
+        out.push('\n');
+        write_datatype(&mut out, dt, &mut refs);
+    }
+
+    // `refs` grows as the named types are written so the types they reference are included too.
+    let mut i = 0;
+    while let Some(sid) = refs.get(i).copied() {
+        i += 1;
+        if let Some(ndt) = types.get(sid) {
+            out.push_str("\ntype ");
+            out.push_str(ndt.name());
+            out.push_str(" = ");
+            write_datatype(&mut out, &ndt.inner, &mut refs);
+        }
+    }
+
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in out.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
+// Write the shape of a type for hashing, collecting the named types it references.
+//
+// The `Debug` output of a `DataType` isn't used as the `SpectaID`s it contains depend on where the type is defined,
+// and docs or a type being moved shouldn't change the hash.
+fn write_datatype(out: &mut String, dt: &DataType, refs: &mut Vec<SpectaID>) {
+    match dt {
+        DataType::Any => out.push_str("any"),
+        DataType::Unknown => out.push_str("unknown"),
+        DataType::Primitive(p) => write!(out, "{p:?}").expect("writing to a string can't fail"),
+        DataType::Literal(l) => write!(out, "{l:?}").expect("writing to a string can't fail"),
+        DataType::List(l) => {
+            out.push('[');
+            write_datatype(out, l.ty(), refs);
+            write!(out, "; {:?}; {}]", l.length(), l.unique())
+                .expect("writing to a string can't fail");
+        }
+        DataType::Map(m) => {
+            out.push('{');
+            write_datatype(out, m.key_ty(), refs);
+            out.push_str(": ");
+            write_datatype(out, m.value_ty(), refs);
+            out.push('}');
+        }
+        DataType::Nullable(ty) => {
+            out.push('?');
+            write_datatype(out, ty, refs);
+        }
+        DataType::Struct(s) => {
+            write!(out, "struct {}{:?} ", s.name(), s.generics())
+                .expect("writing to a string can't fail");
+            match s.fields() {
+                StructFields::Unit => out.push_str("unit"),
+                StructFields::Unnamed(fields) => write_unnamed_fields(out, fields, refs),
+                StructFields::Named(fields) => write_named_fields(out, fields, refs),
+            }
+        }
+        DataType::Enum(e) => {
+            write!(out, "enum {}{:?} {:?} {{", e.name(), e.generics(), e.repr())
+                .expect("writing to a string can't fail");
+            for (name, variant) in e.variants() {
+                write!(out, "{name}({}) ", variant.skip()).expect("writing to a string can't fail");
+                match variant.inner() {
+                    EnumVariants::Unit => out.push_str("unit"),
+                    EnumVariants::Unnamed(fields) => write_unnamed_fields(out, fields, refs),
+                    EnumVariants::Named(fields) => write_named_fields(out, fields, refs),
+                }
+                out.push_str(", ");
+            }
+            out.push('}');
+        }
+        DataType::Tuple(t) => {
+            out.push('(');
+            for ty in t.elements() {
+                write_datatype(out, ty, refs);
+                out.push_str(", ");
+            }
+            out.push(')');
+        }
+        DataType::Reference(r) => {
+            out.push_str(r.name());
+            out.push('<');
+            for (generic, ty) in r.generics() {
+                write!(out, "{generic} = ").expect("writing to a string can't fail");
+                write_datatype(out, ty, refs);
+                out.push_str(", ");
+            }
+            out.push('>');
+
+            if !refs.contains(&r.sid()) {
+                refs.push(r.sid());
+            }
+        }
+        DataType::Generic(g) => write!(out, "{g}").expect("writing to a string can't fail"),
+    }
+}
+
+fn write_field(out: &mut String, field: &Field, refs: &mut Vec<SpectaID>) {
+    write!(out, "({}, {}) ", field.optional(), field.flatten())
+        .expect("writing to a string can't fail");
+    match field.ty() {
+        Some(ty) => write_datatype(out, ty, refs),
+        // The field is skipped
+        None => out.push('!'),
+    }
+}
+
+fn write_unnamed_fields(out: &mut String, fields: &UnnamedFields, refs: &mut Vec<SpectaID>) {
+    out.push('(');
+    for field in fields.fields() {
+        write_field(out, field, refs);
+        out.push_str(", ");
+    }
+    out.push(')');
+}
+
+fn write_named_fields(out: &mut String, fields: &NamedFields, refs: &mut Vec<SpectaID>) {
+    write!(out, "{:?} {{", fields.tag()).expect("writing to a string can't fail");
+    for (name, field) in fields.fields() {
+        write!(out, "{name}: ").expect("writing to a string can't fail");
+        write_field(out, field, refs);
+        out.push_str(", ");
+    }
+    out.push('}');
 }
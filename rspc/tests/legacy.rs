@@ -0,0 +1,43 @@
+#![cfg(feature = "legacy")]
+#![allow(clippy::unwrap_used, deprecated)]
+
+use futures_util::FutureExt;
+use rspc_legacy::{Error, ErrorCode};
+use serde_json::Value;
+
+// The status of the error returned by a legacy procedure.
+fn status(path: &str, input: Value) -> Option<u16> {
+    let legacy =
+        rspc_legacy::Router::<()>::new()
+            .query("ok", |t| t(|_, _: ()| "ok"))
+            .query("notFound", |t| {
+                t(|_, _: ()| {
+                    Err(Error::new(ErrorCode::NotFound, "not found".into())) as Result<(), _>
+                })
+            })
+            .query("forbidden", |t| {
+                t(|_, _: ()| async {
+                    Err(Error::new(ErrorCode::Forbidden, "forbidden".into())) as Result<(), _>
+                })
+            })
+            .build();
+    let (procedures, _) = rspc::Router::from(legacy).build().unwrap();
+
+    let mut stream = procedures[path].exec_with_deserializer((), input);
+    stream
+        .next()
+        .now_or_never()
+        .unwrap()
+        .unwrap()
+        .err()
+        .map(|err| err.status())
+}
+
+#[test]
+fn error_status() {
+    assert_eq!(status("ok", Value::Null), None);
+    assert_eq!(status("notFound", Value::Null), Some(404));
+    assert_eq!(status("forbidden", Value::Null), Some(403));
+    // The input is invalid so the procedure is never called.
+    assert_eq!(status("ok", Value::Bool(true)), Some(400));
+}
@@ -0,0 +1,166 @@
+#![allow(clippy::unwrap_used)]
+
+use std::{collections::BTreeMap, fmt};
+
+use rspc::{Procedure, ProcedureError, Router};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[allow(dead_code)] // The fields are only used for their types
+mod v1 {
+    use super::*;
+
+    #[derive(Type, Deserialize)]
+    pub struct Page {
+        pub users: Vec<User>,
+    }
+
+    #[derive(Type, Deserialize)]
+    pub struct User {
+        pub id: i32,
+    }
+}
+
+// The same type as `v1::User` but defined somewhere else.
+#[allow(dead_code)] // The fields are only used for their types
+mod moved {
+    use super::*;
+
+    #[derive(Type, Deserialize)]
+    pub struct User {
+        pub id: i32,
+    }
+}
+
+#[allow(dead_code)] // The fields are only used for their types
+mod v2 {
+    use super::*;
+
+    #[derive(Type, Deserialize)]
+    pub struct Page {
+        pub users: Vec<User>,
+    }
+
+    #[derive(Type, Deserialize)]
+    pub struct User {
+        pub id: String,
+    }
+}
+
+fn hashes(router: Router<()>) -> BTreeMap<String, String> {
+    let (_, types) = router.build().unwrap();
+    types.persisted_queries()
+}
+
+#[test]
+fn stable() {
+    let router = || {
+        <Router>::new()
+            .procedure(
+                "a",
+                Procedure::builder().query(|_, _: i32| async { Ok::<_, Infallible>(()) }),
+            )
+            .nest(
+                "b",
+                <Router>::new().procedure(
+                    "c",
+                    Procedure::builder()
+                        .mutation(|_, _: v1::User| async { Ok::<_, Infallible>(()) }),
+                ),
+            )
+    };
+
+    let a = hashes(router());
+    assert_eq!(a, hashes(router()));
+    assert_eq!(a.keys().collect::<Vec<_>>(), ["a", "b.c"]);
+    assert!(a
+        .values()
+        .all(|hash| hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit())));
+    assert_ne!(a["a"], a["b.c"]);
+}
+
+#[test]
+fn operation_changes() {
+    let query = hashes(<Router>::new().procedure(
+        "a",
+        Procedure::builder().query(|_, _: i32| async { Ok::<_, Infallible>(()) }),
+    ));
+    let mutation = hashes(<Router>::new().procedure(
+        "a",
+        Procedure::builder().mutation(|_, _: i32| async { Ok::<_, Infallible>(()) }),
+    ));
+    let input = hashes(<Router>::new().procedure(
+        "a",
+        Procedure::builder().query(|_, _: String| async { Ok::<_, Infallible>(()) }),
+    ));
+    let output = hashes(<Router>::new().procedure(
+        "a",
+        Procedure::builder().query(|_, _: i32| async { Ok::<_, Infallible>(1i32) }),
+    ));
+    let renamed = hashes(<Router>::new().procedure(
+        "b",
+        Procedure::builder().query(|_, _: i32| async { Ok::<_, Infallible>(()) }),
+    ));
+
+    assert_ne!(query["a"], mutation["a"]);
+    assert_ne!(query["a"], input["a"]);
+    assert_ne!(query["a"], output["a"]);
+    assert_ne!(query["a"], renamed["b"]);
+}
+
+#[test]
+fn referenced_types() {
+    let v1 = hashes(<Router>::new().procedure(
+        "a",
+        Procedure::builder().query(|_, _: v1::User| async { Ok::<_, Infallible>(()) }),
+    ));
+    let moved = hashes(<Router>::new().procedure(
+        "a",
+        Procedure::builder().query(|_, _: moved::User| async { Ok::<_, Infallible>(()) }),
+    ));
+    let v2 = hashes(<Router>::new().procedure(
+        "a",
+        Procedure::builder().query(|_, _: v2::User| async { Ok::<_, Infallible>(()) }),
+    ));
+
+    // Only the definition of the type matters, not where it is.
+    assert_eq!(v1["a"], moved["a"]);
+    assert_ne!(v1["a"], v2["a"]);
+
+    // Types referenced through another type are included.
+    let v1 = hashes(<Router>::new().procedure(
+        "a",
+        Procedure::builder().query(|_, _: v1::Page| async { Ok::<_, Infallible>(()) }),
+    ));
+    let v2 = hashes(<Router>::new().procedure(
+        "a",
+        Procedure::builder().query(|_, _: v2::Page| async { Ok::<_, Infallible>(()) }),
+    ));
+    assert_ne!(v1["a"], v2["a"]);
+}
+
+#[derive(Type, Debug)]
+pub enum Infallible {}
+
+impl fmt::Display for Infallible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Serialize for Infallible {
+    fn serialize<S>(&self, _: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        unreachable!()
+    }
+}
+
+impl std::error::Error for Infallible {}
+
+impl rspc::Error for Infallible {
+    fn into_procedure_error(self) -> ProcedureError {
+        unreachable!()
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt;
 
-use rspc::{Procedure, ProcedureError, Router};
+use rspc::{Procedure, ProcedureError, ProcedureKind, Router};
 use rspc_procedure::ResolverError;
 use serde::Serialize;
 use specta::Type;
@@ -53,6 +53,39 @@ fn errors() {
     assert_eq!(format!("{:?}", router.build().unwrap_err()), "[Duplicate procedure at path [\"abc\"]. Original: rspc/tests/router.rs:42:17 Duplicate: rspc/tests/router.rs:45:10\n]");
 }
 
+#[test]
+#[allow(clippy::unwrap_used)]
+fn procedure_kinds() {
+    let (procedures, _) = <Router>::new()
+        .procedure(
+            "query",
+            Procedure::builder().query(|_, _: ()| async { Ok::<_, Infallible>(()) }),
+        )
+        .nest(
+            "nested",
+            <Router>::new().procedure(
+                "subscription",
+                Procedure::builder().subscription(|_, _: ()| async {
+                    Ok::<_, Infallible>(rspc::Stream(futures_util::stream::empty::<
+                        Result<(), Infallible>,
+                    >()))
+                }),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        ProcedureKind::of(&procedures, "query"),
+        Some(ProcedureKind::Query)
+    );
+    assert_eq!(
+        ProcedureKind::of(&procedures, "nested.subscription"),
+        Some(ProcedureKind::Subscription)
+    );
+    assert_eq!(ProcedureKind::of(&procedures, "unknown"), None);
+}
+
 #[derive(Type, Debug)]
 pub enum Infallible {}
 
@@ -0,0 +1,178 @@
+#![cfg(feature = "rust")]
+#![allow(clippy::unwrap_used)]
+
+use std::fmt;
+
+use rspc::{Procedure, ProcedureError, Router, Rust, Types};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Type, Serialize, Deserialize)]
+pub struct User {
+    pub id: u32,
+    pub name: Option<String>,
+    pub role: Role,
+}
+
+#[derive(Type, Serialize, Deserialize)]
+pub enum Role {
+    Admin,
+    Guest(String),
+}
+
+fn types() -> Types {
+    let (_, types) =
+        <Router>::new()
+            .procedure(
+                "version",
+                Procedure::builder().query(|_, _: ()| async { Ok::<_, Infallible>(String::new()) }),
+            )
+            .nest(
+                "users",
+                <Router>::new()
+                    .procedure(
+                        "get",
+                        Procedure::builder().query(|_, _: u32| async {
+                            Ok::<_, Infallible>(User {
+                                id: 0,
+                                name: None,
+                                role: Role::Admin,
+                            })
+                        }),
+                    )
+                    .procedure(
+                        "type",
+                        Procedure::builder()
+                            .mutation(|_, _: User| async { Ok::<_, Infallible>(()) }),
+                    )
+                    .nest(
+                        "admin",
+                        <Router>::new().procedure(
+                            "events",
+                            Procedure::builder().subscription(|_, _: ()| async {
+                                Ok::<_, Infallible>(rspc::Stream(futures_util::stream::iter([
+                                    Ok::<_, Infallible>(true),
+                                ])))
+                            }),
+                        ),
+                    ),
+            )
+            .build()
+            .unwrap();
+    types
+}
+
+// The `rspc_client::Procedure` impl for a procedure.
+fn procedure(name: &str, input: &str, output: &str, root: &str, key: &str, kind: &str) -> String {
+    format!(
+        r#"pub struct {name};
+
+impl rspc_client::Procedure for {name} {{
+    type Input = {input};
+    type Output = {output};
+    type Error = {root}Infallible;
+    type Procedures = {root}Procedures;
+    const KEY: &'static str = {key:?};
+    const KIND: rspc_client::ProcedureKind = rspc_client::ProcedureKind::{kind};
+}}
+"#
+    )
+}
+
+#[test]
+fn procedures() {
+    let out = Rust::default().export(&types());
+
+    assert!(out.contains(&procedure(
+        "version", "()", "String", "", "version", "Query"
+    )));
+    assert!(out.contains("pub mod users {"));
+    // Keys are the full path and not just the name of the procedure.
+    assert!(out.contains(&procedure(
+        "get",
+        "u32",
+        "super::User",
+        "super::",
+        "users.get",
+        "Query"
+    )));
+    assert!(out.contains(&procedure(
+        "r#type",
+        "super::User",
+        "()",
+        "super::",
+        "users.type",
+        "Mutation"
+    )));
+    assert!(out.contains("pub mod admin {"));
+    assert!(out.contains(&procedure(
+        "events",
+        "()",
+        "bool",
+        "super::super::",
+        "users.admin.events",
+        "Subscription"
+    )));
+}
+
+#[test]
+fn named_types() {
+    let out = Rust::default().header("// My header").export(&types());
+
+    assert!(out.starts_with("// My header\n//! This file was generated by [rspc]"));
+    assert!(out.contains(
+        "pub struct User {
+pub id: u32,
+pub name: Option<String>,
+pub role: Role,
+}"
+    ));
+    assert!(out.contains(
+        "pub enum Role {
+Admin,
+Guest(String),
+}"
+    ));
+    // Types are only defined once, even though they're used by multiple procedures.
+    assert_eq!(out.matches("pub struct User ").count(), 1);
+}
+
+#[test]
+fn export_to() {
+    let dir = std::env::temp_dir().join(format!("rspc-rust-{}", std::process::id()));
+    let path = dir.join("nested").join("bindings.rs");
+    Rust::default().export_to(&path, &types()).unwrap();
+
+    // The output is formatted with `rustfmt`
+    let out = std::fs::read_to_string(&path).unwrap();
+    assert!(out.contains("pub struct User {\n    pub id: u32,\n"));
+    assert!(out.contains("pub mod users {\n    pub mod admin {\n        pub struct events;\n"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[derive(Type, Debug)]
+pub enum Infallible {}
+
+impl fmt::Display for Infallible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Serialize for Infallible {
+    fn serialize<S>(&self, _: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match *self {}
+    }
+}
+
+impl std::error::Error for Infallible {}
+
+impl rspc::Error for Infallible {
+    fn into_procedure_error(self) -> ProcedureError {
+        match self {}
+    }
+}